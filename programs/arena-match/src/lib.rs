@@ -1,6 +1,8 @@
 use anchor_lang::prelude::*;
+use anchor_spl::associated_token::AssociatedToken;
+use anchor_spl::token::{self, CloseAccount, Mint, MintTo, Token, TokenAccount, TransferChecked};
 use ephemeral_rollups_sdk::anchor::{commit, delegate, ephemeral};
-use ephemeral_rollups_sdk::cpi::DelegateConfig;
+use ephemeral_rollups_sdk::cpi::{delegate_account, DelegateAccounts, DelegateConfig};
 use ephemeral_rollups_sdk::ephem::commit_and_undelegate_accounts;
 use session_keys::{session_auth_or, Session, SessionError, SessionToken};
 
@@ -9,6 +11,14 @@ declare_id!("45A9Qb4YVeWwL35aBCTcT4bcfsgcFUW3GUHAbvhNJJGi");
 // ── Seeds ───────────────────────────────────────────────────────────────────
 pub const MATCH_SEED: &[u8] = b"arena_match";
 pub const PLAYER_STATE_SEED: &[u8] = b"player_state";
+pub const ESCROW_SEED: &[u8] = b"match_escrow";
+pub const MATCH_TOKEN_VAULT_SEED: &[u8] = b"match_token_vault";
+pub const CONFIG_SEED: &[u8] = b"arena_config";
+pub const REWARD_VAULT_SEED: &[u8] = b"reward_vault";
+pub const PROFILE_SEED: &[u8] = b"player_profile";
+pub const SEASON_SEED: &[u8] = b"season";
+pub const SEASON_STATS_SEED: &[u8] = b"season_stats";
+pub const TOURNAMENT_SEED: &[u8] = b"tournament";
 
 // ── Game Constants (mirror MATCH_CONFIG from TypeScript relay) ──────────────
 pub const MAX_ROUNDS: u8 = 3;
@@ -17,10 +27,41 @@ pub const HP_PER_ROUND: u8 = 3;
 pub const ROUND_TICKS: u32 = 1200; // 60s × 20Hz
 pub const DAMAGE_COOLDOWN_TICKS: u32 = 10; // ~500ms between hits
 pub const MAX_DAMAGE_PER_HIT: u8 = 1;
+pub const MAX_SERVER_EVENTS_PER_TICK: usize = 32;
+pub const MAX_DISPUTE_WINDOW_SECS: u32 = 7 * 24 * 60 * 60; // 1 week
+pub const MAX_PROTOCOL_FEE_BPS: u16 = 1000; // 10%
+pub const SEASON_LEADERBOARD_SIZE: usize = 10;
+pub const TOURNAMENT_MAX_ENTRANTS: usize = 32;
+pub const TOURNAMENT_BRACKET_NODES: usize = 2 * TOURNAMENT_MAX_ENTRANTS - 1; // 31 matches + 32 seeds
+pub const TOURNAMENT_MAX_PAIRINGS: usize = TOURNAMENT_MAX_ENTRANTS / 2; // per Swiss / round-robin round
+pub const TOURNAMENT_MATCH_ID_SPAN: u64 = (TOURNAMENT_MAX_ENTRANTS * TOURNAMENT_MAX_PAIRINGS) as u64;
+pub const TOURNAMENT_NO_ENTRANT: u8 = u8::MAX; // bye in a pairing
+
+// ── Rating Constants (fixed-point Elo) ──────────────────────────────────────
+pub const ELO_INITIAL_RATING: u16 = 1500;
+pub const ELO_MIN_RATING: u16 = 100;
+pub const ELO_K_FACTOR: i32 = 32;
+/// Expected score (bps) of the lower-rated player for rating gaps of
+/// 0, 25, 50 … 800 points: 10000 / (1 + 10^(gap / 400)), rounded.
+pub const ELO_EXPECTED_BPS: [u32; 33] = [
+    5000, 4641, 4285, 3937, 3599, 3275, 2966, 2675, 2403, 2150, 1917, 1704, 1510, 1334, 1177, 1035, 909, 797, 698,
+    610, 532, 464, 405, 352, 307, 267, 232, 201, 175, 152, 132, 114, 99,
+];
+
+// ── Achievements (PlayerProfile.achievements bits) ──────────────────────────
+pub const ACHIEVEMENT_FLAWLESS_ROUND: u32 = 1 << 0; // won an arena round without losing HP
+pub const ACHIEVEMENT_ALL_GOLD: u32 = 1 << 1; // finished a derby with every gold coin
+pub const ACHIEVEMENT_CLEAN_FINISH: u32 = 1 << 2; // finished a derby with zero collisions
+pub const ACHIEVEMENT_WIN_STREAK: u32 = 1 << 3; // ACHIEVEMENT_WIN_STREAK_LEN arena wins in a row
+pub const ACHIEVEMENT_WIN_STREAK_LEN: u32 = 10;
 
 // ── Derby Seeds ──────────────────────────────────────────────────────────
 pub const DERBY_SEED: &[u8] = b"derby_race";
 pub const DERBY_PLAYER_SEED: &[u8] = b"derby_player";
+pub const DERBY_TOKEN_VAULT_SEED: &[u8] = b"derby_token_vault";
+pub const TRACK_LEADERBOARD_SEED: &[u8] = b"track_leaderboard";
+pub const DERBY_EVENT_SEED: &[u8] = b"derby_event";
+pub const TRACK_GHOST_SEED: &[u8] = b"track_ghost";
 
 // ── Derby Constants ──────────────────────────────────────────────────────
 pub const DERBY_MAX_LAPS: u8 = 3;
@@ -28,8 +69,21 @@ pub const DERBY_CHECKPOINT_COUNT: u8 = 4;
 pub const DERBY_MAX_OBSTACLES: u8 = 10;
 pub const DERBY_MAX_BOOSTS: u8 = 8;
 pub const DERBY_MAX_GOLD: u8 = 15;
+pub const DERBY_MIN_OBSTACLES: u8 = 4;
+pub const DERBY_MIN_BOOSTS: u8 = 4;
+pub const DERBY_MIN_GOLD: u8 = 8;
+pub const DERBY_LAYOUT_DOMAIN: &[u8] = b"derby_layout"; // layout = sha256(domain ‖ vrf_seed)
 pub const DERBY_MAX_TICKS: u32 = 6000; // 5min at 20Hz
-pub const DERBY_BOOST_DURATION_TICKS: u32 = 100; // 5s at 20Hz
+pub const DERBY_EXPIRY_SECS: i64 = 86_400; // 24h after created_at an unsettled race can be cancelled by anyone
+pub const DERBY_BOOST_DURATION_TICKS: u32 = 100; // 5s at 20Hz — stacked boosts extend the active one
+pub const DERBY_MIN_CHECKPOINT_GAP_TICKS: u32 = 40; // 2s at 20Hz — fastest plausible split unboosted
+pub const DERBY_MIN_BOOSTED_CHECKPOINT_GAP_TICKS: u32 = 20; // 1s at 20Hz — while current_tick < boost_end_tick
+pub const DERBY_COLLISION_DEBOUNCE_TICKS: u32 = 20; // 1s at 20Hz — repeat hits on one obstacle inside this are duplicates
+pub const DERBY_MAX_RACERS: usize = 8;
+pub const DERBY_SPLIT_COUNT: usize = (DERBY_MAX_LAPS * DERBY_CHECKPOINT_COUNT) as usize; // checkpoint ticks per run
+pub const TRACK_LEADERBOARD_SIZE: usize = 10;
+pub const DERBY_EVENT_LEADERBOARD_SIZE: usize = 10;
+pub const DERBY_EVENT_PRIZE_PLACES: usize = 3;
 
 // ═══════════════════════════════════════════════════════════════════════════
 // PROGRAM
@@ -41,26 +95,56 @@ pub mod arena_match {
     use super::*;
 
     // ── 1. Create match (on L1) ────────────────────────────────────────────
-    pub fn create_match(ctx: Context<CreateMatch>, match_id: u64) -> Result<()> {
+    // A non-zero stake is deposited into the match escrow PDA right away.
+    // A token wager only records mint and amount; both players deposit
+    // through deposit_match_tokens before the first round.
+    pub fn create_match(
+        ctx: Context<CreateMatch>,
+        match_id: u64,
+        params: SettlementParams,
+        stake_lamports: u64,
+        token_wager: Option<TokenWager>,
+        ranked: bool,
+    ) -> Result<()> {
         let m = &mut ctx.accounts.arena_match;
-        m.match_id = match_id;
-        m.game_server = ctx.accounts.game_server.key();
-        m.player1 = ctx.accounts.player1.key();
-        m.player2 = Pubkey::default();
-        m.status = MatchStatus::WaitingForPlayer;
-        m.current_round = 0;
-        m.player1_rounds_won = 0;
-        m.player2_rounds_won = 0;
-        m.player1_hp = HP_PER_ROUND;
-        m.player2_hp = HP_PER_ROUND;
-        m.current_tick = 0;
-        m.round_start_tick = 0;
-        m.last_p1_damage_tick = 0;
-        m.last_p2_damage_tick = 0;
-        m.winner = Pubkey::default();
-        m.created_at = Clock::get()?.unix_timestamp;
-        m.settled_at = 0;
-        msg!("Match {} created by {} (server: {})", match_id, m.player1, m.game_server);
+        let player1 = ctx.accounts.player1.key();
+        m.init(match_id, ctx.accounts.game_server.key(), player1, player1, params)?;
+        m.stake_lamports = stake_lamports;
+        m.ranked = ranked;
+        if let Some(wager) = token_wager {
+            require!(wager.amount > 0, ArenaError::InvalidTokenWager);
+            m.token_mint = wager.mint;
+            m.token_stake = wager.amount;
+        }
+
+        match (&mut ctx.accounts.escrow, stake_lamports) {
+            (None, 0) => {}
+            (Some(escrow), stake) if stake > 0 => {
+                escrow.match_id = match_id;
+                escrow.stake = stake;
+                escrow.player1_deposited = true;
+                escrow.player2_deposited = false;
+                anchor_lang::system_program::transfer(
+                    CpiContext::new(
+                        ctx.accounts.system_program.to_account_info(),
+                        anchor_lang::system_program::Transfer {
+                            from: ctx.accounts.player1.to_account_info(),
+                            to: escrow.to_account_info(),
+                        },
+                    ),
+                    stake,
+                )?;
+            }
+            _ => return Err(ArenaError::StakeMismatch.into()),
+        }
+
+        msg!(
+            "Match {} created by {} (server: {}, stake: {})",
+            match_id,
+            m.player1,
+            m.game_server,
+            stake_lamports
+        );
         Ok(())
     }
 
@@ -69,38 +153,38 @@ pub mod arena_match {
         ctx.accounts.payer.key() == ctx.accounts.player2.key(),
         SessionError::InvalidToken
     )]
+    // Staked matches must be joined on L1 (before delegation) by player 2's
+    // own wallet — a session key or the ER can't move player 2's lamports.
     pub fn join_match(ctx: Context<JoinMatch>, _match_id: u64) -> Result<()> {
-        let m = &mut ctx.accounts.arena_match;
         let p2 = ctx.accounts.player2.key();
-        require!(m.status == MatchStatus::WaitingForPlayer, ArenaError::MatchNotJoinable);
-        require!(m.player1 != p2, ArenaError::CannotJoinOwnMatch);
+        ctx.accounts.arena_match.join(p2)?;
 
-        m.player2 = p2;
-        m.status = MatchStatus::Countdown;
-        m.current_round = 1;
-        m.current_tick = 0;
-        m.round_start_tick = 0;
-        m.player1_hp = HP_PER_ROUND;
-        m.player2_hp = HP_PER_ROUND;
-        msg!("Player {} joined match {}", p2, m.match_id);
+        let stake = ctx.accounts.arena_match.stake_lamports;
+        if stake > 0 {
+            require_keys_eq!(ctx.accounts.payer.key(), p2, ArenaError::StakeRequiresWallet);
+            let (Some(escrow), Some(system_program)) = (&mut ctx.accounts.escrow, &ctx.accounts.system_program)
+            else {
+                return Err(ArenaError::StakeMismatch.into());
+            };
+            escrow.player2_deposited = true;
+            anchor_lang::system_program::transfer(
+                CpiContext::new(
+                    system_program.to_account_info(),
+                    anchor_lang::system_program::Transfer {
+                        from: ctx.accounts.payer.to_account_info(),
+                        to: escrow.to_account_info(),
+                    },
+                ),
+                stake,
+            )?;
+            msg!("Player {} staked {} lamports", p2, stake);
+        }
         Ok(())
     }
 
     // ── 3. Start round (server only, on ER) ────────────────────────────────
     pub fn start_round(ctx: Context<ServerAction>, _match_id: u64) -> Result<()> {
-        let m = &mut ctx.accounts.arena_match;
-        require!(
-            m.status == MatchStatus::Countdown || m.status == MatchStatus::RoundEnd,
-            ArenaError::InvalidMatchState
-        );
-        m.status = MatchStatus::Active;
-        m.round_start_tick = m.current_tick;
-        m.player1_hp = HP_PER_ROUND;
-        m.player2_hp = HP_PER_ROUND;
-        m.last_p1_damage_tick = 0;
-        m.last_p2_damage_tick = 0;
-        msg!("Round {} started at tick {}", m.current_round, m.current_tick);
-        Ok(())
+        ctx.accounts.arena_match.start_round()
     }
 
     // ── 4. Submit input (on ER, fast) ──────────────────────────────────────
@@ -148,92 +232,17 @@ pub mod arena_match {
         _match_id: u64,
         target_slot: u8, // 1 = player1, 2 = player2
     ) -> Result<()> {
-        let m = &mut ctx.accounts.arena_match;
-        require!(m.status == MatchStatus::Active, ArenaError::MatchNotActive);
-
-        match target_slot {
-            1 => {
-                let cooldown_ok = m.current_tick.saturating_sub(m.last_p2_damage_tick) >= DAMAGE_COOLDOWN_TICKS;
-                require!(cooldown_ok, ArenaError::DamageCooldown);
-                m.player1_hp = m.player1_hp.saturating_sub(MAX_DAMAGE_PER_HIT);
-                m.last_p2_damage_tick = m.current_tick;
-                msg!("P1 hit! HP: {}", m.player1_hp);
-            }
-            2 => {
-                let cooldown_ok = m.current_tick.saturating_sub(m.last_p1_damage_tick) >= DAMAGE_COOLDOWN_TICKS;
-                require!(cooldown_ok, ArenaError::DamageCooldown);
-                m.player2_hp = m.player2_hp.saturating_sub(MAX_DAMAGE_PER_HIT);
-                m.last_p1_damage_tick = m.current_tick;
-                msg!("P2 hit! HP: {}", m.player2_hp);
-            }
-            _ => return Err(ArenaError::InvalidTargetSlot.into()),
-        }
-
-        Ok(())
+        ctx.accounts.arena_match.apply_damage(target_slot)
     }
 
     // ── 6. End round (server only, on ER) ──────────────────────────────────
     pub fn end_round(ctx: Context<ServerAction>, _match_id: u64) -> Result<()> {
-        let m = &mut ctx.accounts.arena_match;
-        require!(m.status == MatchStatus::Active, ArenaError::MatchNotActive);
-
-        if m.player1_hp > m.player2_hp {
-            m.player1_rounds_won += 1;
-            msg!("Round {} won by P1", m.current_round);
-        } else if m.player2_hp > m.player1_hp {
-            m.player2_rounds_won += 1;
-            msg!("Round {} won by P2", m.current_round);
-        } else {
-            msg!("Round {} draw", m.current_round);
-        }
-
-        if m.player1_rounds_won >= WINS_NEEDED {
-            m.status = MatchStatus::Complete;
-            m.winner = m.player1;
-            m.settled_at = Clock::get()?.unix_timestamp;
-            msg!("Match complete! Winner: P1 ({})", m.player1);
-        } else if m.player2_rounds_won >= WINS_NEEDED {
-            m.status = MatchStatus::Complete;
-            m.winner = m.player2;
-            m.settled_at = Clock::get()?.unix_timestamp;
-            msg!("Match complete! Winner: P2 ({})", m.player2);
-        } else if m.current_round >= MAX_ROUNDS {
-            m.status = MatchStatus::Complete;
-            m.winner = Pubkey::default();
-            m.settled_at = Clock::get()?.unix_timestamp;
-            msg!("Match complete! Draw.");
-        } else {
-            m.status = MatchStatus::RoundEnd;
-            m.current_round += 1;
-            msg!("Advancing to round {}", m.current_round);
-        }
-
-        Ok(())
+        ctx.accounts.arena_match.end_round()
     }
 
     // ── 7. Forfeit (player disconnected / timed out) ───────────────────────
     pub fn forfeit(ctx: Context<ServerAction>, _match_id: u64, forfeiter_slot: u8) -> Result<()> {
-        let m = &mut ctx.accounts.arena_match;
-        require!(
-            m.status == MatchStatus::Active || m.status == MatchStatus::Countdown || m.status == MatchStatus::RoundEnd,
-            ArenaError::InvalidMatchState
-        );
-
-        match forfeiter_slot {
-            1 => {
-                m.winner = m.player2;
-                msg!("P1 forfeited. Winner: P2 ({})", m.player2);
-            }
-            2 => {
-                m.winner = m.player1;
-                msg!("P2 forfeited. Winner: P1 ({})", m.player1);
-            }
-            _ => return Err(ArenaError::InvalidTargetSlot.into()),
-        }
-
-        m.status = MatchStatus::Complete;
-        m.settled_at = Clock::get()?.unix_timestamp;
-        Ok(())
+        ctx.accounts.arena_match.forfeit(forfeiter_slot)
     }
 
     // ── 8. Delegate match to ER ────────────────────────────────────────────
@@ -271,17 +280,34 @@ pub mod arena_match {
     // ── 10. End match — commit + undelegate back to L1 ─────────────────────
     // No status check — game server is trusted (authenticated via signer constraint).
     // On-chain game actions are fire-and-forget so status may lag behind relay state.
-    pub fn end_match(ctx: Context<EndMatch>, _match_id: u64) -> Result<()> {
+    // Strict matches (ranked / wagered) must be Complete or Cancelled first.
+    // Delegated PlayerState PDAs are passed as remaining accounts and
+    // undelegated with the match, so close_match can close them on L1.
+    pub fn end_match<'info>(ctx: Context<'_, '_, 'info, 'info, EndMatch<'info>>, _match_id: u64) -> Result<()> {
         let m = &mut ctx.accounts.arena_match;
+        m.require_settleable()?;
+        let mut expected: Vec<Pubkey> = [m.player1, m.player2]
+            .iter()
+            .filter(|p| **p != Pubkey::default())
+            .map(|p| m.player_state_pda(p))
+            .collect();
+        for acc in ctx.remaining_accounts {
+            // Each player state at most once
+            let i = expected.iter().position(|k| *k == acc.key()).ok_or(ArenaError::InvalidPlayerState)?;
+            expected.swap_remove(i);
+        }
 
         let match_id = m.match_id;
         let winner = m.winner;
 
         m.exit(&crate::ID)?;
 
+        let info = m.to_account_info();
+        let mut accounts = vec![&info];
+        accounts.extend(ctx.remaining_accounts.iter());
         commit_and_undelegate_accounts(
             &ctx.accounts.payer,
-            vec![&m.to_account_info()],
+            accounts,
             &ctx.accounts.magic_context,
             &ctx.accounts.magic_program,
         )?;
@@ -296,37 +322,839 @@ pub mod arena_match {
         match_id: u64,
     ) -> Result<()> {
         let ps = &mut ctx.accounts.player_state;
-        ps.match_id = match_id;
-        ps.player = ctx.accounts.player.key();
-        ps.dx = 0;
-        ps.dy = 0;
-        ps.attacking = false;
-        ps.last_tick = 0;
-        ps.input_count = 0;
+        let player = ctx.accounts.player.key();
+        ps.init(match_id, player, player);
         msg!("Player state created for match {} player {}", match_id, ps.player);
         Ok(())
     }
 
     // ── 12. Cancel match (before any player joined or on error) ────────────
-    pub fn cancel_match(ctx: Context<CancelMatch>, _match_id: u64) -> Result<()> {
+    // Closing the escrow refunds player 1's stake together with its rent.
+    // Deposited tokens go back to player 1's token account and the vault is
+    // closed.
+    pub fn cancel_match(ctx: Context<CancelMatch>, match_id: u64) -> Result<()> {
         let m = &ctx.accounts.arena_match;
         require!(
             m.status == MatchStatus::WaitingForPlayer,
             ArenaError::MatchAlreadyStarted
         );
+        require!(
+            m.stake_lamports == 0 || ctx.accounts.escrow.is_some(),
+            ArenaError::StakeMismatch
+        );
+
+        if m.player1_tokens_deposited {
+            let a = &ctx.accounts;
+            let (Some(mint), Some(vault), Some(dest), Some(token_program)) =
+                (&a.token_mint, &a.token_vault, &a.player1_token_account, &a.token_program)
+            else {
+                return Err(ArenaError::MissingTokenAccounts.into());
+            };
+            let bump = [ctx.bumps.arena_match];
+            let seeds: &[&[u8]] = &[MATCH_SEED, &match_id.to_le_bytes(), &bump];
+            let authority = m.to_account_info();
+            transfer_tokens_signed(token_program, vault, mint, dest, &authority, seeds, m.token_stake)?;
+            close_vault_signed(token_program, vault, &a.player1.to_account_info(), &authority, seeds)?;
+            msg!("Match {}: {} tokens refunded to player 1", match_id, m.token_stake);
+        }
         msg!("Match {} cancelled", m.match_id);
         Ok(())
     }
 
     // ── 13. Close match PDA — reclaim rent after settlement ──────────────
-    pub fn close_match(_ctx: Context<CloseMatch>, _match_id: u64) -> Result<()> {
-        msg!("Match PDA closed, rent reclaimed");
+    // Also closes the match's PlayerState PDAs, passed as remaining accounts
+    // (player1's, plus player2's once someone joined). Each expected PDA must
+    // be present; one that was never created or was already closed through
+    // close_player_state is skipped, while one owned by anyone else (still
+    // delegated) fails the close. Rent goes back to whoever paid it: the
+    // match's rent payer (minus the agreed close fee) and each player state's
+    // rent payer, which must also be in remaining accounts if not the former.
+    // A Complete result is recorded in both players' profiles, created on
+    // first use at the game server's expense. Ranked matches also update
+    // both Elo ratings.
+    pub fn close_match<'info>(
+        ctx: Context<'_, '_, 'info, 'info, CloseMatch<'info>>,
+        match_id: u64,
+    ) -> Result<()> {
+        let m = &ctx.accounts.arena_match;
+        m.require_closable(Clock::get()?.unix_timestamp)?;
+        require!(m.stake_lamports == 0 || m.paid_out, ArenaError::PayoutPending);
+        require!(m.tokens_settled(), ArenaError::TokenPayoutPending);
+        require!(
+            m.tournament == Pubkey::default() || m.tournament_reported,
+            ArenaError::TournamentReportPending
+        );
+        if m.status == MatchStatus::Complete && !m.season_recorded {
+            let season = ctx.accounts.season.as_deref().map(|s| &**s);
+            require!(
+                !Season::requires_record(season, ctx.accounts.config.current_season, m.settled_at),
+                ArenaError::SeasonReportPending
+            );
+        }
+        let rent_payer = ctx.accounts.rent_payer.to_account_info();
+        let mut closed = 0;
+
+        transfer_lamports(&m.to_account_info(), &ctx.accounts.payer.to_account_info(), m.close_fee)?;
+
+        for player in [m.player1, m.player2] {
+            if player == Pubkey::default() {
+                continue;
+            }
+            let expected = m.player_state_pda(&player);
+            let info = ctx
+                .remaining_accounts
+                .iter()
+                .find(|acc| acc.key() == expected)
+                .ok_or(ArenaError::MissingPlayerState)?;
+            if info.owner != &crate::ID {
+                // Only a PDA that doesn't exist (never created, or closed) is
+                // skipped; a still-delegated one must be undelegated first.
+                require!(
+                    info.owner == &anchor_lang::system_program::ID && info.lamports() == 0,
+                    ArenaError::PlayerStateNotClosable
+                );
+                continue;
+            }
+            let ps: Account<'info, PlayerState> = Account::try_from(info)?;
+            require!(
+                ps.match_id == match_id && ps.player == player,
+                ArenaError::InvalidPlayerState
+            );
+            let refund_to = if ps.rent_payer == rent_payer.key() {
+                rent_payer.clone()
+            } else {
+                ctx.remaining_accounts
+                    .iter()
+                    .find(|acc| acc.key() == ps.rent_payer)
+                    .ok_or(ArenaError::MissingRentPayer)?
+                    .clone()
+            };
+            ps.close(refund_to)?;
+            closed += 1;
+        }
+
+        let unlocked = ctx.accounts.player1_profile.record_match(m, m.player1);
+        emit_achievements(&ctx.accounts.player1_profile, unlocked);
+        if m.player2 != Pubkey::default() {
+            let profile = ctx
+                .accounts
+                .player2_profile
+                .as_mut()
+                .ok_or(ArenaError::MissingPlayerAccounts)?;
+            let unlocked = profile.record_match(m, m.player2);
+            emit_achievements(profile, unlocked);
+
+            if m.ranked && m.status == MatchStatus::Complete {
+                let p1 = &mut ctx.accounts.player1_profile;
+                let delta = PlayerProfile::record_rated_match(p1, profile, m);
+                emit!(RatingsUpdated {
+                    match_id,
+                    player1: m.player1,
+                    player2: m.player2,
+                    player1_rating: p1.arena_rating,
+                    player2_rating: profile.arena_rating,
+                    delta,
+                });
+                msg!("Ratings: P1 {} / P2 {} ({:+})", p1.arena_rating, profile.arena_rating, delta);
+            }
+        }
+
+        msg!(
+            "Match PDA and {} player state PDA(s) closed, rent refunded (close fee: {})",
+            closed,
+            m.close_fee
+        );
         Ok(())
     }
 
     // ── 14. Close player state PDA — reclaim rent after settlement ───────
-    pub fn close_player_state(_ctx: Context<ClosePlayerState>, _match_id: u64) -> Result<()> {
-        msg!("Player state PDA closed, rent reclaimed");
+    pub fn close_player_state(ctx: Context<ClosePlayerState>, _match_id: u64) -> Result<()> {
+        ctx.accounts.arena_match.require_closable(Clock::get()?.unix_timestamp)?;
+        msg!("Player state PDA closed, rent refunded to {}", ctx.accounts.rent_payer.key());
+        Ok(())
+    }
+
+    // ── 22. Server tick — one relay frame applied atomically (on ER) ─────
+    // Events run in order through the same guards as the single-action
+    // instructions; any failing event reverts the whole frame.
+    pub fn server_tick(ctx: Context<ServerAction>, _match_id: u64, events: Vec<ServerEvent>) -> Result<()> {
+        require!(
+            !events.is_empty() && events.len() <= MAX_SERVER_EVENTS_PER_TICK,
+            ArenaError::InvalidEventBatch
+        );
+        let m = &mut ctx.accounts.arena_match;
+        for event in events {
+            match event {
+                ServerEvent::AdvanceTick { tick } => m.advance_tick(tick)?,
+                ServerEvent::StartRound => m.start_round()?,
+                ServerEvent::ApplyDamage { target_slot } => m.apply_damage(target_slot)?,
+                ServerEvent::EndRound => m.end_round()?,
+                ServerEvent::Forfeit { forfeiter_slot } => m.forfeit(forfeiter_slot)?,
+            }
+        }
+        Ok(())
+    }
+
+    // ── 23. Setup match — create + delegate match and player states (on L1)
+    // Replaces create_match + create_player_state ×2 + delegate_match +
+    // delegate_player_state ×2 with one transaction. Player 2 is optional:
+    // when present the match starts already joined (Countdown); when absent
+    // the match is open and player 2 joins on ER with their own player state.
+    // `ranked` and `params.strict` mean the same as in create_match.
+    pub fn setup_match(ctx: Context<SetupMatch>, match_id: u64, params: SettlementParams, ranked: bool) -> Result<()> {
+        let a = &ctx.accounts;
+        let id_bytes = match_id.to_le_bytes();
+        let validator = ctx.remaining_accounts.first().map(|acc| acc.key());
+        let p1 = a.player1.key();
+
+        let mut m = ArenaMatchState::default();
+        m.init(match_id, a.game_server.key(), p1, p1, params)?;
+        m.ranked = ranked;
+
+        let mut states = vec![(
+            ctx.bumps.player1_state,
+            a.player1.to_account_info(),
+            a.player1_state.to_account_info(),
+            a.buffer_player1_state.to_account_info(),
+            a.delegation_record_player1_state.to_account_info(),
+            a.delegation_metadata_player1_state.to_account_info(),
+        )];
+
+        if let Some(player2) = &a.player2 {
+            let p2 = player2.key();
+            m.join(p2)?;
+            let (expected, bump) = Pubkey::find_program_address(
+                &[PLAYER_STATE_SEED, &id_bytes, p2.as_ref()],
+                &crate::ID,
+            );
+            match (
+                &a.player2_state,
+                &a.buffer_player2_state,
+                &a.delegation_record_player2_state,
+                &a.delegation_metadata_player2_state,
+            ) {
+                (Some(ps), Some(buffer), Some(record), Some(metadata)) => {
+                    require_keys_eq!(ps.key(), expected, ArenaError::InvalidPlayerState);
+                    states.push((
+                        bump,
+                        player2.to_account_info(),
+                        ps.to_account_info(),
+                        buffer.to_account_info(),
+                        record.to_account_info(),
+                        metadata.to_account_info(),
+                    ));
+                }
+                _ => return Err(ArenaError::MissingPlayerAccounts.into()),
+            }
+        }
+
+        a.create_program_account(
+            &a.player1.to_account_info(),
+            &a.arena_match.to_account_info(),
+            8 + ArenaMatchState::LEN,
+            &[MATCH_SEED, &id_bytes, &[ctx.bumps.arena_match]],
+        )?;
+        m.try_serialize(&mut &mut a.arena_match.try_borrow_mut_data()?[..])?;
+
+        for (bump, player, pda, _, _, _) in &states {
+            a.create_program_account(
+                player,
+                pda,
+                8 + PlayerState::LEN,
+                &[PLAYER_STATE_SEED, &id_bytes, player.key.as_ref(), &[*bump]],
+            )?;
+            let mut ps = PlayerState::default();
+            ps.init(match_id, player.key(), player.key());
+            ps.try_serialize(&mut &mut pda.try_borrow_mut_data()?[..])?;
+        }
+
+        a.delegate(
+            &a.arena_match.to_account_info(),
+            &a.buffer_arena_match.to_account_info(),
+            &a.delegation_record_arena_match.to_account_info(),
+            &a.delegation_metadata_arena_match.to_account_info(),
+            &[MATCH_SEED, &id_bytes],
+            validator,
+        )?;
+        for (_, player, pda, buffer, record, metadata) in &states {
+            a.delegate(
+                pda,
+                buffer,
+                record,
+                metadata,
+                &[PLAYER_STATE_SEED, &id_bytes, player.key.as_ref()],
+                validator,
+            )?;
+        }
+
+        msg!(
+            "Match {} set up by {} (server: {}) with {} player state(s), delegated to ER",
+            match_id,
+            p1,
+            m.game_server,
+            states.len()
+        );
+        Ok(())
+    }
+
+    // ── 24. Raise dispute (participant, on L1 during the challenge window) ──
+    pub fn raise_dispute(ctx: Context<RaiseDispute>, _match_id: u64) -> Result<()> {
+        let m = &mut ctx.accounts.arena_match;
+        m.raise_dispute(Clock::get()?.unix_timestamp)?;
+        msg!("Match {}: dispute raised by {}", m.match_id, ctx.accounts.challenger.key());
+        Ok(())
+    }
+
+    // ── 25. Resolve dispute (arbiter, on L1) ───────────────────────────────
+    pub fn resolve_dispute(
+        ctx: Context<ResolveDispute>,
+        _match_id: u64,
+        resolution: DisputeResolution,
+    ) -> Result<()> {
+        ctx.accounts.arena_match.resolve_dispute(resolution)
+    }
+
+    // ── 26. Payout — release the staked pot (on L1, once) ──────────────────
+    // Winner takes the pot minus the protocol fee; a draw (winner == default)
+    // or Cancelled match refunds each deposit in full. Waits for the dispute
+    // window like close_match. The escrow is closed here, returning its rent
+    // to player 1.
+    pub fn payout(ctx: Context<Payout>, _match_id: u64) -> Result<()> {
+        let m = &mut ctx.accounts.arena_match;
+        require!(m.stake_lamports > 0, ArenaError::NoStake);
+        require!(!m.paid_out, ArenaError::AlreadyPaidOut);
+        require!(
+            m.status == MatchStatus::Complete || m.status == MatchStatus::Cancelled,
+            ArenaError::MatchNotComplete
+        );
+        m.require_result_final(Clock::get()?.unix_timestamp)?;
+
+        let escrow = &ctx.accounts.escrow;
+        let p1_deposit = if escrow.player1_deposited { escrow.stake } else { 0 };
+        let p2_deposit = if escrow.player2_deposited { escrow.stake } else { 0 };
+        let pot = p1_deposit + p2_deposit;
+        let decided = m.status == MatchStatus::Complete && m.winner != Pubkey::default();
+        let fee = if decided { ctx.accounts.config.fee_for(pot) } else { 0 };
+        let (p1_amount, p2_amount) = if decided && m.winner == m.player1 {
+            (pot - fee, 0)
+        } else if decided && m.winner == m.player2 {
+            (0, pot - fee)
+        } else {
+            (p1_deposit, p2_deposit)
+        };
+
+        let escrow_info = escrow.to_account_info();
+        transfer_lamports(&escrow_info, &ctx.accounts.treasury.to_account_info(), fee)?;
+        transfer_lamports(&escrow_info, &ctx.accounts.player1.to_account_info(), p1_amount)?;
+        if p2_amount > 0 {
+            let player2 = ctx.accounts.player2.as_ref().ok_or(ArenaError::MissingPlayerAccounts)?;
+            transfer_lamports(&escrow_info, &player2.to_account_info(), p2_amount)?;
+        }
+        m.paid_out = true;
+
+        emit!(MatchPaidOut {
+            match_id: m.match_id,
+            winner: m.winner,
+            pot,
+            fee,
+            player1_amount: p1_amount,
+            player2_amount: p2_amount,
+        });
+        msg!("Match {} paid out: P1 {} / P2 {} lamports (fee: {})", m.match_id, p1_amount, p2_amount, fee);
+        Ok(())
+    }
+
+    // ── 27. Deposit match tokens (player, on L1 before delegation) ─────────
+    // Player 1 deposits first and pays the vault's rent; player 2 deposits
+    // after joining. The first round can't start until both are in.
+    pub fn deposit_match_tokens(ctx: Context<DepositMatchTokens>, _match_id: u64) -> Result<()> {
+        let m = &mut ctx.accounts.arena_match;
+        require!(m.token_stake > 0, ArenaError::NoTokenStake);
+        require!(
+            m.status == MatchStatus::WaitingForPlayer || m.status == MatchStatus::Countdown,
+            ArenaError::MatchAlreadyStarted
+        );
+
+        let depositor = ctx.accounts.depositor.key();
+        if depositor == m.player1 {
+            require!(!m.player1_tokens_deposited, ArenaError::TokensAlreadyDeposited);
+            m.player1_tokens_deposited = true;
+        } else if depositor == m.player2 && m.player2 != Pubkey::default() {
+            require!(m.player1_tokens_deposited, ArenaError::TokenDepositOrder);
+            require!(!m.player2_tokens_deposited, ArenaError::TokensAlreadyDeposited);
+            m.player2_tokens_deposited = true;
+        } else {
+            return Err(ArenaError::UnauthorizedPlayer.into());
+        }
+
+        token::transfer_checked(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.depositor_token_account.to_account_info(),
+                    mint: ctx.accounts.token_mint.to_account_info(),
+                    to: ctx.accounts.token_vault.to_account_info(),
+                    authority: ctx.accounts.depositor.to_account_info(),
+                },
+            ),
+            m.token_stake,
+            ctx.accounts.token_mint.decimals,
+        )?;
+        msg!("Match {}: {} deposited {} tokens", m.match_id, depositor, m.token_stake);
+        Ok(())
+    }
+
+    // ── 28. Payout match tokens — release the token pot (on L1, once) ──────
+    // Same split and fee as payout, paid to the players' associated token
+    // accounts and the treasury's. A match settled mid-game is aborted
+    // first, which refunds both deposits.
+    // The vault is closed and its rent returned to player 1.
+    pub fn payout_match_tokens(ctx: Context<PayoutMatchTokens>, match_id: u64) -> Result<()> {
+        let m = &ctx.accounts.arena_match;
+        require!(m.token_stake > 0, ArenaError::NoTokenStake);
+        require!(!m.tokens_paid_out, ArenaError::TokensAlreadyPaidOut);
+        require!(
+            m.status == MatchStatus::Complete || m.status == MatchStatus::Cancelled,
+            ArenaError::MatchNotComplete
+        );
+        m.require_result_final(Clock::get()?.unix_timestamp)?;
+
+        let p1_deposit = if m.player1_tokens_deposited { m.token_stake } else { 0 };
+        let p2_deposit = if m.player2_tokens_deposited { m.token_stake } else { 0 };
+        let pot = p1_deposit + p2_deposit;
+        let decided = m.status == MatchStatus::Complete && m.winner != Pubkey::default();
+        let fee = if decided { ctx.accounts.config.fee_for(pot) } else { 0 };
+        let (p1_amount, p2_amount) = if decided && m.winner == m.player1 {
+            (pot - fee, 0)
+        } else if decided && m.winner == m.player2 {
+            (0, pot - fee)
+        } else {
+            (p1_deposit, p2_deposit)
+        };
+
+        let a = &ctx.accounts;
+        let bump = [ctx.bumps.arena_match];
+        let seeds: &[&[u8]] = &[MATCH_SEED, &match_id.to_le_bytes(), &bump];
+        let authority = m.to_account_info();
+        transfer_tokens_signed(&a.token_program, &a.token_vault, &a.token_mint, &a.treasury_token_account, &authority, seeds, fee)?;
+        transfer_tokens_signed(&a.token_program, &a.token_vault, &a.token_mint, &a.player1_token_account, &authority, seeds, p1_amount)?;
+        if p2_amount > 0 {
+            let dest = a.player2_token_account.as_ref().ok_or(ArenaError::MissingTokenAccounts)?;
+            transfer_tokens_signed(&a.token_program, &a.token_vault, &a.token_mint, dest, &authority, seeds, p2_amount)?;
+        }
+        close_vault_signed(&a.token_program, &a.token_vault, &a.player1.to_account_info(), &authority, seeds)?;
+
+        let m = &mut ctx.accounts.arena_match;
+        m.tokens_paid_out = true;
+        emit!(MatchTokensPaidOut {
+            match_id: m.match_id,
+            mint: m.token_mint,
+            winner: m.winner,
+            pot,
+            fee,
+            player1_amount: p1_amount,
+            player2_amount: p2_amount,
+        });
+        msg!("Match {} tokens paid out: P1 {} / P2 {} (fee: {})", m.match_id, p1_amount, p2_amount, fee);
+        Ok(())
+    }
+
+    // ── 29. Initialize config (program upgrade authority, on L1, once) ─────
+    pub fn initialize_config(ctx: Context<InitializeConfig>, treasury: Pubkey, fee_bps: u16) -> Result<()> {
+        require!(fee_bps <= MAX_PROTOCOL_FEE_BPS, ArenaError::FeeTooHigh);
+        let c = &mut ctx.accounts.config;
+        c.admin = ctx.accounts.admin.key();
+        c.treasury = treasury;
+        c.fee_bps = fee_bps;
+        c.reward_mint = Pubkey::default();
+        c.reward_per_gold = 0;
+        c.current_season = 0;
+        msg!("Config initialized (admin: {}, treasury: {}, fee: {} bps)", c.admin, treasury, fee_bps);
+        Ok(())
+    }
+
+    // ── 30. Update config (admin, on L1) ───────────────────────────────────
+    // Takes effect on the next payout, including matches already running.
+    pub fn update_config(
+        ctx: Context<UpdateConfig>,
+        admin: Pubkey,
+        treasury: Pubkey,
+        fee_bps: u16,
+    ) -> Result<()> {
+        require!(fee_bps <= MAX_PROTOCOL_FEE_BPS, ArenaError::FeeTooHigh);
+        let c = &mut ctx.accounts.config;
+        c.admin = admin;
+        c.treasury = treasury;
+        c.fee_bps = fee_bps;
+        msg!("Config updated (admin: {}, treasury: {}, fee: {} bps)", admin, treasury, fee_bps);
+        Ok(())
+    }
+
+    // ── 31. Set derby rewards (admin, on L1) ───────────────────────────────
+    // Rewards are minted when the config PDA is the mint authority, otherwise
+    // paid from the reward vault, which the admin tops up with plain token
+    // transfers. reward_per_gold = 0 turns rewards off.
+    pub fn set_derby_rewards(ctx: Context<SetDerbyRewards>, reward_per_gold: u64) -> Result<()> {
+        let c = &mut ctx.accounts.config;
+        c.reward_mint = ctx.accounts.reward_mint.key();
+        c.reward_per_gold = reward_per_gold;
+        msg!("Derby rewards set: {} per gold of mint {}", reward_per_gold, c.reward_mint);
+        Ok(())
+    }
+
+    // ── 32. Start season (admin, on L1) ────────────────────────────────────
+    // Seasons are numbered 1, 2, 3 … Starting the next one freezes the
+    // current season's leaderboards for good.
+    pub fn start_season(ctx: Context<StartSeason>, season_id: u32, start_ts: i64, end_ts: i64) -> Result<()> {
+        let c = &mut ctx.accounts.config;
+        require!(season_id == c.current_season + 1, ArenaError::InvalidSeason);
+        require!(end_ts > start_ts, ArenaError::InvalidSeason);
+
+        if c.current_season > 0 {
+            let previous = ctx.accounts.previous_season.as_mut().ok_or(ArenaError::InvalidSeason)?;
+            previous.frozen = true;
+            msg!("Season {} frozen", previous.season_id);
+        }
+
+        let season = &mut ctx.accounts.season;
+        season.season_id = season_id;
+        season.start_ts = start_ts;
+        season.end_ts = end_ts;
+        season.frozen = false;
+        c.current_season = season_id;
+        msg!("Season {} started ({} – {})", season_id, start_ts, end_ts);
+        Ok(())
+    }
+
+    // ── 33. Record match in season (on L1, once, before close_match) ───────
+    // Adds a final Complete result to both players' season stats and the
+    // season's wins leaderboard. The season is the one running at
+    // settled_at, so a result still counts after the dispute window.
+    pub fn record_match_season(ctx: Context<RecordMatchSeason>, _match_id: u64) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let m = &mut ctx.accounts.arena_match;
+        require!(m.status == MatchStatus::Complete, ArenaError::MatchNotComplete);
+        m.require_result_final(now)?;
+        require!(!m.season_recorded, ArenaError::SeasonAlreadyRecorded);
+        let season = &mut ctx.accounts.season;
+        require!(season.is_active(m.settled_at), ArenaError::SeasonNotActive);
+
+        let p1 = &mut ctx.accounts.player1_stats;
+        let p2 = &mut ctx.accounts.player2_stats;
+        p1.init_for(season.season_id, m.player1);
+        p2.init_for(season.season_id, m.player2);
+        if m.winner == Pubkey::default() {
+            p1.arena_draws = p1.arena_draws.saturating_add(1);
+            p2.arena_draws = p2.arena_draws.saturating_add(1);
+        } else {
+            let (winner, loser) = if m.winner == m.player1 { (p1, p2) } else { (p2, p1) };
+            winner.arena_wins = winner.arena_wins.saturating_add(1);
+            loser.arena_losses = loser.arena_losses.saturating_add(1);
+            season.record_arena_wins(winner.wallet, winner.arena_wins);
+        }
+        m.season_recorded = true;
+        msg!("Match {} recorded in season {}", m.match_id, season.season_id);
+        Ok(())
+    }
+
+    // ── 34. Create tournament (game server, on L1) ─────────────────────────
+    // Single-elimination, Swiss (`rounds` rounds) or round-robin for up to
+    // TOURNAMENT_MAX_ENTRANTS players. Entry fees are held in the tournament
+    // PDA; `prize_split_bps` is the champion's and the runner-up's share of
+    // the pot.
+    #[allow(clippy::too_many_arguments)]
+    pub fn create_tournament(
+        ctx: Context<CreateTournament>,
+        tournament_id: u64,
+        base_match_id: u64,
+        max_entrants: u8,
+        entry_fee: u64,
+        prize_split_bps: [u16; 2],
+        params: SettlementParams,
+        format: TournamentFormat,
+        rounds: u8,
+    ) -> Result<()> {
+        require!(
+            max_entrants >= 2 && max_entrants as usize <= TOURNAMENT_MAX_ENTRANTS,
+            ArenaError::InvalidTournamentParams
+        );
+        require!(
+            (format == TournamentFormat::Swiss) == (rounds > 0) && rounds < max_entrants,
+            ArenaError::InvalidTournamentParams
+        );
+        require!(
+            prize_split_bps[0] as u32 + prize_split_bps[1] as u32 == 10_000,
+            ArenaError::InvalidTournamentParams
+        );
+        require!(
+            base_match_id.checked_add(TOURNAMENT_MATCH_ID_SPAN).is_some(),
+            ArenaError::InvalidTournamentParams
+        );
+        require!(params.dispute_params_valid(), ArenaError::InvalidDisputeParams);
+
+        let t = &mut ctx.accounts.tournament;
+        t.tournament_id = tournament_id;
+        t.game_server = ctx.accounts.game_server.key();
+        t.base_match_id = base_match_id;
+        t.status = TournamentStatus::Registration;
+        t.max_entrants = max_entrants;
+        t.entrant_count = 0;
+        t.bracket_size = 0;
+        t.entry_fee = entry_fee;
+        t.prize_split_bps = prize_split_bps;
+        t.params = params;
+        t.entrants = [Pubkey::default(); TOURNAMENT_MAX_ENTRANTS];
+        t.bracket = [Pubkey::default(); TOURNAMENT_BRACKET_NODES];
+        t.champion = Pubkey::default();
+        t.runner_up = Pubkey::default();
+        t.paid_out = false;
+        t.format = format;
+        t.total_rounds = rounds;
+        t.round = 0;
+        t.pairing_count = 0;
+        t.reported_mask = 0;
+        t.byes = 0;
+        t.pairings = [[TOURNAMENT_NO_ENTRANT; 2]; TOURNAMENT_MAX_PAIRINGS];
+        t.points = [0; TOURNAMENT_MAX_ENTRANTS];
+        t.played = [0; TOURNAMENT_MAX_ENTRANTS];
+        t.buchholz = [0; TOURNAMENT_MAX_ENTRANTS];
+        msg!(
+            "Tournament {} created ({:?}, max {} entrants, fee {})",
+            tournament_id,
+            format,
+            max_entrants,
+            entry_fee
+        );
+        Ok(())
+    }
+
+    // ── 35. Join tournament (entrant, on L1 during registration) ───────────
+    pub fn join_tournament(ctx: Context<JoinTournament>, _tournament_id: u64) -> Result<()> {
+        let entrant = ctx.accounts.entrant.key();
+        let t = &mut ctx.accounts.tournament;
+        require!(t.status == TournamentStatus::Registration, ArenaError::TournamentNotOpen);
+        require!(t.entrant_count < t.max_entrants, ArenaError::TournamentFull);
+        require!(
+            !t.entrants[..t.entrant_count as usize].contains(&entrant),
+            ArenaError::AlreadyEntered
+        );
+        t.entrants[t.entrant_count as usize] = entrant;
+        t.entrant_count += 1;
+
+        if t.entry_fee > 0 {
+            anchor_lang::system_program::transfer(
+                CpiContext::new(
+                    ctx.accounts.system_program.to_account_info(),
+                    anchor_lang::system_program::Transfer {
+                        from: ctx.accounts.entrant.to_account_info(),
+                        to: t.to_account_info(),
+                    },
+                ),
+                t.entry_fee,
+            )?;
+        }
+        msg!("{} entered tournament {} ({}/{})", entrant, t.tournament_id, t.entrant_count, t.max_entrants);
+        Ok(())
+    }
+
+    // ── 36. Start tournament (game server) ─────────────────────────────────
+    // Closes registration. Elimination seeds the bracket in join order,
+    // with missing entrants as first-round byes; Swiss and round-robin pair
+    // round 1.
+    pub fn start_tournament(ctx: Context<TournamentServerAction>, _tournament_id: u64) -> Result<()> {
+        let t = &mut ctx.accounts.tournament;
+        require!(t.status == TournamentStatus::Registration, ArenaError::TournamentNotOpen);
+        require!(t.entrant_count >= 2, ArenaError::NotEnoughEntrants);
+        match t.format {
+            TournamentFormat::SingleElimination => t.seed_bracket(),
+            TournamentFormat::Swiss => {
+                require!(t.total_rounds < t.entrant_count, ArenaError::NotEnoughEntrants);
+                t.pair_round();
+            }
+            TournamentFormat::RoundRobin => {
+                t.total_rounds = t.entrant_count + t.entrant_count % 2 - 1;
+                t.pair_round();
+            }
+        }
+        t.status = TournamentStatus::Running;
+        msg!("Tournament {} started with {} entrants", t.tournament_id, t.entrant_count);
+        Ok(())
+    }
+
+    // ── 37. Create tournament match (game server, on L1) ───────────────────
+    // Spawns the ArenaMatchState for bracket node `node` once both of its
+    // players are known. The match starts joined (Countdown) and is played
+    // and delegated like any other.
+    pub fn create_tournament_match(ctx: Context<CreateTournamentMatch>, _tournament_id: u64, node: u8) -> Result<()> {
+        let t = &ctx.accounts.tournament;
+        require!(t.status == TournamentStatus::Running, ArenaError::TournamentNotRunning);
+        let (player1, player2) = t.pairing(node as usize)?;
+
+        let m = &mut ctx.accounts.arena_match;
+        m.init(t.match_id_for(node), t.game_server, player1, ctx.accounts.game_server.key(), t.params)?;
+        m.join(player2)?;
+        m.tournament = t.key();
+        msg!("Tournament {} node {}: match {} spawned", t.tournament_id, node, m.match_id);
+        Ok(())
+    }
+
+    // ── 38. Report tournament match (anyone, on L1) ────────────────────────
+    // Feeds a final, settled match back to the tournament. Elimination
+    // advances the winner and the final crowns the champion; a draw counts
+    // for nothing there. Swiss and round-robin score win 2 / draw 1 and pair
+    // the next round once every result is in; after the last round the
+    // standings (points, then Buchholz) decide. A cancelled match never
+    // counts — once closed, the node can be spawned again as a replay. Once
+    // the tournament is cancelled, reporting only releases the match so it
+    // can be closed.
+    pub fn report_tournament_match(ctx: Context<ReportTournamentMatch>, _tournament_id: u64, node: u8) -> Result<()> {
+        let m = &mut ctx.accounts.arena_match;
+        require!(!m.tournament_reported, ArenaError::TournamentMatchReported);
+        require!(
+            m.status == MatchStatus::Complete || m.status == MatchStatus::Cancelled,
+            ArenaError::MatchNotComplete
+        );
+        m.require_result_final(Clock::get()?.unix_timestamp)?;
+        m.tournament_reported = true;
+
+        let t = &mut ctx.accounts.tournament;
+        require!(
+            t.status == TournamentStatus::Running || t.status == TournamentStatus::Cancelled,
+            ArenaError::TournamentNotRunning
+        );
+        if t.status == TournamentStatus::Cancelled {
+            msg!("Tournament {} cancelled: node {} released", t.tournament_id, node);
+            return Ok(());
+        }
+        let counts = match t.format {
+            TournamentFormat::SingleElimination => {
+                m.status == MatchStatus::Complete && m.winner != Pubkey::default()
+            }
+            TournamentFormat::Swiss | TournamentFormat::RoundRobin => m.status == MatchStatus::Complete,
+        };
+        if !counts {
+            msg!("Tournament {} node {}: no result, replay after close", t.tournament_id, node);
+            return Ok(());
+        }
+        emit!(TournamentMatchReported {
+            tournament_id: t.tournament_id,
+            node,
+            match_id: m.match_id,
+            winner: m.winner,
+        });
+
+        if t.format == TournamentFormat::SingleElimination {
+            let loser = if m.winner == m.player1 { m.player2 } else { m.player1 };
+            t.bracket[node as usize] = m.winner;
+            msg!("Tournament {} node {}: {} advances", t.tournament_id, node, m.winner);
+            if node == 0 {
+                t.finish(m.winner, loser);
+            }
+        } else {
+            t.record_result(node as usize, m.winner);
+            msg!("Tournament {} round {} node {}: result recorded", t.tournament_id, t.round, node);
+            if t.round_complete() {
+                if t.round == t.total_rounds {
+                    let standings = t.final_standings();
+                    let (champion, runner_up) = (t.entrants[standings[0]], t.entrants[standings[1]]);
+                    t.finish(champion, runner_up);
+                } else {
+                    t.pair_round();
+                    msg!("Tournament {}: round {} paired", t.tournament_id, t.round);
+                }
+            }
+        }
+
+        if t.status == TournamentStatus::Complete {
+            emit!(TournamentCompleted {
+                tournament_id: t.tournament_id,
+                champion: t.champion,
+                runner_up: t.runner_up,
+            });
+            msg!("Tournament {} champion: {}", t.tournament_id, t.champion);
+        }
+        Ok(())
+    }
+
+    // ── 39. Payout tournament (anyone, on L1) ──────────────────────────────
+    // Splits the entry-fee pot between champion and runner-up after the
+    // protocol fee.
+    pub fn payout_tournament(ctx: Context<PayoutTournament>, _tournament_id: u64) -> Result<()> {
+        let t = &mut ctx.accounts.tournament;
+        require!(t.status == TournamentStatus::Complete, ArenaError::TournamentNotComplete);
+        require!(!t.paid_out, ArenaError::TournamentAlreadyPaidOut);
+
+        let pot = t.entry_fee * t.entrant_count as u64;
+        let fee = ctx.accounts.config.fee_for(pot);
+        let champion_amount = (pot - fee) * t.prize_split_bps[0] as u64 / 10_000;
+        let runner_up_amount = pot - fee - champion_amount;
+
+        let info = t.to_account_info();
+        transfer_lamports(&info, &ctx.accounts.treasury.to_account_info(), fee)?;
+        transfer_lamports(&info, &ctx.accounts.champion.to_account_info(), champion_amount)?;
+        transfer_lamports(&info, &ctx.accounts.runner_up.to_account_info(), runner_up_amount)?;
+        t.paid_out = true;
+
+        emit!(TournamentPaidOut {
+            tournament_id: t.tournament_id,
+            pot,
+            fee,
+            champion_amount,
+            runner_up_amount,
+        });
+        msg!(
+            "Tournament {} paid out: champion {} / runner-up {} lamports (fee: {})",
+            t.tournament_id,
+            champion_amount,
+            runner_up_amount,
+            fee
+        );
+        Ok(())
+    }
+
+    // ── 40. Cancel tournament (game server) ────────────────────────────────
+    // Refunds every entry fee. Entrants are passed as remaining accounts.
+    // Matches already spawned still settle as usual (abort_match any that
+    // can't finish) and are then reported to release them for close_match.
+    pub fn cancel_tournament<'info>(
+        ctx: Context<'_, '_, 'info, 'info, TournamentServerAction<'info>>,
+        _tournament_id: u64,
+    ) -> Result<()> {
+        let t = &mut ctx.accounts.tournament;
+        require!(
+            t.status == TournamentStatus::Registration || t.status == TournamentStatus::Running,
+            ArenaError::InvalidTournamentState
+        );
+        let info = t.to_account_info();
+        for entrant in &t.entrants[..t.entrant_count as usize] {
+            let to = ctx
+                .remaining_accounts
+                .iter()
+                .find(|acc| acc.key() == *entrant)
+                .ok_or(ArenaError::MissingEntrant)?;
+            transfer_lamports(&info, to, t.entry_fee)?;
+        }
+        t.status = TournamentStatus::Cancelled;
+        msg!("Tournament {} cancelled, {} entry fee(s) refunded", t.tournament_id, t.entrant_count);
+        Ok(())
+    }
+
+    // ── 41. Abort match (game server, on ER or L1) ─────────────────────────
+    // Cancels a match that can't be played out (server crash, both players
+    // gone) from any non-final status. payout and payout_match_tokens then
+    // refund each deposit, and strict matches can settle.
+    pub fn abort_match(ctx: Context<ServerAction>, _match_id: u64) -> Result<()> {
+        ctx.accounts.arena_match.abort()
+    }
+
+    // ── 42. Close tournament (game server, on L1) ──────────────────────────
+    // Refunds the tournament PDA's rent to the game server that created it,
+    // once the champion is crowned and the prizes are paid.
+    pub fn close_tournament(ctx: Context<CloseTournament>, _tournament_id: u64) -> Result<()> {
+        let t = &ctx.accounts.tournament;
+        require!(t.status == TournamentStatus::Complete, ArenaError::TournamentNotComplete);
+        require!(t.paid_out, ArenaError::TournamentPayoutPending);
+        msg!("Tournament {} closed, rent refunded to {}", t.tournament_id, ctx.accounts.game_server.key());
         Ok(())
     }
 
@@ -338,13 +1166,29 @@ pub mod arena_match {
     pub fn create_derby(
         ctx: Context<CreateDerby>,
         race_id: u64,
-        vrf_seed: [u8; 32],
+        seed: SeedRequest,
+        params: SettlementParams,
+        token_wager: Option<TokenWager>,
+        max_racers: u8,
     ) -> Result<()> {
+        require!(
+            params.close_fee <= Rent::get()?.minimum_balance(8 + DerbyRaceState::LEN),
+            DerbyError::CloseFeeTooHigh
+        );
+        require!(params.dispute_params_valid(), DerbyError::InvalidDisputeParams);
+        require!(
+            (1..=DERBY_MAX_RACERS).contains(&(max_racers as usize)),
+            DerbyError::InvalidRacerCount
+        );
+        require!(
+            max_racers == 1
+                || (token_wager.is_none() && ctx.accounts.derby_event.is_none() && ctx.accounts.track_ghost.is_none()),
+            DerbyError::MultiplayerUnsupported
+        );
         let d = &mut ctx.accounts.derby_race;
         d.race_id = race_id;
         d.game_server = ctx.accounts.game_server.key();
         d.player = ctx.accounts.player.key();
-        d.vrf_seed = vrf_seed;
         d.status = DerbyStatus::Created;
         d.current_tick = 0;
         d.current_lap = 0;
@@ -358,7 +1202,103 @@ pub mod arena_match {
         d.boost_bitmask = 0;
         d.created_at = Clock::get()?.unix_timestamp;
         d.settled_at = 0;
-        msg!("Derby {} created by {} (server: {})", race_id, d.player, d.game_server);
+        d.rent_payer = d.player;
+        d.close_fee = params.close_fee;
+        d.strict = params.strict;
+        d.arbiter = params.arbiter;
+        d.dispute_window_secs = params.dispute_window_secs;
+        d.dispute_status = DisputeStatus::None;
+        d.token_mint = Pubkey::default();
+        d.token_stake = 0;
+        d.player_tokens_deposited = false;
+        d.server_tokens_deposited = false;
+        d.tokens_paid_out = false;
+        d.rewarded = false;
+        d.reward_amount = 0;
+        d.season_recorded = false;
+        d.event = Pubkey::default();
+        d.event_recorded = false;
+        d.max_racers = max_racers;
+        d.racers = [Pubkey::default(); DERBY_MAX_RACERS];
+        d.finished_count = 0;
+        d.obstacle_hits = [0; DERBY_MAX_LAPS as usize];
+        d.obstacle_hit_ticks = [0; DERBY_MAX_OBSTACLES as usize];
+        d.boosted_ticks = 0;
+        d.boost_accrued_tick = 0;
+        d.last_split_tick = 0;
+        d.lap_ticks = [0; DERBY_MAX_LAPS as usize];
+        d.checkpoint_ticks = [0; DERBY_SPLIT_COUNT];
+        d.ghost_race_id = 0;
+        d.ghost_finish_tick = 0;
+        d.ghost_margin = 0;
+        d.seed_commitment = [0; 32];
+        d.player_seed = [0; 32];
+        d.server_secret = [0; 32];
+        d.seed_contributed = false;
+        d.layout = TrackLayout::default();
+        d.track_origin = TrackOrigin::Own;
+        match seed {
+            SeedRequest::Fixed { vrf_seed } => {
+                d.vrf_seed = vrf_seed;
+                d.seed_source = SeedSource::Server;
+                d.seed_fulfilled = true;
+            }
+            SeedRequest::CommitReveal { commitment } => {
+                d.vrf_seed = [0; 32];
+                d.seed_source = SeedSource::CommitReveal;
+                d.seed_commitment = commitment;
+                d.seed_fulfilled = false;
+            }
+        }
+        if let Some(ghost) = ctx.accounts.track_ghost.as_ref() {
+            require!(d.seed_fulfilled && ghost.vrf_seed == d.vrf_seed, DerbyError::GhostSeedMismatch);
+            require!(ghost.player != Pubkey::default(), DerbyError::NoGhostRecord);
+            d.track_origin = TrackOrigin::Ghost;
+            d.ghost_race_id = ghost.race_id;
+            d.ghost_finish_tick = ghost.finish_tick;
+            msg!("Derby {} races the ghost of derby {} (tick {})", race_id, ghost.race_id, ghost.finish_tick);
+        }
+        if max_racers == 1 {
+            d.racers[0] = d.player;
+            d.racer_count = 1;
+        } else {
+            d.racer_count = 0;
+        }
+        if let Some(wager) = token_wager {
+            require!(wager.amount > 0, DerbyError::InvalidTokenWager);
+            require!(d.seed_source == SeedSource::CommitReveal, DerbyError::VerifiableSeedRequired);
+            d.token_mint = wager.mint;
+            d.token_stake = wager.amount;
+        }
+        if let Some(event) = ctx.accounts.derby_event.as_mut() {
+            require!(event.game_server == d.game_server, DerbyError::UnauthorizedServer);
+            require!(event.is_open(d.created_at), DerbyError::EventNotOpen);
+            require!(d.seed_fulfilled && event.vrf_seed == d.vrf_seed, DerbyError::EventSeedMismatch);
+            d.track_origin = TrackOrigin::Event;
+            if event.entry_fee > 0 {
+                anchor_lang::system_program::transfer(
+                    CpiContext::new(
+                        ctx.accounts.system_program.to_account_info(),
+                        anchor_lang::system_program::Transfer {
+                            from: ctx.accounts.player.to_account_info(),
+                            to: event.to_account_info(),
+                        },
+                    ),
+                    event.entry_fee,
+                )?;
+            }
+            event.entrant_count += 1;
+            event.dispute_window_secs = event.dispute_window_secs.max(d.dispute_window_secs);
+            d.event = event.key();
+            msg!("Derby {} entered event {} (entry fee: {})", race_id, event.event_id, event.entry_fee);
+        }
+        msg!(
+            "Derby {} created by {} (server: {}, racers: {})",
+            race_id,
+            d.player,
+            d.game_server,
+            max_racers
+        );
         Ok(())
     }
 
@@ -380,11 +1320,24 @@ pub mod arena_match {
     pub fn start_derby(ctx: Context<DerbyServerAction>, _race_id: u64) -> Result<()> {
         let d = &mut ctx.accounts.derby_race;
         require!(d.status == DerbyStatus::Created, DerbyError::InvalidDerbyState);
+        require!(
+            d.token_stake == 0 || (d.player_tokens_deposited && d.server_tokens_deposited),
+            DerbyError::TokenStakePending
+        );
+        require!(!d.is_multiplayer() || d.racer_count >= 2, DerbyError::NotEnoughRacers);
+        require!(d.seed_fulfilled, DerbyError::SeedNotFulfilled);
         d.status = DerbyStatus::Racing;
         d.current_tick = 0;
         d.current_lap = 0;
         d.checkpoints_passed = 0;
-        msg!("Derby {} started", d.race_id);
+        d.layout = TrackLayout::derive(&d.vrf_seed);
+        msg!(
+            "Derby {} started ({} obstacles, {} gold, {} boosts)",
+            d.race_id,
+            d.layout.obstacle_count,
+            d.layout.gold_count,
+            d.layout.boost_count
+        );
         Ok(())
     }
 
@@ -412,56 +1365,51 @@ pub mod arena_match {
     }
 
     // ── D5. Derby server update (server validates collisions/pickups) ────
+    // Multiplayer races address one racer through its derby_player PDA;
+    // placements are handed out in the order racers finish, and the race
+    // is Finished once every racer has (or by finish_derby_dnf).
     pub fn derby_server_update(
-        ctx: Context<DerbyServerAction>,
+        ctx: Context<DerbyServerUpdate>,
         _race_id: u64,
         action: DerbyAction,
     ) -> Result<()> {
         let d = &mut ctx.accounts.derby_race;
         require!(d.status == DerbyStatus::Racing, DerbyError::RaceNotActive);
 
-        match action {
-            DerbyAction::RecordCollision => {
-                d.collisions = d.collisions.saturating_add(1);
-                msg!("Derby {}: collision #{}", d.race_id, d.collisions);
-            }
-            DerbyAction::CollectGold { item_index } => {
-                require!(item_index < DERBY_MAX_GOLD, DerbyError::InvalidItemIndex);
-                let bit = 1u16 << item_index;
-                require!(d.gold_bitmask & bit == 0, DerbyError::ItemAlreadyCollected);
-                d.gold_bitmask |= bit;
-                d.gold_collected = d.gold_collected.saturating_add(1);
-                msg!("Derby {}: gold #{} collected", d.race_id, item_index);
-            }
-            DerbyAction::CollectBoost { item_index } => {
-                require!(item_index < DERBY_MAX_BOOSTS, DerbyError::InvalidItemIndex);
-                let bit = 1u8 << item_index;
-                require!(d.boost_bitmask & bit == 0, DerbyError::ItemAlreadyCollected);
-                d.boost_bitmask |= bit;
-                d.boosts_collected = d.boosts_collected.saturating_add(1);
-                d.boost_end_tick = d.current_tick.saturating_add(DERBY_BOOST_DURATION_TICKS);
-                msg!("Derby {}: boost #{} collected, active until tick {}", d.race_id, item_index, d.boost_end_tick);
-            }
-            DerbyAction::PassCheckpoint { checkpoint_id } => {
-                require!(checkpoint_id < DERBY_CHECKPOINT_COUNT, DerbyError::InvalidCheckpoint);
-                let bit = 1u8 << checkpoint_id;
-                d.checkpoints_passed |= bit;
-                msg!("Derby {}: checkpoint {} passed", d.race_id, checkpoint_id);
-            }
-            DerbyAction::CompleteLap => {
-                let all_checkpoints = (1u8 << DERBY_CHECKPOINT_COUNT) - 1;
-                require!(d.checkpoints_passed & all_checkpoints == all_checkpoints, DerbyError::MissingCheckpoints);
-                d.current_lap = d.current_lap.saturating_add(1);
-                d.checkpoints_passed = 0; // reset for next lap
-                msg!("Derby {}: lap {} complete", d.race_id, d.current_lap);
+        if let Some(racer) = ctx.accounts.derby_player.as_mut() {
+            require!(d.is_multiplayer(), DerbyError::NotMultiplayer);
+            require!(racer.placement == 0, DerbyError::RacerAlreadyFinished);
+            if racer.progress.apply(d.race_id, d.current_tick, &d.layout, action)? {
+                d.finished_count += 1;
+                racer.placement = d.finished_count;
+                if racer.placement == 1 {
+                    d.finish_tick = racer.progress.finish_tick;
+                }
+                emit!(DerbyRacerFinished {
+                    race_id: d.race_id,
+                    player: racer.player,
+                    placement: racer.placement,
+                    finish_tick: racer.progress.finish_tick,
+                });
+                msg!("Derby {}: {} placed {}", d.race_id, racer.player, racer.placement);
+                if d.finished_count == d.racer_count {
+                    d.status = DerbyStatus::Finished;
+                    d.settled_at = Clock::get()?.unix_timestamp;
+                    msg!("Derby {}: all {} racers finished", d.race_id, d.racer_count);
+                }
             }
-            DerbyAction::FinishRace { tick } => {
-                require!(d.current_lap >= DERBY_MAX_LAPS, DerbyError::LapsNotComplete);
-                require!(tick <= DERBY_MAX_TICKS, DerbyError::RaceTimedOut);
+        } else {
+            require!(!d.is_multiplayer(), DerbyError::MissingDerbyPlayer);
+            let mut progress = d.progress();
+            let finished = progress.apply(d.race_id, d.current_tick, &d.layout, action)?;
+            d.set_progress(&progress);
+            if finished {
                 d.status = DerbyStatus::Finished;
-                d.finish_tick = tick;
                 d.settled_at = Clock::get()?.unix_timestamp;
-                msg!("Derby {}: finished at tick {}", d.race_id, d.finish_tick);
+                if d.ghost_finish_tick > 0 {
+                    d.ghost_margin = d.ghost_finish_tick as i32 - d.finish_tick as i32;
+                    msg!("Derby {}: {:+} ticks against the ghost", d.race_id, d.ghost_margin);
+                }
             }
         }
 
@@ -471,18 +1419,33 @@ pub mod arena_match {
     // ── D6. End derby — commit + undelegate back to L1 ───────────────────
     // No status check — matches arena's end_match pattern.
     // Game server is trusted to decide when to settle (fire-and-forget events
-    // may not have confirmed on ER yet).
-    pub fn end_derby(ctx: Context<EndDerby>, _race_id: u64) -> Result<()> {
+    // may not have confirmed on ER yet). Strict races must be Finished or
+    // Cancelled first. A multiplayer race's derby_player PDAs are passed as
+    // remaining accounts, all of them in join order, and undelegated with it
+    // (close_derby can't load a racer left delegated).
+    pub fn end_derby<'info>(
+        ctx: Context<'_, '_, 'info, 'info, EndDerby<'info>>,
+        _race_id: u64,
+    ) -> Result<()> {
         let d = &mut ctx.accounts.derby_race;
+        d.require_settleable()?;
+        let racer_count = if d.is_multiplayer() { d.racer_count as usize } else { 0 };
+        require!(ctx.remaining_accounts.len() == racer_count, DerbyError::MissingDerbyPlayer);
+        for (acc, player) in ctx.remaining_accounts.iter().zip(&d.racers[..racer_count]) {
+            require_keys_eq!(acc.key(), d.racer_pda(player), DerbyError::InvalidDerbyPlayer);
+        }
 
         let race_id = d.race_id;
         let finish_tick = d.finish_tick;
 
         d.exit(&crate::ID)?;
 
+        let info = d.to_account_info();
+        let mut accounts = vec![&info];
+        accounts.extend(ctx.remaining_accounts.iter());
         commit_and_undelegate_accounts(
             &ctx.accounts.payer,
-            vec![&d.to_account_info()],
+            accounts,
             &ctx.accounts.magic_context,
             &ctx.accounts.magic_program,
         )?;
@@ -491,20 +1454,44 @@ pub mod arena_match {
         Ok(())
     }
 
-    // ── D7. Close derby — reclaim rent after settlement ──────────────────
-    // No status check — matches arena's close_match pattern.
-    // Game server can close PDA at any time to reclaim rent.
+    // ── D7. Close derby — refund rent after settlement ───────────────────
+    // No status check unless strict — matches arena's close_match pattern.
+    // Game server can close PDA at any time; rent goes back to the player
+    // who paid it, minus the close fee agreed at creation.
     // Emits a SHA256 result hash before closing for permanent verifiability
-    // (tx logs survive PDA deletion).
-    pub fn close_derby(
-        ctx: Context<CloseDerby>,
+    // (tx logs survive PDA deletion). The result is recorded in the player's
+    // profile, and a Finished race is offered to its track's leaderboard.
+    // A Finished event race must be recorded in its event first.
+    // Multiplayer races also close every racer's derby_player PDA, passed
+    // as remaining accounts with the racers' wallets for the rent refund;
+    // their placements go into the hash and each finisher is offered to the
+    // track leaderboard. Profiles are only updated for single-racer races,
+    // so multiplayer races take none.
+    // The fastest finisher replaces the track's ghost if it beat it, and a
+    // ghost-mode race reports its margin against the ghost it raced.
+    pub fn close_derby<'info>(
+        ctx: Context<'_, '_, 'info, 'info, CloseDerby<'info>>,
         _race_id: u64,
     ) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
         let d = &ctx.accounts.derby_race;
+        d.require_closable(now)?;
+        require!(d.tokens_settled(), DerbyError::TokenPayoutPending);
+        require!(
+            d.event == Pubkey::default() || d.status != DerbyStatus::Finished || d.event_recorded,
+            DerbyError::EventReportPending
+        );
+        if d.status == DerbyStatus::Finished && !d.is_multiplayer() && !d.season_recorded {
+            let season = ctx.accounts.season.as_deref().map(|s| &**s);
+            require!(
+                !Season::requires_record(season, ctx.accounts.config.current_season, d.settled_at),
+                DerbyError::SeasonReportPending
+            );
+        }
 
         // Build deterministic byte representation of race result.
         // All fields read from the PDA (committed from ER via MagicBlock delegation).
-        let mut data = Vec::with_capacity(85);
+        let mut data = Vec::with_capacity(128);
         data.extend_from_slice(&d.race_id.to_le_bytes());
         data.extend_from_slice(d.player.as_ref());
         data.extend_from_slice(&d.vrf_seed);
@@ -515,10 +1502,579 @@ pub mod arena_match {
         data.extend_from_slice(&[d.boosts_collected]);
         data.extend_from_slice(&d.gold_bitmask.to_le_bytes());
         data.extend_from_slice(&[d.boost_bitmask]);
+        data.extend_from_slice(&[d.rewarded as u8]);
+        data.extend_from_slice(&d.reward_amount.to_le_bytes());
+        data.extend_from_slice(&d.boosted_ticks.to_le_bytes());
+        extend_splits(&mut data, &d.lap_ticks, &d.checkpoint_ticks);
+
+        let racers = d.load_racers(ctx.remaining_accounts)?;
+        for racer in &racers {
+            data.extend_from_slice(racer.player.as_ref());
+            data.extend_from_slice(&[racer.placement]);
+            data.extend_from_slice(&racer.progress.finish_tick.to_le_bytes());
+            data.extend_from_slice(&racer.progress.collisions.to_le_bytes());
+            data.extend_from_slice(&[racer.progress.gold_collected]);
+            data.extend_from_slice(&racer.progress.boosted_ticks.to_le_bytes());
+            extend_splits(&mut data, &racer.progress.lap_ticks, &racer.progress.checkpoint_ticks);
+        }
 
         let hash = solana_sha256_hasher::hash(&data);
         msg!("Derby {} result: hash={}", d.race_id, hash);
-        msg!("Derby {} closed, rent reclaimed", d.race_id);
+
+        if d.is_multiplayer() {
+            require!(ctx.accounts.player_profile.is_none(), DerbyError::MultiplayerUnsupported);
+        } else {
+            let profile = ctx.accounts.player_profile.as_mut().ok_or(DerbyError::MissingPlayerProfile)?;
+            let unlocked = profile.record_derby(d);
+            emit_achievements(profile, unlocked);
+        }
+
+        if !d.has_shared_track() {
+            require!(
+                ctx.accounts.track_leaderboard.is_none() && ctx.accounts.track_ghost.is_none(),
+                DerbyError::NoSharedTrack
+            );
+        } else if d.status != DerbyStatus::Finished {
+            // Only a Finished run sets the rent payers, so no other close
+            // may create a board or ghost nobody could reclaim
+            require!(
+                ctx.accounts.track_leaderboard.is_none() && ctx.accounts.track_ghost.is_none(),
+                DerbyError::UnexpectedTrackAccount
+            );
+        } else {
+            let board = ctx.accounts.track_leaderboard.as_mut().ok_or(DerbyError::MissingTrackLeaderboard)?;
+            if board.rent_payer == Pubkey::default() {
+                board.vrf_seed = d.vrf_seed;
+                board.rent_payer = ctx.accounts.payer.key();
+            }
+            let records: Vec<TrackRecord> = if d.is_multiplayer() {
+                racers.iter().filter(|r| r.placement > 0).map(|r| r.track_record()).collect()
+            } else {
+                vec![d.track_record()]
+            };
+            for record in records {
+                match board.record(record) {
+                    Some(rank) => msg!("Derby {}: {} track rank {}", d.race_id, record.player, rank + 1),
+                    None => msg!("Derby {}: {} outside the track top {}", d.race_id, record.player, TRACK_LEADERBOARD_SIZE),
+                }
+            }
+
+            let fastest = if d.is_multiplayer() {
+                racers
+                    .iter()
+                    .filter(|r| r.placement > 0)
+                    .map(|r| (r.player, r.progress))
+                    .min_by_key(|(_, progress)| progress.finish_tick)
+            } else {
+                Some((d.player, d.progress()))
+            };
+            let ghost = ctx.accounts.track_ghost.as_mut().ok_or(DerbyError::MissingTrackGhost)?;
+            if ghost.rent_payer == Pubkey::default() {
+                ghost.vrf_seed = d.vrf_seed;
+                ghost.rent_payer = ctx.accounts.payer.key();
+            }
+            if let Some((player, progress)) = fastest {
+                if ghost.beaten_by(progress.finish_tick) {
+                    ghost.replace(player, d.race_id, &progress, now);
+                    msg!("Derby {}: {} set the track ghost at tick {}", d.race_id, player, progress.finish_tick);
+                }
+            }
+        }
+
+        if d.status == DerbyStatus::Finished && d.ghost_finish_tick > 0 {
+            emit!(GhostRaceSettled {
+                race_id: d.race_id,
+                player: d.player,
+                ghost_race_id: d.ghost_race_id,
+                ghost_finish_tick: d.ghost_finish_tick,
+                finish_tick: d.finish_tick,
+                margin: d.ghost_margin,
+                beaten: d.ghost_margin > 0,
+            });
+        }
+
+        let closed = racers.len();
+        let rent_payer = ctx.accounts.rent_payer.to_account_info();
+        for racer in racers {
+            let refund_to = if racer.rent_payer == rent_payer.key() {
+                rent_payer.clone()
+            } else {
+                ctx.remaining_accounts
+                    .iter()
+                    .find(|acc| acc.key() == racer.rent_payer)
+                    .ok_or(DerbyError::MissingRentPayer)?
+                    .clone()
+            };
+            racer.close(refund_to)?;
+        }
+
+        transfer_lamports(&d.to_account_info(), &ctx.accounts.payer.to_account_info(), d.close_fee)?;
+        msg!(
+            "Derby {} and {} derby player PDA(s) closed, rent refunded (close fee: {})",
+            d.race_id,
+            closed,
+            d.close_fee
+        );
+        Ok(())
+    }
+
+    // ── D8. Raise derby dispute (player, on L1 during the challenge window)
+    pub fn raise_derby_dispute(ctx: Context<RaiseDerbyDispute>, _race_id: u64) -> Result<()> {
+        let d = &mut ctx.accounts.derby_race;
+        d.raise_dispute(Clock::get()?.unix_timestamp)?;
+        msg!("Derby {}: dispute raised by {}", d.race_id, d.player);
+        Ok(())
+    }
+
+    // ── D9. Resolve derby dispute (arbiter, on L1) ───────────────────────
+    pub fn resolve_derby_dispute(
+        ctx: Context<ResolveDerbyDispute>,
+        _race_id: u64,
+        resolution: DisputeResolution,
+    ) -> Result<()> {
+        ctx.accounts.derby_race.resolve_dispute(resolution)
+    }
+
+    // ── D10. Deposit derby tokens (player or server, on L1 before delegation)
+    // The player's entry is matched by the game server as the prize. The
+    // player deposits first and pays the vault's rent.
+    pub fn deposit_derby_tokens(ctx: Context<DepositDerbyTokens>, _race_id: u64) -> Result<()> {
+        let d = &mut ctx.accounts.derby_race;
+        require!(d.token_stake > 0, DerbyError::NoTokenStake);
+        require!(d.status == DerbyStatus::Created, DerbyError::InvalidDerbyState);
+
+        let depositor = ctx.accounts.depositor.key();
+        if depositor == d.player {
+            require!(!d.player_tokens_deposited, DerbyError::TokensAlreadyDeposited);
+            d.player_tokens_deposited = true;
+        } else if depositor == d.game_server {
+            require!(d.player_tokens_deposited, DerbyError::TokenDepositOrder);
+            require!(!d.server_tokens_deposited, DerbyError::TokensAlreadyDeposited);
+            d.server_tokens_deposited = true;
+        } else {
+            return Err(DerbyError::UnauthorizedPlayer.into());
+        }
+
+        token::transfer_checked(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.depositor_token_account.to_account_info(),
+                    mint: ctx.accounts.token_mint.to_account_info(),
+                    to: ctx.accounts.token_vault.to_account_info(),
+                    authority: ctx.accounts.depositor.to_account_info(),
+                },
+            ),
+            d.token_stake,
+            ctx.accounts.token_mint.decimals,
+        )?;
+        msg!("Derby {}: {} deposited {} tokens", d.race_id, depositor, d.token_stake);
+        Ok(())
+    }
+
+    // ── D11. Payout derby tokens (on L1, once) ───────────────────────────
+    // Finished → the player takes the pot. Settled while still Racing after
+    // the clock ran out (current_tick reached DERBY_MAX_TICKS) → the game
+    // server takes the pot. Cancelled, never started, or settled mid-race →
+    // each deposit refunded. A taken pot pays the protocol fee; refunds don't.
+    // A race the server abandoned is refunded after expire_derby.
+    pub fn payout_derby_tokens(ctx: Context<PayoutDerbyTokens>, race_id: u64) -> Result<()> {
+        let d = &ctx.accounts.derby_race;
+        require!(d.token_stake > 0, DerbyError::NoTokenStake);
+        require!(!d.tokens_paid_out, DerbyError::TokensAlreadyPaidOut);
+        d.require_closable(Clock::get()?.unix_timestamp)?;
+
+        let player_deposit = if d.player_tokens_deposited { d.token_stake } else { 0 };
+        let server_deposit = if d.server_tokens_deposited { d.token_stake } else { 0 };
+        let pot = player_deposit + server_deposit;
+        let timed_out = d.status == DerbyStatus::Racing && d.current_tick >= DERBY_MAX_TICKS;
+        let fee = if d.status == DerbyStatus::Finished || timed_out {
+            ctx.accounts.config.fee_for(pot)
+        } else {
+            0
+        };
+        let (player_amount, server_amount) = if d.status == DerbyStatus::Finished {
+            (pot - fee, 0)
+        } else if timed_out {
+            (0, pot - fee)
+        } else {
+            (player_deposit, server_deposit)
+        };
+
+        let a = &ctx.accounts;
+        let bump = [ctx.bumps.derby_race];
+        let seeds: &[&[u8]] = &[DERBY_SEED, &race_id.to_le_bytes(), &bump];
+        let authority = d.to_account_info();
+        transfer_tokens_signed(&a.token_program, &a.token_vault, &a.token_mint, &a.treasury_token_account, &authority, seeds, fee)?;
+        transfer_tokens_signed(&a.token_program, &a.token_vault, &a.token_mint, &a.player_token_account, &authority, seeds, player_amount)?;
+        transfer_tokens_signed(&a.token_program, &a.token_vault, &a.token_mint, &a.server_token_account, &authority, seeds, server_amount)?;
+        close_vault_signed(&a.token_program, &a.token_vault, &a.player.to_account_info(), &authority, seeds)?;
+
+        let d = &mut ctx.accounts.derby_race;
+        d.tokens_paid_out = true;
+        emit!(DerbyTokensPaidOut {
+            race_id: d.race_id,
+            mint: d.token_mint,
+            status: d.status,
+            pot,
+            fee,
+            player_amount,
+            server_amount,
+        });
+        msg!("Derby {} tokens paid out: player {} / server {} (fee: {})", d.race_id, player_amount, server_amount, fee);
+        Ok(())
+    }
+
+    // ── D12. Reward derby gold (on L1, once, before close_derby) ─────────
+    // Pays gold_collected × reward_per_gold of the reward mint to the
+    // player's associated token account. Finished races only, after the
+    // dispute window. close_derby's result hash records the reward.
+    pub fn reward_derby(ctx: Context<RewardDerby>, _race_id: u64) -> Result<()> {
+        let d = &ctx.accounts.derby_race;
+        let c = &ctx.accounts.config;
+        require!(c.reward_per_gold > 0, DerbyError::RewardsDisabled);
+        require!(d.status == DerbyStatus::Finished, DerbyError::RaceNotFinished);
+        require!(!d.is_multiplayer(), DerbyError::MultiplayerUnsupported);
+        require!(!d.rewarded, DerbyError::AlreadyRewarded);
+        d.require_closable(Clock::get()?.unix_timestamp)?;
+
+        let amount = (d.gold_collected as u64)
+            .checked_mul(c.reward_per_gold)
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+        let bump = [ctx.bumps.config];
+        let seeds: &[&[u8]] = &[CONFIG_SEED, &bump];
+        let a = &ctx.accounts;
+        let mint_authority: Option<Pubkey> = a.reward_mint.mint_authority.into();
+
+        if amount > 0 {
+            if mint_authority == Some(c.key()) {
+                token::mint_to(
+                    CpiContext::new_with_signer(
+                        a.token_program.to_account_info(),
+                        MintTo {
+                            mint: a.reward_mint.to_account_info(),
+                            to: a.player_token_account.to_account_info(),
+                            authority: c.to_account_info(),
+                        },
+                        &[seeds],
+                    ),
+                    amount,
+                )?;
+            } else {
+                let vault = a.reward_vault.as_ref().ok_or(DerbyError::MissingRewardVault)?;
+                transfer_tokens_signed(
+                    &a.token_program,
+                    vault,
+                    &a.reward_mint,
+                    &a.player_token_account,
+                    &c.to_account_info(),
+                    seeds,
+                    amount,
+                )?;
+            }
+        }
+
+        let d = &mut ctx.accounts.derby_race;
+        d.rewarded = true;
+        d.reward_amount = amount;
+        emit!(DerbyRewarded {
+            race_id: d.race_id,
+            player: d.player,
+            mint: ctx.accounts.reward_mint.key(),
+            gold_collected: d.gold_collected,
+            amount,
+        });
+        msg!("Derby {}: {} gold rewarded with {} tokens", d.race_id, d.gold_collected, amount);
+        Ok(())
+    }
+
+    // ── D13. Record derby in season (on L1, once, before close_derby) ────
+    // Finished races only: counts the finish in the player's season stats
+    // and feeds the season's best-time leaderboard. Like record_match_season,
+    // the season is the one running at settled_at.
+    pub fn record_derby_season(ctx: Context<RecordDerbySeason>, _race_id: u64) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let d = &mut ctx.accounts.derby_race;
+        require!(d.status == DerbyStatus::Finished, DerbyError::RaceNotFinished);
+        require!(!d.is_multiplayer(), DerbyError::MultiplayerUnsupported);
+        d.require_closable(now)?;
+        require!(!d.season_recorded, DerbyError::SeasonAlreadyRecorded);
+        let season = &mut ctx.accounts.season;
+        require!(season.is_active(d.settled_at), DerbyError::SeasonNotActive);
+
+        let stats = &mut ctx.accounts.player_stats;
+        stats.init_for(season.season_id, d.player);
+        stats.derby_races_finished = stats.derby_races_finished.saturating_add(1);
+        if stats.derby_best_finish_tick == 0 || d.finish_tick < stats.derby_best_finish_tick {
+            stats.derby_best_finish_tick = d.finish_tick;
+        }
+        season.record_derby_time(d.player, d.finish_tick, d.race_id);
+        d.season_recorded = true;
+        msg!("Derby {} recorded in season {}", d.race_id, season.season_id);
+        Ok(())
+    }
+
+    // ── D14. Create derby event (game server, on L1) ─────────────────────
+    // A time trial: every race created against the event during
+    // [start_ts, end_ts) runs the same vrf_seed and pays the entry fee into
+    // the event's prize pool.
+    pub fn create_derby_event(
+        ctx: Context<CreateDerbyEvent>,
+        event_id: u64,
+        vrf_seed: [u8; 32],
+        start_ts: i64,
+        end_ts: i64,
+        entry_fee: u64,
+        prize_split_bps: [u16; DERBY_EVENT_PRIZE_PLACES],
+    ) -> Result<()> {
+        require!(end_ts > start_ts, DerbyError::InvalidEventParams);
+        require!(
+            prize_split_bps.iter().map(|&bps| bps as u32).sum::<u32>() == 10_000,
+            DerbyError::InvalidEventParams
+        );
+        let event = &mut ctx.accounts.derby_event;
+        event.event_id = event_id;
+        event.game_server = ctx.accounts.game_server.key();
+        event.vrf_seed = vrf_seed;
+        event.start_ts = start_ts;
+        event.end_ts = end_ts;
+        event.entry_fee = entry_fee;
+        event.prize_split_bps = prize_split_bps;
+        event.entrant_count = 0;
+        event.leaderboard = [TrackRecord::default(); DERBY_EVENT_LEADERBOARD_SIZE];
+        event.finalized = false;
+        event.dispute_window_secs = 0;
+        msg!("Derby event {} created: {} to {}, entry fee {}", event_id, start_ts, end_ts, entry_fee);
+        Ok(())
+    }
+
+    // ── D15. Record derby in event (game server, on L1, before close_derby)
+    // Ranks a Finished event race by its player's best run. Races that
+    // settled after the window or after finalization are marked recorded
+    // without being ranked, so they can still be closed.
+    pub fn record_derby_event(ctx: Context<RecordDerbyEvent>, _race_id: u64) -> Result<()> {
+        let d = &mut ctx.accounts.derby_race;
+        require!(d.status == DerbyStatus::Finished, DerbyError::RaceNotFinished);
+        d.require_closable(Clock::get()?.unix_timestamp)?;
+        require!(!d.event_recorded, DerbyError::EventAlreadyRecorded);
+        let event = &mut ctx.accounts.derby_event;
+
+        if event.finalized || d.settled_at >= event.end_ts {
+            msg!("Derby {}: finished outside event {}, not ranked", d.race_id, event.event_id);
+        } else {
+            match event.record(d.track_record()) {
+                Some(rank) => msg!("Derby {}: event {} rank {}", d.race_id, event.event_id, rank + 1),
+                None => msg!("Derby {}: not a best run in event {}", d.race_id, event.event_id),
+            }
+        }
+        d.event_recorded = true;
+        Ok(())
+    }
+
+    // ── D16. Finalize derby event (game server, on L1, after end_ts) ─────
+    // Pays the prize pool to the podium; podium wallets are passed as
+    // remaining accounts. Unfilled places and rounding go to first place,
+    // and a pool with no finisher returns to the game server. Waits out
+    // the longest dispute window of the entered races past end_ts.
+    pub fn finalize_derby_event<'info>(
+        ctx: Context<'_, '_, 'info, 'info, FinalizeDerbyEvent<'info>>,
+        _event_id: u64,
+    ) -> Result<()> {
+        let event = &mut ctx.accounts.derby_event;
+        require!(!event.finalized, DerbyError::EventFinalized);
+        require!(Clock::get()?.unix_timestamp >= event.finalizable_at(), DerbyError::EventWindowOpen);
+
+        let pot = event.entry_fee * event.entrant_count as u64;
+        let podium = event.podium();
+        let places = podium.len();
+        let fee = if podium.is_empty() { 0 } else { ctx.accounts.config.fee_for(pot) };
+        let mut winners = [Pubkey::default(); DERBY_EVENT_PRIZE_PLACES];
+        let mut amounts = [0u64; DERBY_EVENT_PRIZE_PLACES];
+        for (place, record) in podium.iter().enumerate() {
+            winners[place] = record.player;
+            amounts[place] = (pot - fee) * event.prize_split_bps[place] as u64 / 10_000;
+        }
+        if !podium.is_empty() {
+            amounts[0] += pot - fee - amounts.iter().sum::<u64>();
+        }
+
+        let info = event.to_account_info();
+        transfer_lamports(&info, &ctx.accounts.treasury.to_account_info(), fee)?;
+        if podium.is_empty() {
+            transfer_lamports(&info, &ctx.accounts.game_server.to_account_info(), pot)?;
+        }
+        for (winner, amount) in winners.iter().zip(amounts).take(places) {
+            let to = ctx
+                .remaining_accounts
+                .iter()
+                .find(|acc| acc.key() == *winner)
+                .ok_or(DerbyError::MissingPrizeAccount)?;
+            transfer_lamports(&info, to, amount)?;
+        }
+        event.finalized = true;
+
+        emit!(DerbyEventFinalized {
+            event_id: event.event_id,
+            entrant_count: event.entrant_count,
+            pot,
+            fee,
+            winners,
+            amounts,
+        });
+        msg!("Derby event {} finalized: {} lamports to {} place(s)", event.event_id, pot - fee, places);
+        Ok(())
+    }
+
+    // ── D17. Join multiplayer derby (game server + racer, on L1) ─────────
+    // Every racer of a multiplayer race, its creator included, joins before
+    // the start and pays the rent of their own derby_player PDA.
+    pub fn join_derby(ctx: Context<JoinDerby>, race_id: u64) -> Result<()> {
+        let d = &mut ctx.accounts.derby_race;
+        require!(d.status == DerbyStatus::Created, DerbyError::InvalidDerbyState);
+        require!(d.is_multiplayer(), DerbyError::NotMultiplayer);
+        require!(d.racer_count < d.max_racers, DerbyError::RaceFull);
+        let racer = ctx.accounts.racer.key();
+        d.racers[d.racer_count as usize] = racer;
+        d.racer_count += 1;
+
+        let p = &mut ctx.accounts.derby_player;
+        p.race_id = race_id;
+        p.player = racer;
+        p.progress = DerbyProgress::default();
+        p.placement = 0;
+        p.rent_payer = racer;
+        msg!("{} joined derby {} ({}/{})", racer, race_id, d.racer_count, d.max_racers);
+        Ok(())
+    }
+
+    // ── D18. Delegate derby player to ER ─────────────────────────────────
+    pub fn delegate_derby_player(ctx: Context<DelegateDerbyPlayer>, race_id: u64, player: Pubkey) -> Result<()> {
+        ctx.accounts.delegate_pda(
+            &ctx.accounts.payer,
+            &[DERBY_PLAYER_SEED, &race_id.to_le_bytes(), player.as_ref()],
+            DelegateConfig {
+                validator: ctx.remaining_accounts.first().map(|acc| acc.key()),
+                ..Default::default()
+            },
+        )?;
+        msg!("Derby player delegated to ER");
+        Ok(())
+    }
+
+    // ── D19. Contribute derby seed (player, on L1 before delegation) ─────
+    // Commit-reveal races only. The player's entropy arrives after the
+    // server's commitment is on-chain, so neither side picks the layout.
+    pub fn contribute_derby_seed(
+        ctx: Context<ContributeDerbySeed>,
+        _race_id: u64,
+        player_seed: [u8; 32],
+    ) -> Result<()> {
+        let d = &mut ctx.accounts.derby_race;
+        require!(d.status == DerbyStatus::Created, DerbyError::InvalidDerbyState);
+        require!(d.seed_source == SeedSource::CommitReveal, DerbyError::SeedNotCommitReveal);
+        require!(!d.seed_contributed, DerbyError::SeedAlreadyContributed);
+        d.player_seed = player_seed;
+        d.seed_contributed = true;
+        msg!("Derby {}: player seed contributed", d.race_id);
+        Ok(())
+    }
+
+    // ── D20. Reveal derby seed (game server, on L1 before delegation) ────
+    // Opens the commitment and fixes
+    // vrf_seed = sha256(server_secret ‖ player_seed ‖ race_id LE).
+    pub fn reveal_derby_seed(
+        ctx: Context<DerbyServerAction>,
+        _race_id: u64,
+        server_secret: [u8; 32],
+    ) -> Result<()> {
+        let d = &mut ctx.accounts.derby_race;
+        require!(d.seed_source == SeedSource::CommitReveal, DerbyError::SeedNotCommitReveal);
+        require!(!d.seed_fulfilled, DerbyError::SeedAlreadyFulfilled);
+        require!(d.seed_contributed, DerbyError::SeedNotContributed);
+        require!(
+            solana_sha256_hasher::hash(&server_secret).to_bytes() == d.seed_commitment,
+            DerbyError::SeedCommitmentMismatch
+        );
+        d.server_secret = server_secret;
+        d.vrf_seed =
+            solana_sha256_hasher::hashv(&[&server_secret, &d.player_seed, &d.race_id.to_le_bytes()]).to_bytes();
+        d.seed_fulfilled = true;
+        emit!(DerbySeedFulfilled {
+            race_id: d.race_id,
+            vrf_seed: d.vrf_seed,
+            seed_commitment: d.seed_commitment,
+            player_seed: d.player_seed,
+            server_secret,
+        });
+        msg!("Derby {}: seed fulfilled by commit-reveal", d.race_id);
+        Ok(())
+    }
+
+    // ── D21. Cancel derby (game server, on ER or L1) ─────────────────────
+    // Moves a race that can't finish (disconnect, crash) to Cancelled, so
+    // strict races can still be settled. Token deposits are then refunded
+    // by payout_derby_tokens.
+    pub fn cancel_derby(ctx: Context<DerbyServerAction>, _race_id: u64) -> Result<()> {
+        let d = &mut ctx.accounts.derby_race;
+        require!(
+            d.status == DerbyStatus::Created || d.status == DerbyStatus::Racing,
+            DerbyError::InvalidDerbyState
+        );
+        d.status = DerbyStatus::Cancelled;
+        msg!("Derby {} cancelled at tick {}", d.race_id, d.current_tick);
+        Ok(())
+    }
+
+    // ── D22. Close track leaderboard (rent payer, on L1) ─────────────────
+    // Reclaims a board's rent once its track is no longer raced.
+    pub fn close_track_leaderboard(ctx: Context<CloseTrackLeaderboard>, _vrf_seed: [u8; 32]) -> Result<()> {
+        msg!("Track leaderboard closed, rent refunded to {}", ctx.accounts.rent_payer.key());
+        Ok(())
+    }
+
+    // ── D23. Close track ghost (rent payer, on L1) ───────────────────────
+    // Ghost races on the track can't be created until a new Finished run
+    // recreates it.
+    pub fn close_track_ghost(ctx: Context<CloseTrackGhost>, _vrf_seed: [u8; 32]) -> Result<()> {
+        msg!("Track ghost closed, rent refunded to {}", ctx.accounts.rent_payer.key());
+        Ok(())
+    }
+
+    // ── D24. Finish derby with DNFs (game server, on ER) ─────────────────
+    // Ends a multiplayer race that has a winner but stragglers that won't
+    // finish (disconnect, timeout). Racers without a placement keep 0 and
+    // count as did-not-finish; the rest settles like a fully Finished race.
+    pub fn finish_derby_dnf(ctx: Context<DerbyServerAction>, _race_id: u64) -> Result<()> {
+        let d = &mut ctx.accounts.derby_race;
+        require!(d.status == DerbyStatus::Racing, DerbyError::RaceNotActive);
+        require!(d.is_multiplayer(), DerbyError::NotMultiplayer);
+        require!(d.finished_count > 0, DerbyError::NoFinishers);
+        d.status = DerbyStatus::Finished;
+        d.settled_at = Clock::get()?.unix_timestamp;
+        msg!(
+            "Derby {}: finished with {} of {} racers, {} DNF",
+            d.race_id,
+            d.finished_count,
+            d.racer_count,
+            d.racer_count - d.finished_count
+        );
+        Ok(())
+    }
+
+    // ── D25. Expire derby (anyone, on ER or L1) ──────────────────────────
+    // cancel_derby for a race the game server abandoned: once
+    // DERBY_EXPIRY_SECS have passed since creation, a Created or Racing race
+    // moves to Cancelled, so payout_derby_tokens refunds both deposits.
+    pub fn expire_derby(ctx: Context<ExpireDerby>, _race_id: u64) -> Result<()> {
+        let d = &mut ctx.accounts.derby_race;
+        require!(
+            d.status == DerbyStatus::Created || d.status == DerbyStatus::Racing,
+            DerbyError::InvalidDerbyState
+        );
+        let now = Clock::get()?.unix_timestamp;
+        require!(now >= d.created_at.saturating_add(DERBY_EXPIRY_SECS), DerbyError::RaceNotExpired);
+        d.status = DerbyStatus::Cancelled;
+        msg!("Derby {} expired at tick {}, cancelled by {}", d.race_id, d.current_tick, ctx.accounts.caller.key());
         Ok(())
     }
 }
@@ -528,6 +2084,7 @@ pub mod arena_match {
 // ═══════════════════════════════════════════════════════════════════════════
 
 #[account]
+#[derive(Default)]
 pub struct ArenaMatchState {
     pub match_id: u64,            // 8
     pub game_server: Pubkey,      // 32 — authority for server-only actions
@@ -546,165 +2103,2571 @@ pub struct ArenaMatchState {
     pub winner: Pubkey,           // 32
     pub created_at: i64,          // 8
     pub settled_at: i64,          // 8
+    pub rent_payer: Pubkey,       // 32 — refunded when the PDA is closed
+    pub close_fee: u64,           // 8 — lamports kept by the game server on close
+    pub strict: bool,             // 1 — settlement requires Complete / Cancelled
+    pub arbiter: Pubkey,          // 32 — resolves disputes (default = disputes disabled)
+    pub dispute_window_secs: u32, // 4 — challenge period after settled_at
+    pub dispute_status: DisputeStatus, // 1
+    pub stake_lamports: u64,      // 8 — per-player stake held in the escrow PDA
+    pub paid_out: bool,           // 1 — escrow released
+    pub token_mint: Pubkey,       // 32 — SPL wager mint (default = no token wager)
+    pub token_stake: u64,         // 8 — per-player token stake held in the vault
+    pub player1_tokens_deposited: bool, // 1
+    pub player2_tokens_deposited: bool, // 1
+    pub tokens_paid_out: bool,    // 1 — token vault released
+    pub forfeited_by: Pubkey,     // 32 — player who forfeited (default = none)
+    pub ranked: bool,             // 1 — Complete result updates Elo ratings
+    pub season_recorded: bool,    // 1 — counted in the current season
+    pub player1_flawless_rounds: u8, // 1 — rounds P1 won without losing HP
+    pub player2_flawless_rounds: u8, // 1
+    pub tournament: Pubkey,       // 32 — spawning tournament (default = none)
+    pub tournament_reported: bool, // 1 — result fed back to the bracket
 }
 
 impl ArenaMatchState {
-    pub const LEN: usize = 8 + 32 + 32 + 32 + 1 + 1 + 1 + 1 + 1 + 1 + 4 + 4 + 4 + 4 + 32 + 8 + 8;
-}
+    pub const LEN: usize = 8 + 32 + 32 + 32 + 1 + 1 + 1 + 1 + 1 + 1 + 4 + 4 + 4 + 4 + 32 + 8 + 8 + 32 + 8 + 1 + 32 + 4 + 1
+        + 8 + 1 + 32 + 8 + 1 + 1 + 1 + 32 + 1 + 1 + 1 + 1 + 32 + 1;
 
-#[account]
-pub struct PlayerState {
-    pub match_id: u64,    // 8
-    pub player: Pubkey,   // 32
-    pub dx: i8,           // 1
-    pub dy: i8,           // 1
-    pub attacking: bool,  // 1
-    pub last_tick: u32,   // 4
-    pub input_count: u64, // 8
-}
+    pub fn player_state_pda(&self, player: &Pubkey) -> Pubkey {
+        Pubkey::find_program_address(
+            &[PLAYER_STATE_SEED, &self.match_id.to_le_bytes(), player.as_ref()],
+            &crate::ID,
+        )
+        .0
+    }
 
-impl PlayerState {
-    pub const LEN: usize = 8 + 32 + 1 + 1 + 1 + 4 + 8;
-}
+    pub fn init(
+        &mut self,
+        match_id: u64,
+        game_server: Pubkey,
+        player1: Pubkey,
+        rent_payer: Pubkey,
+        params: SettlementParams,
+    ) -> Result<()> {
+        require!(
+            params.close_fee <= Rent::get()?.minimum_balance(8 + Self::LEN),
+            ArenaError::CloseFeeTooHigh
+        );
+        require!(params.dispute_params_valid(), ArenaError::InvalidDisputeParams);
+        self.match_id = match_id;
+        self.game_server = game_server;
+        self.player1 = player1;
+        self.player2 = Pubkey::default();
+        self.status = MatchStatus::WaitingForPlayer;
+        self.current_round = 0;
+        self.player1_rounds_won = 0;
+        self.player2_rounds_won = 0;
+        self.player1_hp = HP_PER_ROUND;
+        self.player2_hp = HP_PER_ROUND;
+        self.current_tick = 0;
+        self.round_start_tick = 0;
+        self.last_p1_damage_tick = 0;
+        self.last_p2_damage_tick = 0;
+        self.winner = Pubkey::default();
+        self.created_at = Clock::get()?.unix_timestamp;
+        self.settled_at = 0;
+        self.rent_payer = rent_payer;
+        self.close_fee = params.close_fee;
+        self.strict = params.strict;
+        self.arbiter = params.arbiter;
+        self.dispute_window_secs = params.dispute_window_secs;
+        self.dispute_status = DisputeStatus::None;
+        self.stake_lamports = 0;
+        self.paid_out = false;
+        self.token_mint = Pubkey::default();
+        self.token_stake = 0;
+        self.player1_tokens_deposited = false;
+        self.player2_tokens_deposited = false;
+        self.tokens_paid_out = false;
+        self.forfeited_by = Pubkey::default();
+        self.ranked = false;
+        self.season_recorded = false;
+        self.player1_flawless_rounds = 0;
+        self.player2_flawless_rounds = 0;
+        self.tournament = Pubkey::default();
+        self.tournament_reported = false;
+        Ok(())
+    }
 
-#[account]
-pub struct DerbyRaceState {
-    pub race_id: u64,           // 8
-    pub game_server: Pubkey,    // 32
-    pub player: Pubkey,         // 32
-    pub vrf_seed: [u8; 32],     // 32
-    pub status: DerbyStatus,    // 1
-    pub current_tick: u32,      // 4
-    pub current_lap: u8,        // 1
-    pub checkpoints_passed: u8, // 1 — bitmask per lap (4 bits used)
-    pub collisions: u16,        // 2
-    pub gold_collected: u8,     // 1
-    pub boosts_collected: u8,   // 1
-    pub boost_end_tick: u32,    // 4
-    pub finish_tick: u32,       // 4
-    pub gold_bitmask: u16,      // 2 — which gold coins collected (15 bits)
-    pub boost_bitmask: u8,      // 1 — which boosts collected (8 bits)
-    pub created_at: i64,        // 8
-    pub settled_at: i64,        // 8
-}
+    /// Nothing left in the token vault: no deposit was made, or the pot was
+    /// paid out.
+    pub fn tokens_settled(&self) -> bool {
+        self.tokens_paid_out || !(self.player1_tokens_deposited || self.player2_tokens_deposited)
+    }
 
-impl DerbyRaceState {
-    pub const LEN: usize = 8 + 32 + 32 + 32 + 1 + 4 + 1 + 1 + 2 + 1 + 1 + 4 + 4 + 2 + 1 + 8 + 8;
-}
+    pub fn require_settleable(&self) -> Result<()> {
+        if self.strict {
+            require!(
+                self.status == MatchStatus::Complete || self.status == MatchStatus::Cancelled,
+                ArenaError::MatchNotComplete
+            );
+        }
+        Ok(())
+    }
 
-// ═══════════════════════════════════════════════════════════════════════════
-// ENUMS
-// ═══════════════════════════════════════════════════════════════════════════
+    pub fn require_closable(&self, now: i64) -> Result<()> {
+        self.require_settleable()?;
+        self.require_result_final(now)
+    }
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
-pub enum MatchStatus {
-    WaitingForPlayer, // 0
-    Countdown,        // 1
-    Active,           // 2
-    RoundEnd,         // 3
-    Complete,         // 4
-    Cancelled,        // 5
-}
+    /// No open dispute and the challenge window elapsed. A match that never
+    /// reached a result (settled_at == 0) has nothing to contest; a resolved
+    /// dispute ends the window early.
+    pub fn require_result_final(&self, now: i64) -> Result<()> {
+        require!(self.dispute_status != DisputeStatus::Raised, ArenaError::DisputePending);
+        if self.settled_at != 0 && self.dispute_status == DisputeStatus::None {
+            let deadline = self.settled_at.saturating_add(self.dispute_window_secs as i64);
+            require!(now >= deadline, ArenaError::DisputeWindowOpen);
+        }
+        Ok(())
+    }
 
-impl Default for MatchStatus {
-    fn default() -> Self {
-        MatchStatus::WaitingForPlayer
+    pub fn raise_dispute(&mut self, now: i64) -> Result<()> {
+        require!(self.arbiter != Pubkey::default(), ArenaError::DisputesDisabled);
+        require!(self.status == MatchStatus::Complete, ArenaError::MatchNotComplete);
+        require!(self.dispute_status == DisputeStatus::None, ArenaError::InvalidDisputeState);
+        let deadline = self.settled_at.saturating_add(self.dispute_window_secs as i64);
+        require!(now < deadline, ArenaError::DisputeWindowClosed);
+        self.dispute_status = DisputeStatus::Raised;
+        Ok(())
     }
-}
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
-pub enum DerbyStatus {
-    Created,   // 0 — on L1, awaiting delegation
-    Racing,    // 1 — active on ER
-    Finished,  // 2 — race complete
-    Cancelled, // 3
-}
+    pub fn resolve_dispute(&mut self, resolution: DisputeResolution) -> Result<()> {
+        require!(self.dispute_status == DisputeStatus::Raised, ArenaError::InvalidDisputeState);
+        match resolution {
+            DisputeResolution::Confirm => {
+                msg!("Match {}: dispute resolved, result confirmed", self.match_id);
+            }
+            DisputeResolution::OverrideWinner { winner } => {
+                require!(
+                    winner == self.player1 || winner == self.player2 || winner == Pubkey::default(),
+                    ArenaError::InvalidWinner
+                );
+                self.winner = winner;
+                msg!("Match {}: dispute resolved, winner set to {}", self.match_id, winner);
+            }
+            DisputeResolution::Void => {
+                self.status = MatchStatus::Cancelled;
+                self.winner = Pubkey::default();
+                msg!("Match {}: dispute resolved, result voided", self.match_id);
+            }
+        }
+        self.dispute_status = DisputeStatus::Resolved;
+        Ok(())
+    }
 
-impl Default for DerbyStatus {
-    fn default() -> Self {
-        DerbyStatus::Created
+    pub fn join(&mut self, player2: Pubkey) -> Result<()> {
+        require!(self.status == MatchStatus::WaitingForPlayer, ArenaError::MatchNotJoinable);
+        require!(self.player1 != player2, ArenaError::CannotJoinOwnMatch);
+
+        self.player2 = player2;
+        self.status = MatchStatus::Countdown;
+        self.current_round = 1;
+        self.current_tick = 0;
+        self.round_start_tick = 0;
+        self.player1_hp = HP_PER_ROUND;
+        self.player2_hp = HP_PER_ROUND;
+        msg!("Player {} joined match {}", player2, self.match_id);
+        Ok(())
     }
-}
 
+    fn is_in_progress(&self) -> bool {
+        self.status == MatchStatus::Active || self.status == MatchStatus::Countdown || self.status == MatchStatus::RoundEnd
+    }
+
+    pub fn advance_tick(&mut self, tick: u32) -> Result<()> {
+        require!(self.is_in_progress(), ArenaError::InvalidMatchState);
+        if tick > self.current_tick {
+            self.current_tick = tick;
+        }
+        Ok(())
+    }
+
+    pub fn start_round(&mut self) -> Result<()> {
+        require!(
+            self.status == MatchStatus::Countdown || self.status == MatchStatus::RoundEnd,
+            ArenaError::InvalidMatchState
+        );
+        require!(
+            self.token_stake == 0 || (self.player1_tokens_deposited && self.player2_tokens_deposited),
+            ArenaError::TokenStakePending
+        );
+        self.status = MatchStatus::Active;
+        self.round_start_tick = self.current_tick;
+        self.player1_hp = HP_PER_ROUND;
+        self.player2_hp = HP_PER_ROUND;
+        self.last_p1_damage_tick = 0;
+        self.last_p2_damage_tick = 0;
+        msg!("Round {} started at tick {}", self.current_round, self.current_tick);
+        Ok(())
+    }
+
+    pub fn apply_damage(&mut self, target_slot: u8) -> Result<()> {
+        require!(self.status == MatchStatus::Active, ArenaError::MatchNotActive);
+
+        match target_slot {
+            1 => {
+                let cooldown_ok = self.current_tick.saturating_sub(self.last_p2_damage_tick) >= DAMAGE_COOLDOWN_TICKS;
+                require!(cooldown_ok, ArenaError::DamageCooldown);
+                self.player1_hp = self.player1_hp.saturating_sub(MAX_DAMAGE_PER_HIT);
+                self.last_p2_damage_tick = self.current_tick;
+                msg!("P1 hit! HP: {}", self.player1_hp);
+            }
+            2 => {
+                let cooldown_ok = self.current_tick.saturating_sub(self.last_p1_damage_tick) >= DAMAGE_COOLDOWN_TICKS;
+                require!(cooldown_ok, ArenaError::DamageCooldown);
+                self.player2_hp = self.player2_hp.saturating_sub(MAX_DAMAGE_PER_HIT);
+                self.last_p1_damage_tick = self.current_tick;
+                msg!("P2 hit! HP: {}", self.player2_hp);
+            }
+            _ => return Err(ArenaError::InvalidTargetSlot.into()),
+        }
+
+        Ok(())
+    }
+
+    pub fn end_round(&mut self) -> Result<()> {
+        require!(self.status == MatchStatus::Active, ArenaError::MatchNotActive);
+
+        if self.player1_hp > self.player2_hp {
+            self.player1_rounds_won += 1;
+            if self.player1_hp == HP_PER_ROUND {
+                self.player1_flawless_rounds += 1;
+            }
+            msg!("Round {} won by P1", self.current_round);
+        } else if self.player2_hp > self.player1_hp {
+            self.player2_rounds_won += 1;
+            if self.player2_hp == HP_PER_ROUND {
+                self.player2_flawless_rounds += 1;
+            }
+            msg!("Round {} won by P2", self.current_round);
+        } else {
+            msg!("Round {} draw", self.current_round);
+        }
+
+        if self.player1_rounds_won >= WINS_NEEDED {
+            self.status = MatchStatus::Complete;
+            self.winner = self.player1;
+            self.settled_at = Clock::get()?.unix_timestamp;
+            msg!("Match complete! Winner: P1 ({})", self.player1);
+        } else if self.player2_rounds_won >= WINS_NEEDED {
+            self.status = MatchStatus::Complete;
+            self.winner = self.player2;
+            self.settled_at = Clock::get()?.unix_timestamp;
+            msg!("Match complete! Winner: P2 ({})", self.player2);
+        } else if self.current_round >= MAX_ROUNDS {
+            self.status = MatchStatus::Complete;
+            self.winner = Pubkey::default();
+            self.settled_at = Clock::get()?.unix_timestamp;
+            msg!("Match complete! Draw.");
+        } else {
+            self.status = MatchStatus::RoundEnd;
+            self.current_round += 1;
+            msg!("Advancing to round {}", self.current_round);
+        }
+
+        Ok(())
+    }
+
+    pub fn forfeit(&mut self, forfeiter_slot: u8) -> Result<()> {
+        require!(self.is_in_progress(), ArenaError::InvalidMatchState);
+
+        match forfeiter_slot {
+            1 => {
+                self.winner = self.player2;
+                self.forfeited_by = self.player1;
+                msg!("P1 forfeited. Winner: P2 ({})", self.player2);
+            }
+            2 => {
+                self.winner = self.player1;
+                self.forfeited_by = self.player2;
+                msg!("P2 forfeited. Winner: P1 ({})", self.player1);
+            }
+            _ => return Err(ArenaError::InvalidTargetSlot.into()),
+        }
+
+        self.status = MatchStatus::Complete;
+        self.settled_at = Clock::get()?.unix_timestamp;
+        Ok(())
+    }
+
+    pub fn abort(&mut self) -> Result<()> {
+        require!(
+            self.status == MatchStatus::WaitingForPlayer || self.is_in_progress(),
+            ArenaError::InvalidMatchState
+        );
+        self.status = MatchStatus::Cancelled;
+        self.winner = Pubkey::default();
+        msg!("Match {} aborted in round {}", self.match_id, self.current_round);
+        Ok(())
+    }
+}
+
+#[account]
+#[derive(Default)]
+pub struct PlayerState {
+    pub match_id: u64,    // 8
+    pub player: Pubkey,   // 32
+    pub dx: i8,           // 1
+    pub dy: i8,           // 1
+    pub attacking: bool,  // 1
+    pub last_tick: u32,   // 4
+    pub input_count: u64, // 8
+    pub rent_payer: Pubkey, // 32 — refunded when the PDA is closed
+}
+
+impl PlayerState {
+    pub const LEN: usize = 8 + 32 + 1 + 1 + 1 + 4 + 8 + 32;
+
+    pub fn init(&mut self, match_id: u64, player: Pubkey, rent_payer: Pubkey) {
+        self.match_id = match_id;
+        self.player = player;
+        self.dx = 0;
+        self.dy = 0;
+        self.attacking = false;
+        self.last_tick = 0;
+        self.input_count = 0;
+        self.rent_payer = rent_payer;
+    }
+}
+
+#[account]
+pub struct MatchEscrow {
+    pub match_id: u64,           // 8
+    pub stake: u64,              // 8 — lamports per player
+    pub player1_deposited: bool, // 1
+    pub player2_deposited: bool, // 1
+}
+
+impl MatchEscrow {
+    pub const LEN: usize = 8 + 8 + 1 + 1;
+}
+
+/// Global protocol settings — a single PDA, set up by the program's upgrade
+/// authority and editable by its admin without a redeploy.
+#[account]
+pub struct ArenaConfig {
+    pub admin: Pubkey,    // 32 — may update the config
+    pub treasury: Pubkey, // 32 — receives the protocol fee
+    pub fee_bps: u16,     // 2 — fee on every pot paid to a winner
+    pub reward_mint: Pubkey,  // 32 — derby gold reward mint (default = rewards off)
+    pub reward_per_gold: u64, // 8 — reward tokens per gold collected
+    pub current_season: u32,  // 4 — 0 = no season started yet
+}
+
+impl ArenaConfig {
+    pub const LEN: usize = 32 + 32 + 2 + 32 + 8 + 4;
+
+    pub fn fee_for(&self, pot: u64) -> u64 {
+        (pot as u128 * self.fee_bps as u128 / 10_000) as u64
+    }
+}
+
+/// Lifetime stats per wallet. Outlives the per-match accounts — created on
+/// first settlement and updated by close_match / close_derby.
+#[account]
+#[derive(Default)]
+pub struct PlayerProfile {
+    pub wallet: Pubkey,              // 32
+    pub arena_wins: u32,             // 4
+    pub arena_losses: u32,           // 4
+    pub arena_draws: u32,            // 4
+    pub arena_forfeits: u32,         // 4 — matches this player forfeited
+    pub arena_rounds_won: u32,       // 4
+    pub derby_races_finished: u32,   // 4
+    pub derby_best_finish_tick: u32, // 4 — 0 = no finished race yet
+    pub derby_total_gold: u32,       // 4
+    pub derby_total_collisions: u32, // 4
+    pub arena_rating: u16,           // 2 — Elo, 0 = unrated (ELO_INITIAL_RATING)
+    pub arena_ranked_games: u32,     // 4
+    pub achievements: u32,           // 4 — ACHIEVEMENT_* bits unlocked
+    pub arena_win_streak: u32,       // 4 — current run of Complete wins
+}
+
+impl PlayerProfile {
+    pub const LEN: usize = 32 + 4 + 4 + 4 + 4 + 4 + 4 + 4 + 4 + 4 + 2 + 4 + 4 + 4;
+
+    /// Sets the given achievement bits and returns the ones that are new.
+    pub fn unlock(&mut self, achievements: u32) -> u32 {
+        let new = achievements & !self.achievements;
+        self.achievements |= new;
+        new
+    }
+
+    pub fn rating(&self) -> u16 {
+        if self.arena_rating == 0 {
+            ELO_INITIAL_RATING
+        } else {
+            self.arena_rating
+        }
+    }
+
+    /// Zero-sum Elo update for a Complete ranked match. Returns player 1's
+    /// rating change; player 2 gets the opposite (both floored at
+    /// ELO_MIN_RATING).
+    pub fn record_rated_match(p1: &mut PlayerProfile, p2: &mut PlayerProfile, m: &ArenaMatchState) -> i32 {
+        let (r1, r2) = (p1.rating(), p2.rating());
+        let score1: i32 = if m.winner == m.player1 {
+            10_000
+        } else if m.winner == m.player2 {
+            0
+        } else {
+            5_000
+        };
+        let diff = ELO_K_FACTOR * (score1 - elo_expected_bps(r1, r2) as i32);
+        let delta = (diff + diff.signum() * 5_000) / 10_000;
+
+        p1.arena_rating = (r1 as i32 + delta).max(ELO_MIN_RATING as i32) as u16;
+        p2.arena_rating = (r2 as i32 - delta).max(ELO_MIN_RATING as i32) as u16;
+        p1.arena_ranked_games = p1.arena_ranked_games.saturating_add(1);
+        p2.arena_ranked_games = p2.arena_ranked_games.saturating_add(1);
+        delta
+    }
+
+    /// Only Complete matches count — cancelled or abandoned ones don't.
+    /// Returns the achievements this match unlocked.
+    pub fn record_match(&mut self, m: &ArenaMatchState, wallet: Pubkey) -> u32 {
+        self.wallet = wallet;
+        if m.status != MatchStatus::Complete {
+            return 0;
+        }
+        let (rounds_won, flawless_rounds) = if wallet == m.player1 {
+            (m.player1_rounds_won, m.player1_flawless_rounds)
+        } else {
+            (m.player2_rounds_won, m.player2_flawless_rounds)
+        };
+        self.arena_rounds_won = self.arena_rounds_won.saturating_add(rounds_won as u32);
+        if m.winner == Pubkey::default() {
+            self.arena_draws = self.arena_draws.saturating_add(1);
+            self.arena_win_streak = 0;
+        } else if m.winner == wallet {
+            self.arena_wins = self.arena_wins.saturating_add(1);
+            self.arena_win_streak = self.arena_win_streak.saturating_add(1);
+        } else {
+            self.arena_losses = self.arena_losses.saturating_add(1);
+            self.arena_win_streak = 0;
+        }
+        if m.forfeited_by == wallet {
+            self.arena_forfeits = self.arena_forfeits.saturating_add(1);
+        }
+
+        let mut earned = 0;
+        if flawless_rounds > 0 {
+            earned |= ACHIEVEMENT_FLAWLESS_ROUND;
+        }
+        if self.arena_win_streak >= ACHIEVEMENT_WIN_STREAK_LEN {
+            earned |= ACHIEVEMENT_WIN_STREAK;
+        }
+        self.unlock(earned)
+    }
+
+    /// Gold and collisions count for every race that was run, finishes and
+    /// best time only for Finished ones. Returns the achievements this race
+    /// unlocked.
+    pub fn record_derby(&mut self, d: &DerbyRaceState) -> u32 {
+        self.wallet = d.player;
+        let mut earned = 0;
+        match d.status {
+            DerbyStatus::Finished => {
+                self.derby_races_finished = self.derby_races_finished.saturating_add(1);
+                if self.derby_best_finish_tick == 0 || d.finish_tick < self.derby_best_finish_tick {
+                    self.derby_best_finish_tick = d.finish_tick;
+                }
+                if d.gold_bitmask == d.layout.all_gold_mask() {
+                    earned |= ACHIEVEMENT_ALL_GOLD;
+                }
+                if d.collisions == 0 {
+                    earned |= ACHIEVEMENT_CLEAN_FINISH;
+                }
+            }
+            DerbyStatus::Racing => {}
+            DerbyStatus::Created | DerbyStatus::Cancelled => return 0,
+        }
+        self.derby_total_gold = self.derby_total_gold.saturating_add(d.gold_collected as u32);
+        self.derby_total_collisions = self.derby_total_collisions.saturating_add(d.collisions as u32);
+        self.unlock(earned)
+    }
+}
+
+/// One competitive season. Leaderboards hold the top SEASON_LEADERBOARD_SIZE
+/// players, one entry each; `frozen` is set when the next season starts.
+#[account]
+pub struct Season {
+    pub season_id: u32, // 4
+    pub start_ts: i64,  // 8
+    pub end_ts: i64,    // 8
+    pub frozen: bool,   // 1
+    pub arena_leaderboard: [SeasonArenaEntry; SEASON_LEADERBOARD_SIZE], // 10 × 36 — most wins first
+    pub derby_leaderboard: [SeasonDerbyEntry; SEASON_LEADERBOARD_SIZE], // 10 × 44 — fastest finish first
+}
+
+impl Season {
+    pub const LEN: usize = 4 + 8 + 8 + 1 + SEASON_LEADERBOARD_SIZE * (32 + 4) + SEASON_LEADERBOARD_SIZE * (32 + 4 + 8);
+
+    /// Whether results stamped at `ts` still go into this season.
+    pub fn is_active(&self, ts: i64) -> bool {
+        !self.frozen && ts >= self.start_ts && ts < self.end_ts
+    }
+
+    /// A result settled inside an unfrozen season must be recorded there
+    /// before its account closes. `season` is the config's current season,
+    /// and leaving it out once a season exists counts as unrecorded.
+    pub fn requires_record(season: Option<&Season>, current_season: u32, settled_at: i64) -> bool {
+        current_season != 0 && season.is_none_or(|s| s.is_active(settled_at))
+    }
+
+    /// Sets the player's season wins and re-ranks. Ties keep the player who
+    /// got there first ahead.
+    pub fn record_arena_wins(&mut self, player: Pubkey, wins: u32) {
+        let mut entries: Vec<SeasonArenaEntry> = self
+            .arena_leaderboard
+            .iter()
+            .copied()
+            .filter(|e| e.player != Pubkey::default() && e.player != player)
+            .collect();
+        entries.push(SeasonArenaEntry { player, wins });
+        entries.sort_by(|a, b| b.wins.cmp(&a.wins));
+        entries.truncate(SEASON_LEADERBOARD_SIZE);
+        self.arena_leaderboard = [SeasonArenaEntry::default(); SEASON_LEADERBOARD_SIZE];
+        self.arena_leaderboard[..entries.len()].copy_from_slice(&entries);
+    }
+
+    /// Keeps the player's best time only. Ties keep the earlier result ahead.
+    pub fn record_derby_time(&mut self, player: Pubkey, finish_tick: u32, race_id: u64) {
+        if let Some(existing) = self.derby_leaderboard.iter().find(|e| e.player == player) {
+            if existing.finish_tick <= finish_tick {
+                return;
+            }
+        }
+        let mut entries: Vec<SeasonDerbyEntry> = self
+            .derby_leaderboard
+            .iter()
+            .copied()
+            .filter(|e| e.player != Pubkey::default() && e.player != player)
+            .collect();
+        entries.push(SeasonDerbyEntry { player, finish_tick, race_id });
+        entries.sort_by(|a, b| a.finish_tick.cmp(&b.finish_tick));
+        entries.truncate(SEASON_LEADERBOARD_SIZE);
+        self.derby_leaderboard = [SeasonDerbyEntry::default(); SEASON_LEADERBOARD_SIZE];
+        self.derby_leaderboard[..entries.len()].copy_from_slice(&entries);
+    }
+}
+
+/// A player's results within one season — separate from the lifetime
+/// totals in PlayerProfile.
+#[account]
+#[derive(Default)]
+pub struct SeasonStats {
+    pub season_id: u32,              // 4
+    pub wallet: Pubkey,              // 32
+    pub arena_wins: u32,             // 4
+    pub arena_losses: u32,           // 4
+    pub arena_draws: u32,            // 4
+    pub derby_races_finished: u32,   // 4
+    pub derby_best_finish_tick: u32, // 4 — 0 = no finished race yet
+}
+
+impl SeasonStats {
+    pub const LEN: usize = 4 + 32 + 4 + 4 + 4 + 4 + 4;
+
+    pub fn init_for(&mut self, season_id: u32, wallet: Pubkey) {
+        self.season_id = season_id;
+        self.wallet = wallet;
+    }
+}
+
+/// Single-elimination bracket. `bracket` is a binary tree stored as an
+/// array: node 0 is the final, node `k` is played by the winners of nodes
+/// `2k + 1` and `2k + 2`, and the `bracket_size` leaves from
+/// `bracket_size - 1` on hold the seeded entrants.
+#[account]
+pub struct Tournament {
+    pub tournament_id: u64,        // 8
+    pub game_server: Pubkey,       // 32 — spawns the bracket matches
+    pub base_match_id: u64,        // 8 — node k is played as match base_match_id + k
+    pub status: TournamentStatus,  // 1
+    pub max_entrants: u8,          // 1
+    pub entrant_count: u8,         // 1
+    pub bracket_size: u8,          // 1 — power of two, set at start
+    pub entry_fee: u64,            // 8 — lamports per entrant, held in this PDA
+    pub prize_split_bps: [u16; 2], // 4 — champion, runner-up
+    pub params: SettlementParams,  // 45 — applied to every bracket match
+    pub entrants: [Pubkey; TOURNAMENT_MAX_ENTRANTS],  // 32 × 32 — join order
+    pub bracket: [Pubkey; TOURNAMENT_BRACKET_NODES], // 63 × 32 — winner per node (default = undecided)
+    pub champion: Pubkey,          // 32
+    pub runner_up: Pubkey,         // 32
+    pub paid_out: bool,            // 1
+    pub format: TournamentFormat,  // 1
+    pub total_rounds: u8,          // 1 — Swiss / round-robin
+    pub round: u8,                 // 1 — current round, from 1
+    pub pairing_count: u8,         // 1
+    pub reported_mask: u16,        // 2 — pairings of this round with a result
+    pub byes: u32,                 // 4 — entrants that had a Swiss bye
+    pub pairings: [[u8; 2]; TOURNAMENT_MAX_PAIRINGS], // 16 × 2 — entrant indices, TOURNAMENT_NO_ENTRANT = bye
+    pub points: [u8; TOURNAMENT_MAX_ENTRANTS],        // 32 — win 2, draw 1
+    pub played: [u32; TOURNAMENT_MAX_ENTRANTS],       // 32 × 4 — bit j = has played entrant j
+    pub buchholz: [u16; TOURNAMENT_MAX_ENTRANTS],     // 32 × 2 — set when the last round ends
+}
+
+impl Tournament {
+    pub const LEN: usize = 8 + 32 + 8 + 1 + 1 + 1 + 1 + 8 + 4 + (8 + 1 + 32 + 4)
+        + TOURNAMENT_MAX_ENTRANTS * 32
+        + TOURNAMENT_BRACKET_NODES * 32
+        + 32 + 32 + 1
+        + 1 + 1 + 1 + 1 + 2 + 4
+        + TOURNAMENT_MAX_PAIRINGS * 2
+        + TOURNAMENT_MAX_ENTRANTS * (1 + 4 + 2);
+
+    /// Elimination node `k` is match `base_match_id + k`; Swiss and
+    /// round-robin pairing `k` of round `r` is `base_match_id + r × 16 + k`.
+    pub fn match_id_for(&self, node: u8) -> u64 {
+        match self.format {
+            TournamentFormat::SingleElimination => self.base_match_id + node as u64,
+            TournamentFormat::Swiss | TournamentFormat::RoundRobin => {
+                self.base_match_id + self.round as u64 * TOURNAMENT_MAX_PAIRINGS as u64 + node as u64
+            }
+        }
+    }
+
+    pub fn finish(&mut self, champion: Pubkey, runner_up: Pubkey) {
+        self.champion = champion;
+        self.runner_up = runner_up;
+        self.status = TournamentStatus::Complete;
+    }
+
+    /// Places entrants so every first-round pair has at least one player:
+    /// the first half take the left slots, the rest the right slots. A pair
+    /// without a right-hand player is a bye and advances straight away.
+    pub fn seed_bracket(&mut self) {
+        let size = (self.entrant_count as usize).next_power_of_two();
+        let half = size / 2;
+        for (k, entrant) in self.entrants[..self.entrant_count as usize].iter().enumerate() {
+            let slot = if k < half { 2 * k } else { 2 * (k - half) + 1 };
+            self.bracket[size - 1 + slot] = *entrant;
+        }
+        for i in 0..half {
+            let right = size - 1 + 2 * i + 1;
+            if self.bracket[right] == Pubkey::default() {
+                self.bracket[half - 1 + i] = self.bracket[right - 1];
+            }
+        }
+        self.bracket_size = size as u8;
+    }
+
+    /// Players of an undecided match node whose two feeder nodes are
+    /// decided, or of an unreported Swiss / round-robin pairing.
+    pub fn pairing(&self, node: usize) -> Result<(Pubkey, Pubkey)> {
+        if self.format != TournamentFormat::SingleElimination {
+            require!(
+                node < self.pairing_count as usize && self.reported_mask & (1 << node) == 0,
+                ArenaError::InvalidBracketNode
+            );
+            let [a, b] = self.pairings[node];
+            require!(b != TOURNAMENT_NO_ENTRANT, ArenaError::InvalidBracketNode);
+            return Ok((self.entrants[a as usize], self.entrants[b as usize]));
+        }
+        require!(node + 1 < self.bracket_size as usize, ArenaError::InvalidBracketNode);
+        require!(self.bracket[node] == Pubkey::default(), ArenaError::InvalidBracketNode);
+        let (left, right) = (self.bracket[2 * node + 1], self.bracket[2 * node + 2]);
+        require!(
+            left != Pubkey::default() && right != Pubkey::default(),
+            ArenaError::BracketNodeNotReady
+        );
+        Ok((left, right))
+    }
+
+    /// Pairs the next Swiss or round-robin round. A bye counts as reported
+    /// right away and scores a win in Swiss, nothing in round-robin.
+    pub fn pair_round(&mut self) {
+        self.round += 1;
+        self.pairings = [[TOURNAMENT_NO_ENTRANT; 2]; TOURNAMENT_MAX_PAIRINGS];
+        self.pairing_count = 0;
+        self.reported_mask = 0;
+        let pairs = match self.format {
+            TournamentFormat::RoundRobin => self.round_robin_pairs(),
+            _ => self.swiss_pairs(),
+        };
+        for [a, b] in pairs {
+            let i = self.pairing_count as usize;
+            self.pairings[i] = [a, b];
+            self.pairing_count += 1;
+            if b == TOURNAMENT_NO_ENTRANT {
+                self.reported_mask |= 1 << i;
+                if self.format == TournamentFormat::Swiss {
+                    self.points[a as usize] += 2;
+                    self.byes |= 1 << a;
+                }
+            }
+        }
+    }
+
+    /// Circle method: entrant 0 keeps its seat and the others rotate one
+    /// seat per round. With an odd count the extra seat is a bye.
+    fn round_robin_pairs(&self) -> Vec<[u8; 2]> {
+        let n = self.entrant_count as usize;
+        let seats = n + n % 2;
+        let r = self.round as usize - 1;
+        let order: Vec<usize> = (0..seats)
+            .map(|i| if i == 0 { 0 } else { 1 + (i - 1 + r) % (seats - 1) })
+            .collect();
+        (0..seats / 2)
+            .map(|i| {
+                let (a, b) = (order[i], order[seats - 1 - i]);
+                match (a < n, b < n) {
+                    (true, true) => [a as u8, b as u8],
+                    (true, false) => [a as u8, TOURNAMENT_NO_ENTRANT],
+                    _ => [b as u8, TOURNAMENT_NO_ENTRANT],
+                }
+            })
+            .collect()
+    }
+
+    /// Walks the standings (points, then entrant order). In an odd round the
+    /// lowest-ranked player without a bye sits out. Every other player meets
+    /// the next one down they haven't played yet, or simply the next one
+    /// down if all remaining candidates would be rematches.
+    fn swiss_pairs(&self) -> Vec<[u8; 2]> {
+        let n = self.entrant_count as usize;
+        let mut order: Vec<usize> = (0..n).collect();
+        order.sort_by(|a, b| self.points[*b].cmp(&self.points[*a]));
+        let mut pairs = Vec::with_capacity(TOURNAMENT_MAX_PAIRINGS);
+        let mut paired: u32 = 0;
+        if n % 2 == 1 {
+            let bye = order.iter().rev().copied().find(|&i| self.byes & (1 << i) == 0).unwrap_or(order[n - 1]);
+            paired |= 1 << bye;
+            pairs.push([bye as u8, TOURNAMENT_NO_ENTRANT]);
+        }
+        for (k, &a) in order.iter().enumerate() {
+            if paired & (1 << a) != 0 {
+                continue;
+            }
+            let mut open = order[k + 1..].iter().copied().filter(|&b| paired & (1 << b) == 0);
+            let first = open.clone().next();
+            if let Some(b) = open.find(|&b| self.played[a] & (1 << b) == 0).or(first) {
+                paired |= (1 << a) | (1 << b);
+                pairs.push([a as u8, b as u8]);
+            }
+        }
+        pairs
+    }
+
+    /// Scores a Swiss / round-robin pairing: win 2, draw 1.
+    pub fn record_result(&mut self, node: usize, winner: Pubkey) {
+        let [a, b] = self.pairings[node].map(|i| i as usize);
+        if winner == Pubkey::default() {
+            self.points[a] += 1;
+            self.points[b] += 1;
+        } else if winner == self.entrants[a] {
+            self.points[a] += 2;
+        } else {
+            self.points[b] += 2;
+        }
+        self.played[a] |= 1 << b;
+        self.played[b] |= 1 << a;
+        self.reported_mask |= 1 << node;
+    }
+
+    pub fn round_complete(&self) -> bool {
+        self.reported_mask.count_ones() == self.pairing_count as u32
+    }
+
+    /// Fills in Buchholz (the summed points of everyone an entrant played)
+    /// and returns entrant indices ordered by points, then Buchholz, then
+    /// entrant order.
+    pub fn final_standings(&mut self) -> Vec<usize> {
+        let n = self.entrant_count as usize;
+        for i in 0..n {
+            self.buchholz[i] = (0..n)
+                .filter(|&j| self.played[i] & (1 << j) != 0)
+                .map(|j| self.points[j] as u16)
+                .sum();
+        }
+        let mut order: Vec<usize> = (0..n).collect();
+        order.sort_by(|a, b| (self.points[*b], self.buchholz[*b]).cmp(&(self.points[*a], self.buchholz[*a])));
+        order
+    }
+}
+
+#[account]
+pub struct DerbyRaceState {
+    pub race_id: u64,           // 8
+    pub game_server: Pubkey,    // 32
+    pub player: Pubkey,         // 32
+    pub vrf_seed: [u8; 32],     // 32
+    pub status: DerbyStatus,    // 1
+    pub current_tick: u32,      // 4
+    pub current_lap: u8,        // 1
+    pub checkpoints_passed: u8, // 1 — bitmask per lap (4 bits used)
+    pub collisions: u16,        // 2
+    pub gold_collected: u8,     // 1
+    pub boosts_collected: u8,   // 1
+    pub boost_end_tick: u32,    // 4
+    pub finish_tick: u32,       // 4
+    pub gold_bitmask: u16,      // 2 — which gold coins collected (15 bits)
+    pub boost_bitmask: u8,      // 1 — which boosts collected (8 bits)
+    pub created_at: i64,        // 8
+    pub settled_at: i64,        // 8
+    pub rent_payer: Pubkey,     // 32 — refunded when the PDA is closed
+    pub close_fee: u64,         // 8 — lamports kept by the game server on close
+    pub strict: bool,           // 1 — settlement requires Finished / Cancelled
+    pub arbiter: Pubkey,        // 32 — resolves disputes (default = disputes disabled)
+    pub dispute_window_secs: u32, // 4 — challenge period after settled_at
+    pub dispute_status: DisputeStatus, // 1
+    pub token_mint: Pubkey,     // 32 — SPL wager mint (default = no token wager)
+    pub token_stake: u64,       // 8 — player entry, matched by the server as prize
+    pub player_tokens_deposited: bool, // 1
+    pub server_tokens_deposited: bool, // 1
+    pub tokens_paid_out: bool,  // 1 — token vault released
+    pub rewarded: bool,         // 1 — gold reward paid
+    pub reward_amount: u64,     // 8 — reward tokens paid for gold
+    pub season_recorded: bool,  // 1 — counted in the current season
+    pub event: Pubkey,          // 32 — DerbyEvent entered (default = none)
+    pub event_recorded: bool,   // 1 — ranked in the event
+    pub max_racers: u8,         // 1 — 1 = single racer, progress kept on this account
+    pub racer_count: u8,        // 1
+    pub racers: [Pubkey; DERBY_MAX_RACERS], // 256 — in join order
+    pub finished_count: u8,     // 1 — placements handed out so far
+    pub ghost_race_id: u64,     // 8 — race whose ghost is raced (ghost mode)
+    pub ghost_finish_tick: u32, // 4 — ghost's finish tick at creation, 0 = no ghost
+    pub ghost_margin: i32,      // 4 — ghost finish minus own finish, > 0 = ghost beaten
+    pub seed_source: SeedSource, // 1 — how vrf_seed was picked
+    pub seed_commitment: [u8; 32], // 32 — sha256(server_secret), commit-reveal only
+    pub player_seed: [u8; 32],  // 32 — player's contribution, commit-reveal only
+    pub server_secret: [u8; 32], // 32 — revealed preimage of seed_commitment
+    pub seed_contributed: bool, // 1
+    pub seed_fulfilled: bool,   // 1 — vrf_seed final, start_derby allowed
+    pub layout: TrackLayout,    // 36 — derived from vrf_seed at start_derby
+    pub obstacle_hits: [u16; DERBY_MAX_LAPS as usize], // 6 — obstacles hit in each lap (10 bits)
+    pub obstacle_hit_ticks: [u32; DERBY_MAX_OBSTACLES as usize], // 40 — last hit tick per obstacle
+    pub boosted_ticks: u32,     // 4 — ticks raced while boosted
+    pub boost_accrued_tick: u32, // 4 — race tick boosted_ticks is counted up to
+    pub last_split_tick: u32,   // 4 — latest checkpoint pass or lap start, for the gap check
+    pub lap_ticks: [u32; DERBY_MAX_LAPS as usize], // 12 — tick each lap was completed
+    pub checkpoint_ticks: [u32; DERBY_SPLIT_COUNT], // 48 — tick each checkpoint was first passed, lap-major
+    pub track_origin: TrackOrigin, // 1 — whose track the race replays
+}
+
+impl DerbyRaceState {
+    pub const LEN: usize = 8 + 32 + 32 + 32 + 1 + 4 + 1 + 1 + 2 + 1 + 1 + 4 + 4 + 2 + 1 + 8 + 8 + 32 + 8 + 1 + 32 + 4 + 1
+        + 32 + 8 + 1 + 1 + 1 + 1 + 8 + 1 + 32 + 1 + 1 + 1 + 32 * DERBY_MAX_RACERS + 1
+        + 8 + 4 + 4
+        + 1 + 32 + 32 + 32 + 1 + 1 + TrackLayout::LEN + 2 * DERBY_MAX_LAPS as usize + 4 * DERBY_MAX_OBSTACLES as usize
+        + 4 + 4 + 4 + 4 * DERBY_MAX_LAPS as usize + 4 * DERBY_SPLIT_COUNT
+        + 1;
+
+    /// Commit-reveal seeds are unique to one race, so there's no track to
+    /// share a leaderboard with.
+    pub fn has_shared_track(&self) -> bool {
+        self.seed_source != SeedSource::CommitReveal
+    }
+
+    /// Racers keep their progress in derby_player PDAs.
+    pub fn is_multiplayer(&self) -> bool {
+        self.max_racers > 1
+    }
+
+    pub fn is_racer(&self, player: &Pubkey) -> bool {
+        self.racers[..self.racer_count as usize].contains(player)
+    }
+
+    pub fn racer_pda(&self, player: &Pubkey) -> Pubkey {
+        Pubkey::find_program_address(
+            &[DERBY_PLAYER_SEED, &self.race_id.to_le_bytes(), player.as_ref()],
+            &crate::ID,
+        )
+        .0
+    }
+
+    /// Loads every racer's derby_player PDA from `accounts`, in join order.
+    /// Empty for single-racer races.
+    pub fn load_racers<'info>(
+        &self,
+        accounts: &'info [AccountInfo<'info>],
+    ) -> Result<Vec<Account<'info, DerbyPlayerState>>> {
+        if !self.is_multiplayer() {
+            return Ok(Vec::new());
+        }
+        self.racers[..self.racer_count as usize]
+            .iter()
+            .map(|player| {
+                let expected = self.racer_pda(player);
+                let info = accounts
+                    .iter()
+                    .find(|acc| acc.key() == expected)
+                    .ok_or(DerbyError::MissingDerbyPlayer)?;
+                Account::try_from(info)
+            })
+            .collect()
+    }
+
+    /// The single racer's progress, as stored inline on the race.
+    pub fn progress(&self) -> DerbyProgress {
+        DerbyProgress {
+            current_lap: self.current_lap,
+            checkpoints_passed: self.checkpoints_passed,
+            collisions: self.collisions,
+            gold_collected: self.gold_collected,
+            boosts_collected: self.boosts_collected,
+            boost_end_tick: self.boost_end_tick,
+            finish_tick: self.finish_tick,
+            gold_bitmask: self.gold_bitmask,
+            boost_bitmask: self.boost_bitmask,
+            obstacle_hits: self.obstacle_hits,
+            obstacle_hit_ticks: self.obstacle_hit_ticks,
+            boosted_ticks: self.boosted_ticks,
+            boost_accrued_tick: self.boost_accrued_tick,
+            last_split_tick: self.last_split_tick,
+            lap_ticks: self.lap_ticks,
+            checkpoint_ticks: self.checkpoint_ticks,
+        }
+    }
+
+    pub fn set_progress(&mut self, p: &DerbyProgress) {
+        self.current_lap = p.current_lap;
+        self.checkpoints_passed = p.checkpoints_passed;
+        self.collisions = p.collisions;
+        self.gold_collected = p.gold_collected;
+        self.boosts_collected = p.boosts_collected;
+        self.boost_end_tick = p.boost_end_tick;
+        self.finish_tick = p.finish_tick;
+        self.gold_bitmask = p.gold_bitmask;
+        self.boost_bitmask = p.boost_bitmask;
+        self.obstacle_hits = p.obstacle_hits;
+        self.obstacle_hit_ticks = p.obstacle_hit_ticks;
+        self.boosted_ticks = p.boosted_ticks;
+        self.boost_accrued_tick = p.boost_accrued_tick;
+        self.last_split_tick = p.last_split_tick;
+        self.lap_ticks = p.lap_ticks;
+        self.checkpoint_ticks = p.checkpoint_ticks;
+    }
+
+    pub fn track_record(&self) -> TrackRecord {
+        TrackRecord {
+            player: self.player,
+            race_id: self.race_id,
+            finish_tick: self.finish_tick,
+            collisions: self.collisions,
+            gold_collected: self.gold_collected,
+        }
+    }
+
+    /// Same rule as `ArenaMatchState::tokens_settled`.
+    pub fn tokens_settled(&self) -> bool {
+        self.tokens_paid_out || !(self.player_tokens_deposited || self.server_tokens_deposited)
+    }
+
+    pub fn require_settleable(&self) -> Result<()> {
+        if self.strict {
+            require!(
+                self.status == DerbyStatus::Finished || self.status == DerbyStatus::Cancelled,
+                DerbyError::RaceNotFinished
+            );
+        }
+        Ok(())
+    }
+
+    /// Same rules as `ArenaMatchState::require_closable`.
+    pub fn require_closable(&self, now: i64) -> Result<()> {
+        self.require_settleable()?;
+        require!(self.dispute_status != DisputeStatus::Raised, DerbyError::DisputePending);
+        if self.settled_at != 0 && self.dispute_status == DisputeStatus::None {
+            let deadline = self.settled_at.saturating_add(self.dispute_window_secs as i64);
+            require!(now >= deadline, DerbyError::DisputeWindowOpen);
+        }
+        Ok(())
+    }
+
+    pub fn raise_dispute(&mut self, now: i64) -> Result<()> {
+        require!(self.arbiter != Pubkey::default(), DerbyError::DisputesDisabled);
+        require!(self.status == DerbyStatus::Finished, DerbyError::RaceNotFinished);
+        require!(self.dispute_status == DisputeStatus::None, DerbyError::InvalidDisputeState);
+        let deadline = self.settled_at.saturating_add(self.dispute_window_secs as i64);
+        require!(now < deadline, DerbyError::DisputeWindowClosed);
+        self.dispute_status = DisputeStatus::Raised;
+        Ok(())
+    }
+
+    /// A derby has no winner to override — only confirm or void.
+    pub fn resolve_dispute(&mut self, resolution: DisputeResolution) -> Result<()> {
+        require!(self.dispute_status == DisputeStatus::Raised, DerbyError::InvalidDisputeState);
+        match resolution {
+            DisputeResolution::Confirm => {
+                msg!("Derby {}: dispute resolved, result confirmed", self.race_id);
+            }
+            DisputeResolution::OverrideWinner { .. } => {
+                return Err(DerbyError::InvalidDisputeResolution.into());
+            }
+            DisputeResolution::Void => {
+                self.status = DerbyStatus::Cancelled;
+                msg!("Derby {}: dispute resolved, result voided", self.race_id);
+            }
+        }
+        self.dispute_status = DisputeStatus::Resolved;
+        Ok(())
+    }
+}
+
+/// One racer of a multiplayer derby. Created by join_derby and closed with
+/// the race.
+#[account]
+pub struct DerbyPlayerState {
+    pub race_id: u64,            // 8
+    pub player: Pubkey,          // 32
+    pub progress: DerbyProgress, // 135
+    pub placement: u8,           // 1 — finishing position, 0 = not finished
+    pub rent_payer: Pubkey,      // 32 — refunded when the PDA is closed
+}
+
+impl DerbyPlayerState {
+    pub const LEN: usize = 8 + 32 + DerbyProgress::LEN + 1 + 32;
+
+    pub fn track_record(&self) -> TrackRecord {
+        TrackRecord {
+            player: self.player,
+            race_id: self.race_id,
+            finish_tick: self.progress.finish_tick,
+            collisions: self.progress.collisions,
+            gold_collected: self.progress.gold_collected,
+        }
+    }
+}
+
+/// Best Finished runs on one track. A track is identified by its `vrf_seed`,
+/// which fixes the layout, so only runs on the same layout are compared.
+/// Commit-reveal races never get one — their seed is unique to the race.
+#[account]
+pub struct TrackLeaderboard {
+    pub vrf_seed: [u8; 32],                             // 32
+    pub rent_payer: Pubkey,                             // 32 — funded the board, may close it
+    pub records: [TrackRecord; TRACK_LEADERBOARD_SIZE], // 10 × 47 — best first
+}
+
+impl TrackLeaderboard {
+    pub const LEN: usize = 32 + 32 + TRACK_LEADERBOARD_SIZE * TrackRecord::LEN;
+
+    /// Inserts the run in rank order and returns its 0-based rank, or None
+    /// if it didn't make the board. Ties keep the earlier run ahead.
+    pub fn record(&mut self, record: TrackRecord) -> Option<usize> {
+        insert_ranked(&mut self.records, record)
+    }
+}
+
+/// Split timeline of the fastest Finished run on one track, raced against
+/// in ghost mode. Replaced by close_derby whenever a run finishes in fewer
+/// ticks; ties keep the standing ghost. Like TrackLeaderboard, only kept
+/// for shared tracks.
+#[account]
+pub struct TrackGhost {
+    pub vrf_seed: [u8; 32],                         // 32
+    pub rent_payer: Pubkey,                         // 32 — funded the ghost, may close it
+    pub player: Pubkey,                             // 32 — default = no run yet
+    pub race_id: u64,                               // 8
+    pub finish_tick: u32,                           // 4
+    pub recorded_at: i64,                           // 8
+    pub lap_ticks: [u32; DERBY_MAX_LAPS as usize],  // 12
+    pub checkpoint_ticks: [u32; DERBY_SPLIT_COUNT], // 48 — lap-major
+}
+
+impl TrackGhost {
+    pub const LEN: usize = 32 + 32 + 32 + 8 + 4 + 8 + 4 * DERBY_MAX_LAPS as usize + 4 * DERBY_SPLIT_COUNT;
+
+    pub fn beaten_by(&self, finish_tick: u32) -> bool {
+        self.player == Pubkey::default() || finish_tick < self.finish_tick
+    }
+
+    pub fn replace(&mut self, player: Pubkey, race_id: u64, progress: &DerbyProgress, now: i64) {
+        self.player = player;
+        self.race_id = race_id;
+        self.finish_tick = progress.finish_tick;
+        self.recorded_at = now;
+        self.lap_ticks = progress.lap_ticks;
+        self.checkpoint_ticks = progress.checkpoint_ticks;
+    }
+}
+
+/// A time-trial window on one track. Races created against the event share
+/// its `vrf_seed`; each player's best Finished run is ranked, and the entry
+/// fees are paid to the podium once the window has closed.
+#[account]
+pub struct DerbyEvent {
+    pub event_id: u64,                                            // 8
+    pub game_server: Pubkey,                                      // 32 — creates the races, finalizes
+    pub vrf_seed: [u8; 32],                                       // 32 — track every race runs
+    pub start_ts: i64,                                            // 8
+    pub end_ts: i64,                                              // 8 — races created before, ranked if settled before
+    pub entry_fee: u64,                                           // 8 — lamports per race, 0 = free
+    pub prize_split_bps: [u16; DERBY_EVENT_PRIZE_PLACES],         // 6 — 1st / 2nd / 3rd, sums to 10000
+    pub entrant_count: u32,                                       // 4 — races created (entry fees paid)
+    pub leaderboard: [TrackRecord; DERBY_EVENT_LEADERBOARD_SIZE], // 10 × 47 — best run per player
+    pub finalized: bool,                                          // 1 — prize pool paid out
+    pub dispute_window_secs: u32,                                 // 4 — longest dispute window of an entered race
+}
+
+impl DerbyEvent {
+    pub const LEN: usize =
+        8 + 32 + 32 + 8 + 8 + 8 + 2 * DERBY_EVENT_PRIZE_PLACES + 4 + DERBY_EVENT_LEADERBOARD_SIZE * TrackRecord::LEN + 1 + 4;
+
+    /// A race settled just before end_ts can't be recorded until its
+    /// dispute window has run, so the podium isn't final until then.
+    pub fn finalizable_at(&self) -> i64 {
+        self.end_ts.saturating_add(self.dispute_window_secs as i64)
+    }
+
+    pub fn is_open(&self, now: i64) -> bool {
+        !self.finalized && now >= self.start_ts && now < self.end_ts
+    }
+
+    /// Ranks the run if it is the player's best so far, replacing their
+    /// previous entry. Returns the 0-based rank, or None if it didn't place.
+    pub fn record(&mut self, record: TrackRecord) -> Option<usize> {
+        let filled = self.leaderboard.iter().take_while(|r| r.player != Pubkey::default()).count();
+        if let Some(i) = self.leaderboard[..filled].iter().position(|r| r.player == record.player) {
+            if !record.ranks_before(&self.leaderboard[i]) {
+                return None;
+            }
+            self.leaderboard.copy_within(i + 1..filled, i);
+            self.leaderboard[filled - 1] = TrackRecord::default();
+        }
+        insert_ranked(&mut self.leaderboard, record)
+    }
+
+    /// The ranked entries that take a prize place.
+    pub fn podium(&self) -> &[TrackRecord] {
+        let filled = self.leaderboard.iter().take_while(|r| r.player != Pubkey::default()).count();
+        &self.leaderboard[..filled.min(DERBY_EVENT_PRIZE_PLACES)]
+    }
+}
+
+// ═══════════════════════════════════════════════════════════════════════════
+// EVENTS
+// ═══════════════════════════════════════════════════════════════════════════
+
+#[event]
+pub struct MatchPaidOut {
+    pub match_id: u64,
+    pub winner: Pubkey, // default = draw / refund
+    pub pot: u64,
+    pub fee: u64, // protocol fee sent to the treasury
+    pub player1_amount: u64,
+    pub player2_amount: u64,
+}
+
+#[event]
+pub struct MatchTokensPaidOut {
+    pub match_id: u64,
+    pub mint: Pubkey,
+    pub winner: Pubkey, // default = draw / refund
+    pub pot: u64,
+    pub fee: u64, // protocol fee sent to the treasury
+    pub player1_amount: u64,
+    pub player2_amount: u64,
+}
+
+#[event]
+pub struct AchievementsUnlocked {
+    pub player: Pubkey,
+    pub unlocked: u32,     // ACHIEVEMENT_* bits earned for the first time
+    pub achievements: u32, // all bits the player now holds
+}
+
+#[event]
+pub struct TournamentMatchReported {
+    pub tournament_id: u64,
+    pub node: u8,
+    pub match_id: u64,
+    pub winner: Pubkey,
+}
+
+#[event]
+pub struct TournamentCompleted {
+    pub tournament_id: u64,
+    pub champion: Pubkey,
+    pub runner_up: Pubkey,
+}
+
+#[event]
+pub struct TournamentPaidOut {
+    pub tournament_id: u64,
+    pub pot: u64,
+    pub fee: u64, // protocol fee sent to the treasury
+    pub champion_amount: u64,
+    pub runner_up_amount: u64,
+}
+
+#[event]
+pub struct RatingsUpdated {
+    pub match_id: u64,
+    pub player1: Pubkey,
+    pub player2: Pubkey,
+    pub player1_rating: u16,
+    pub player2_rating: u16,
+    pub delta: i32, // player 1's change, player 2 got the opposite
+}
+
+#[event]
+pub struct DerbyRewarded {
+    pub race_id: u64,
+    pub player: Pubkey,
+    pub mint: Pubkey,
+    pub gold_collected: u8,
+    pub amount: u64,
+}
+
+#[event]
+pub struct DerbySeedFulfilled {
+    pub race_id: u64,
+    pub vrf_seed: [u8; 32],
+    pub seed_commitment: [u8; 32],
+    pub player_seed: [u8; 32],
+    pub server_secret: [u8; 32],
+}
+
+#[event]
+pub struct GhostRaceSettled {
+    pub race_id: u64,
+    pub player: Pubkey,
+    pub ghost_race_id: u64,
+    pub ghost_finish_tick: u32,
+    pub finish_tick: u32,
+    pub margin: i32, // ghost finish minus own finish in ticks
+    pub beaten: bool,
+}
+
+#[event]
+pub struct DerbyRacerFinished {
+    pub race_id: u64,
+    pub player: Pubkey,
+    pub placement: u8, // 1 = winner
+    pub finish_tick: u32,
+}
+
+#[event]
+pub struct DerbyEventFinalized {
+    pub event_id: u64,
+    pub entrant_count: u32,
+    pub pot: u64,
+    pub fee: u64, // protocol fee sent to the treasury
+    pub winners: [Pubkey; DERBY_EVENT_PRIZE_PLACES], // default = place not filled
+    pub amounts: [u64; DERBY_EVENT_PRIZE_PLACES],
+}
+
+#[event]
+pub struct DerbyTokensPaidOut {
+    pub race_id: u64,
+    pub mint: Pubkey,
+    pub status: DerbyStatus,
+    pub pot: u64,
+    pub fee: u64, // protocol fee sent to the treasury
+    pub player_amount: u64,
+    pub server_amount: u64,
+}
+
+// ═══════════════════════════════════════════════════════════════════════════
+// ENUMS
+// ═══════════════════════════════════════════════════════════════════════════
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TournamentFormat {
+    SingleElimination, // 0
+    Swiss,             // 1
+    RoundRobin,        // 2
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TournamentStatus {
+    Registration, // 0
+    Running,      // 1
+    Complete,     // 2
+    Cancelled,    // 3
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MatchStatus {
+    WaitingForPlayer, // 0
+    Countdown,        // 1
+    Active,           // 2
+    RoundEnd,         // 3
+    Complete,         // 4
+    Cancelled,        // 5
+}
+
+impl Default for MatchStatus {
+    fn default() -> Self {
+        MatchStatus::WaitingForPlayer
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ServerEvent {
+    AdvanceTick { tick: u32 },
+    StartRound,
+    ApplyDamage { target_slot: u8 }, // 1 = player1, 2 = player2
+    EndRound,
+    Forfeit { forfeiter_slot: u8 },
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SeedSource {
+    Server,       // 0 — fixed seed picked by the game server
+    CommitReveal, // 1 — sha256(server_secret ‖ player_seed ‖ race_id)
+}
+
+impl Default for SeedSource {
+    fn default() -> Self {
+        SeedSource::Server
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TrackOrigin {
+    Own,   // 0 — the race's own seed
+    Event, // 1 — the DerbyEvent's track
+    Ghost, // 2 — the TrackGhost's track
+}
+
+impl Default for TrackOrigin {
+    fn default() -> Self {
+        TrackOrigin::Own
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DerbyStatus {
+    Created,   // 0 — on L1, awaiting delegation
+    Racing,    // 1 — active on ER
+    Finished,  // 2 — race complete
+    Cancelled, // 3
+}
+
+impl Default for DerbyStatus {
+    fn default() -> Self {
+        DerbyStatus::Created
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DisputeStatus {
+    None,     // 0 — no dispute raised
+    Raised,   // 1 — blocks closing until the arbiter resolves it
+    Resolved, // 2
+}
+
+impl Default for DisputeStatus {
+    fn default() -> Self {
+        DisputeStatus::None
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DisputeResolution {
+    Confirm,
+    OverrideWinner { winner: Pubkey }, // arena only; default pubkey = draw
+    Void,                              // status -> Cancelled
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DerbyAction {
+    RecordCollision { obstacle_index: u8 },
+    CollectGold { item_index: u8 },
+    CollectBoost { item_index: u8 },
+    PassCheckpoint { checkpoint_id: u8 },
+    CompleteLap,
+    FinishRace { tick: u32 },
+}
+
+/// Settlement terms agreed at creation — the game server and the paying
+/// player both sign the create transaction.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct SettlementParams {
+    pub close_fee: u64,           // lamports of the rent deposit kept by the game server on close
+    pub strict: bool,             // ranked / wagered: settle only from a final status
+    pub arbiter: Pubkey,          // resolves disputes; required when dispute_window_secs > 0
+    pub dispute_window_secs: u32, // challenge period after settlement (0 = none)
+}
+
+impl SettlementParams {
+    pub fn dispute_params_valid(&self) -> bool {
+        self.dispute_window_secs == 0
+            || (self.arbiter != Pubkey::default() && self.dispute_window_secs <= MAX_DISPUTE_WINDOW_SECS)
+    }
+}
+
+/// Optional SPL token wager fixed at creation — each participant deposits
+/// `amount` of `mint` into the token vault PDA.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct TokenWager {
+    pub mint: Pubkey,
+    pub amount: u64,
+}
+
+/// How a derby's track seed is chosen at creation.
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
-pub enum DerbyAction {
-    RecordCollision,
-    CollectGold { item_index: u8 },
-    CollectBoost { item_index: u8 },
-    PassCheckpoint { checkpoint_id: u8 },
-    CompleteLap,
-    FinishRace { tick: u32 },
+pub enum SeedRequest {
+    /// Seed given by the game server — required for events and ghost races,
+    /// which replay a known track. Not allowed with a token wager.
+    Fixed { vrf_seed: [u8; 32] },
+    /// sha256 of a server secret; the seed is derived once the player has
+    /// contributed and the server has revealed the secret.
+    CommitReveal { commitment: [u8; 32] },
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct SeasonArenaEntry {
+    pub player: Pubkey, // default = empty slot
+    pub wins: u32,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct TrackRecord {
+    pub player: Pubkey, // default = empty slot
+    pub race_id: u64,
+    pub finish_tick: u32,
+    pub collisions: u16,
+    pub gold_collected: u8,
+}
+
+impl TrackRecord {
+    pub const LEN: usize = 32 + 8 + 4 + 2 + 1;
+
+    /// Faster finish wins; ties go to fewer collisions, then more gold.
+    pub fn ranks_before(&self, other: &TrackRecord) -> bool {
+        (self.finish_tick, self.collisions, u8::MAX - self.gold_collected)
+            < (other.finish_tick, other.collisions, u8::MAX - other.gold_collected)
+    }
+}
+
+/// Item placement derived from a race's vrf_seed. Segment `s` is the stretch
+/// of track leading up to checkpoint `s`, so an item there can only be
+/// picked up once exactly `s` checkpoints of the current lap are passed.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct TrackLayout {
+    pub obstacle_count: u8,
+    pub gold_count: u8,
+    pub boost_count: u8,
+    pub obstacle_segments: [u8; DERBY_MAX_OBSTACLES as usize],
+    pub gold_segments: [u8; DERBY_MAX_GOLD as usize],
+    pub boost_segments: [u8; DERBY_MAX_BOOSTS as usize],
+}
+
+impl TrackLayout {
+    pub const LEN: usize = 3 + DERBY_MAX_OBSTACLES as usize + DERBY_MAX_GOLD as usize + DERBY_MAX_BOOSTS as usize;
+
+    /// h = sha256(DERBY_LAYOUT_DOMAIN ‖ vrf_seed). Bytes 0–2 pick the
+    /// obstacle, gold and boost counts; from byte 3 on, every 2 bits place
+    /// one item slot in a segment (obstacles, then gold, then boosts).
+    /// Slots past a count stay in segment 0 and are never used.
+    pub fn derive(vrf_seed: &[u8; 32]) -> Self {
+        let h = solana_sha256_hasher::hashv(&[DERBY_LAYOUT_DOMAIN, vrf_seed]).to_bytes();
+        let segment = |slot: usize| (h[3 + slot / 4] >> (2 * (slot % 4))) & 0b11;
+        let mut layout = TrackLayout {
+            obstacle_count: DERBY_MIN_OBSTACLES + h[0] % (DERBY_MAX_OBSTACLES - DERBY_MIN_OBSTACLES + 1),
+            gold_count: DERBY_MIN_GOLD + h[1] % (DERBY_MAX_GOLD - DERBY_MIN_GOLD + 1),
+            boost_count: DERBY_MIN_BOOSTS + h[2] % (DERBY_MAX_BOOSTS - DERBY_MIN_BOOSTS + 1),
+            ..Default::default()
+        };
+        let gold_base = DERBY_MAX_OBSTACLES as usize;
+        let boost_base = gold_base + DERBY_MAX_GOLD as usize;
+        for i in 0..layout.obstacle_count as usize {
+            layout.obstacle_segments[i] = segment(i);
+        }
+        for i in 0..layout.gold_count as usize {
+            layout.gold_segments[i] = segment(gold_base + i);
+        }
+        for i in 0..layout.boost_count as usize {
+            layout.boost_segments[i] = segment(boost_base + i);
+        }
+        layout
+    }
+
+    /// Bitmask with one bit per gold coin on this track.
+    pub fn all_gold_mask(&self) -> u16 {
+        ((1u32 << self.gold_count) - 1) as u16
+    }
+}
+
+/// One racer's lap, checkpoint and pickup state. Single-racer races store
+/// these fields inline on DerbyRaceState; multiplayer racers in their
+/// DerbyPlayerState.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct DerbyProgress {
+    pub current_lap: u8,
+    pub checkpoints_passed: u8, // bitmask per lap (4 bits used)
+    pub collisions: u16,
+    pub gold_collected: u8,
+    pub boosts_collected: u8,
+    pub boost_end_tick: u32,
+    pub finish_tick: u32,
+    pub gold_bitmask: u16, // which gold coins collected (15 bits)
+    pub boost_bitmask: u8, // which boosts collected (8 bits)
+    pub obstacle_hits: [u16; DERBY_MAX_LAPS as usize], // obstacles hit in each lap (10 bits)
+    pub obstacle_hit_ticks: [u32; DERBY_MAX_OBSTACLES as usize], // last hit tick per obstacle, for the debounce
+    pub boosted_ticks: u32,      // ticks raced while boosted
+    pub boost_accrued_tick: u32, // race tick boosted_ticks is counted up to
+    pub last_split_tick: u32,    // latest checkpoint pass or lap start (0 on lap 1)
+    pub lap_ticks: [u32; DERBY_MAX_LAPS as usize], // tick each lap was completed
+    pub checkpoint_ticks: [u32; DERBY_SPLIT_COUNT], // tick each checkpoint was first passed, lap-major
+}
+
+impl DerbyProgress {
+    pub const LEN: usize = 1 + 1 + 2 + 1 + 1 + 4 + 4 + 2 + 1 + 2 * DERBY_MAX_LAPS as usize + 4 * DERBY_MAX_OBSTACLES as usize
+        + 4 + 4 + 4 + 4 * DERBY_MAX_LAPS as usize + 4 * DERBY_SPLIT_COUNT;
+
+    /// Adds the boosted ticks between the last accounted tick and
+    /// `current_tick` to `boosted_ticks`.
+    fn accrue_boost(&mut self, current_tick: u32) {
+        let upto = current_tick.min(self.boost_end_tick);
+        if upto > self.boost_accrued_tick {
+            self.boosted_ticks = self.boosted_ticks.saturating_add(upto - self.boost_accrued_tick);
+        }
+        self.boost_accrued_tick = self.boost_accrued_tick.max(current_tick);
+    }
+
+    /// Track segment the racer is on: checkpoints passed this lap.
+    pub fn segment(&self) -> u8 {
+        self.checkpoints_passed.count_ones() as u8
+    }
+
+    /// Applies one server-validated action. Returns true when it finished
+    /// the race for this racer. Pickups must exist on `layout` and sit in
+    /// the racer's current segment.
+    pub fn apply(&mut self, race_id: u64, current_tick: u32, layout: &TrackLayout, action: DerbyAction) -> Result<bool> {
+        self.accrue_boost(current_tick);
+        match action {
+            DerbyAction::RecordCollision { obstacle_index } => {
+                require!(obstacle_index < layout.obstacle_count, DerbyError::InvalidObstacleIndex);
+                require!(
+                    layout.obstacle_segments[obstacle_index as usize] == self.segment(),
+                    DerbyError::ItemNotReachable
+                );
+                let bit = 1u16 << obstacle_index;
+                let hit_before = self.obstacle_hits.iter().any(|hits| hits & bit != 0);
+                let last_hit = self.obstacle_hit_ticks[obstacle_index as usize];
+                require!(
+                    !hit_before || current_tick >= last_hit + DERBY_COLLISION_DEBOUNCE_TICKS,
+                    DerbyError::CollisionDebounced
+                );
+                let lap = self.current_lap.min(DERBY_MAX_LAPS - 1) as usize;
+                self.obstacle_hits[lap] |= bit;
+                self.obstacle_hit_ticks[obstacle_index as usize] = current_tick;
+                self.collisions = self.collisions.saturating_add(1);
+                msg!("Derby {}: collision #{} (obstacle {})", race_id, self.collisions, obstacle_index);
+            }
+            DerbyAction::CollectGold { item_index } => {
+                require!(item_index < layout.gold_count, DerbyError::InvalidItemIndex);
+                require!(
+                    layout.gold_segments[item_index as usize] == self.segment(),
+                    DerbyError::ItemNotReachable
+                );
+                let bit = 1u16 << item_index;
+                require!(self.gold_bitmask & bit == 0, DerbyError::ItemAlreadyCollected);
+                self.gold_bitmask |= bit;
+                self.gold_collected = self.gold_collected.saturating_add(1);
+                msg!("Derby {}: gold #{} collected", race_id, item_index);
+            }
+            DerbyAction::CollectBoost { item_index } => {
+                require!(item_index < layout.boost_count, DerbyError::InvalidItemIndex);
+                require!(
+                    layout.boost_segments[item_index as usize] == self.segment(),
+                    DerbyError::ItemNotReachable
+                );
+                let bit = 1u8 << item_index;
+                require!(self.boost_bitmask & bit == 0, DerbyError::ItemAlreadyCollected);
+                self.boost_bitmask |= bit;
+                self.boosts_collected = self.boosts_collected.saturating_add(1);
+                // Stacking extends: a boost picked up while one is active adds
+                // its full duration to the current end tick.
+                self.boost_end_tick = self.boost_end_tick.max(current_tick).saturating_add(DERBY_BOOST_DURATION_TICKS);
+                msg!("Derby {}: boost #{} collected, active until tick {}", race_id, item_index, self.boost_end_tick);
+            }
+            DerbyAction::PassCheckpoint { checkpoint_id } => {
+                require!(checkpoint_id < DERBY_CHECKPOINT_COUNT, DerbyError::InvalidCheckpoint);
+                let bit = 1u8 << checkpoint_id;
+                if self.checkpoints_passed & bit == 0 && self.current_lap < DERBY_MAX_LAPS {
+                    let min_gap = if current_tick < self.boost_end_tick {
+                        DERBY_MIN_BOOSTED_CHECKPOINT_GAP_TICKS
+                    } else {
+                        DERBY_MIN_CHECKPOINT_GAP_TICKS
+                    };
+                    require!(
+                        current_tick >= self.last_split_tick + min_gap,
+                        DerbyError::CheckpointTooSoon
+                    );
+                    self.last_split_tick = current_tick;
+                    let split = (self.current_lap * DERBY_CHECKPOINT_COUNT + checkpoint_id) as usize;
+                    self.checkpoint_ticks[split] = current_tick;
+                }
+                self.checkpoints_passed |= bit;
+                msg!("Derby {}: checkpoint {} passed", race_id, checkpoint_id);
+            }
+            DerbyAction::CompleteLap => {
+                let all_checkpoints = (1u8 << DERBY_CHECKPOINT_COUNT) - 1;
+                require!(
+                    self.checkpoints_passed & all_checkpoints == all_checkpoints,
+                    DerbyError::MissingCheckpoints
+                );
+                if self.current_lap < DERBY_MAX_LAPS {
+                    self.lap_ticks[self.current_lap as usize] = current_tick;
+                }
+                self.current_lap = self.current_lap.saturating_add(1);
+                self.checkpoints_passed = 0; // reset for next lap
+                self.last_split_tick = current_tick;
+                msg!("Derby {}: lap {} complete", race_id, self.current_lap);
+            }
+            DerbyAction::FinishRace { tick } => {
+                require!(self.current_lap >= DERBY_MAX_LAPS, DerbyError::LapsNotComplete);
+                require!(tick <= DERBY_MAX_TICKS, DerbyError::RaceTimedOut);
+                self.finish_tick = tick;
+                msg!("Derby {}: finished at tick {}", race_id, self.finish_tick);
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct SeasonDerbyEntry {
+    pub player: Pubkey, // default = empty slot
+    pub finish_tick: u32,
+    pub race_id: u64,
+}
+
+// ═══════════════════════════════════════════════════════════════════════════
+// HELPERS
+// ═══════════════════════════════════════════════════════════════════════════
+
+/// Inserts a run into a best-first board whose empty slots trail, shifting
+/// slower runs down. Returns the 0-based rank, or None if it didn't make the
+/// board. Ties keep the earlier run ahead.
+pub fn insert_ranked(records: &mut [TrackRecord], record: TrackRecord) -> Option<usize> {
+    let filled = records.iter().take_while(|r| r.player != Pubkey::default()).count();
+    let rank = records[..filled].iter().position(|r| record.ranks_before(r)).unwrap_or(filled);
+    if rank >= records.len() {
+        return None;
+    }
+    let last = filled.min(records.len() - 1);
+    records.copy_within(rank..last, rank + 1);
+    records[rank] = record;
+    Some(rank)
+}
+
+/// Appends a run's lap splits, then its checkpoint splits, as u32 LE to a
+/// derby result hash buffer.
+fn extend_splits(data: &mut Vec<u8>, lap_ticks: &[u32], checkpoint_ticks: &[u32]) {
+    for tick in lap_ticks.iter().chain(checkpoint_ticks) {
+        data.extend_from_slice(&tick.to_le_bytes());
+    }
+}
+
+/// Emits AchievementsUnlocked when a settled game earned something new.
+fn emit_achievements(profile: &PlayerProfile, unlocked: u32) {
+    if unlocked == 0 {
+        return;
+    }
+    emit!(AchievementsUnlocked {
+        player: profile.wallet,
+        unlocked,
+        achievements: profile.achievements,
+    });
+    msg!("{} unlocked achievements {:#x}", profile.wallet, unlocked);
+}
+
+/// Moves lamports out of a program-owned PDA (close fees, escrow payouts).
+/// Anchor's `close` constraint then sends whatever is left to the rent payer.
+fn transfer_lamports<'info>(from: &AccountInfo<'info>, to: &AccountInfo<'info>, amount: u64) -> Result<()> {
+    if amount == 0 {
+        return Ok(());
+    }
+    let mut from_lamports = from.try_borrow_mut_lamports()?;
+    **from_lamports = from_lamports.checked_sub(amount).ok_or(ProgramError::InsufficientFunds)?;
+    let mut to_lamports = to.try_borrow_mut_lamports()?;
+    **to_lamports = to_lamports.checked_add(amount).ok_or(ProgramError::ArithmeticOverflow)?;
+    Ok(())
+}
+
+/// Expected score of `rating` against `opponent` in bps, interpolated from
+/// ELO_EXPECTED_BPS. Gaps beyond 800 points count as 800.
+fn elo_expected_bps(rating: u16, opponent: u16) -> u32 {
+    let gap = (rating as i32 - opponent as i32).unsigned_abs().min(800);
+    let i = (gap / 25) as usize;
+    let lo = ELO_EXPECTED_BPS[i];
+    let hi = ELO_EXPECTED_BPS[(i + 1).min(32)];
+    let weaker = lo - (lo - hi) * (gap % 25) / 25;
+    if rating < opponent {
+        weaker
+    } else {
+        10_000 - weaker
+    }
+}
+
+/// Moves tokens out of a vault whose authority is the match / race PDA.
+fn transfer_tokens_signed<'info>(
+    token_program: &Program<'info, Token>,
+    vault: &Account<'info, TokenAccount>,
+    mint: &Account<'info, Mint>,
+    to: &Account<'info, TokenAccount>,
+    authority: &AccountInfo<'info>,
+    seeds: &[&[u8]],
+    amount: u64,
+) -> Result<()> {
+    if amount == 0 {
+        return Ok(());
+    }
+    token::transfer_checked(
+        CpiContext::new_with_signer(
+            token_program.to_account_info(),
+            TransferChecked {
+                from: vault.to_account_info(),
+                mint: mint.to_account_info(),
+                to: to.to_account_info(),
+                authority: authority.clone(),
+            },
+            &[seeds],
+        ),
+        amount,
+        mint.decimals,
+    )
+}
+
+/// Closes an emptied vault, sending its rent to `destination`.
+fn close_vault_signed<'info>(
+    token_program: &Program<'info, Token>,
+    vault: &Account<'info, TokenAccount>,
+    destination: &AccountInfo<'info>,
+    authority: &AccountInfo<'info>,
+    seeds: &[&[u8]],
+) -> Result<()> {
+    token::close_account(CpiContext::new_with_signer(
+        token_program.to_account_info(),
+        CloseAccount {
+            account: vault.to_account_info(),
+            destination: destination.clone(),
+            authority: authority.clone(),
+        },
+        &[seeds],
+    ))
+}
+
+// ═══════════════════════════════════════════════════════════════════════════
+// ACCOUNT CONTEXTS
+// ═══════════════════════════════════════════════════════════════════════════
+
+#[derive(Accounts)]
+#[instruction(match_id: u64)]
+pub struct CreateMatch<'info> {
+    #[account(
+        init,
+        payer = player1,
+        space = 8 + ArenaMatchState::LEN,
+        seeds = [MATCH_SEED, &match_id.to_le_bytes()],
+        bump
+    )]
+    pub arena_match: Account<'info, ArenaMatchState>,
+    pub game_server: Signer<'info>,
+    #[account(mut)]
+    pub player1: Signer<'info>,
+    pub system_program: Program<'info, System>,
+    /// Required when staking (stake_lamports > 0)
+    #[account(
+        init,
+        payer = player1,
+        space = 8 + MatchEscrow::LEN,
+        seeds = [ESCROW_SEED, &match_id.to_le_bytes()],
+        bump
+    )]
+    pub escrow: Option<Account<'info, MatchEscrow>>,
+}
+
+#[derive(Accounts, Session)]
+#[instruction(match_id: u64)]
+pub struct JoinMatch<'info> {
+    #[account(
+        mut,
+        seeds = [MATCH_SEED, &match_id.to_le_bytes()],
+        bump
+    )]
+    pub arena_match: Account<'info, ArenaMatchState>,
+    /// CHECK: The player joining
+    pub player2: AccountInfo<'info>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    #[session(signer = payer, authority = player2.key())]
+    pub session_token: Option<Account<'info, SessionToken>>,
+    /// Required for staked matches (joined on L1)
+    #[account(mut, seeds = [ESCROW_SEED, &match_id.to_le_bytes()], bump)]
+    pub escrow: Option<Account<'info, MatchEscrow>>,
+    pub system_program: Option<Program<'info, System>>,
+}
+
+#[derive(Accounts)]
+#[instruction(match_id: u64)]
+pub struct ServerAction<'info> {
+    #[account(
+        mut,
+        seeds = [MATCH_SEED, &match_id.to_le_bytes()],
+        bump
+    )]
+    pub arena_match: Account<'info, ArenaMatchState>,
+    #[account(
+        mut,
+        constraint = game_server.key() == arena_match.game_server @ ArenaError::UnauthorizedServer
+    )]
+    pub game_server: Signer<'info>,
+}
+
+#[derive(Accounts, Session)]
+#[instruction(match_id: u64)]
+pub struct SubmitInput<'info> {
+    #[account(
+        mut,
+        seeds = [MATCH_SEED, &match_id.to_le_bytes()],
+        bump
+    )]
+    pub arena_match: Account<'info, ArenaMatchState>,
+    #[account(
+        mut,
+        seeds = [PLAYER_STATE_SEED, &match_id.to_le_bytes(), player.key().as_ref()],
+        bump
+    )]
+    pub player_state: Account<'info, PlayerState>,
+    /// CHECK: The player submitting input
+    pub player: AccountInfo<'info>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    #[session(signer = payer, authority = player.key())]
+    pub session_token: Option<Account<'info, SessionToken>>,
+}
+
+#[derive(Accounts)]
+#[instruction(match_id: u64)]
+pub struct CreatePlayerState<'info> {
+    #[account(
+        init,
+        payer = player,
+        space = 8 + PlayerState::LEN,
+        seeds = [PLAYER_STATE_SEED, &match_id.to_le_bytes(), player.key().as_ref()],
+        bump
+    )]
+    pub player_state: Account<'info, PlayerState>,
+    #[account(mut)]
+    pub player: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[delegate]
+#[derive(Accounts)]
+pub struct DelegateMatch<'info> {
+    pub payer: Signer<'info>,
+    /// CHECK: The match PDA to delegate — unchecked per MagicBlock pattern
+    #[account(mut, del)]
+    pub pda: AccountInfo<'info>,
+}
+
+#[delegate]
+#[derive(Accounts)]
+pub struct DelegatePlayerState<'info> {
+    pub payer: Signer<'info>,
+    /// CHECK: The player state PDA to delegate — unchecked per MagicBlock pattern
+    #[account(mut, del)]
+    pub pda: AccountInfo<'info>,
+}
+
+#[commit]
+#[derive(Accounts)]
+#[instruction(match_id: u64)]
+pub struct EndMatch<'info> {
+    #[account(
+        mut,
+        seeds = [MATCH_SEED, &match_id.to_le_bytes()],
+        bump
+    )]
+    pub arena_match: Account<'info, ArenaMatchState>,
+    #[account(
+        mut,
+        constraint = payer.key() == arena_match.game_server @ ArenaError::UnauthorizedServer
+    )]
+    pub payer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(match_id: u64)]
+pub struct CancelMatch<'info> {
+    #[account(
+        mut,
+        seeds = [MATCH_SEED, &match_id.to_le_bytes()],
+        bump,
+        close = player1,
+        constraint = arena_match.player1 == player1.key() @ ArenaError::UnauthorizedPlayer,
+    )]
+    pub arena_match: Account<'info, ArenaMatchState>,
+    #[account(mut)]
+    pub player1: Signer<'info>,
+    /// Required for staked matches — refunds player 1's stake
+    #[account(mut, seeds = [ESCROW_SEED, &match_id.to_le_bytes()], bump, close = player1)]
+    pub escrow: Option<Account<'info, MatchEscrow>>,
+    /// Token accounts below are required once player 1 deposited tokens
+    #[account(address = arena_match.token_mint @ ArenaError::TokenMintMismatch)]
+    pub token_mint: Option<Account<'info, Mint>>,
+    #[account(mut, seeds = [MATCH_TOKEN_VAULT_SEED, &match_id.to_le_bytes()], bump)]
+    pub token_vault: Option<Account<'info, TokenAccount>>,
+    #[account(mut, token::mint = arena_match.token_mint, token::authority = player1)]
+    pub player1_token_account: Option<Account<'info, TokenAccount>>,
+    pub token_program: Option<Program<'info, Token>>,
+}
+
+#[derive(Accounts)]
+#[instruction(match_id: u64)]
+pub struct CloseMatch<'info> {
+    #[account(
+        mut,
+        seeds = [MATCH_SEED, &match_id.to_le_bytes()],
+        bump,
+        close = rent_payer,
+        // No status check — game server is trusted to close at any time.
+        // On-chain status may lag behind relay state due to fire-and-forget ER actions.
+        constraint = payer.key() == arena_match.game_server @ ArenaError::UnauthorizedServer,
+    )]
+    pub arena_match: Account<'info, ArenaMatchState>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    /// CHECK: Receives the match rent — must be the recorded rent payer
+    #[account(mut, address = arena_match.rent_payer @ ArenaError::InvalidRentPayer)]
+    pub rent_payer: UncheckedAccount<'info>,
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = 8 + PlayerProfile::LEN,
+        seeds = [PROFILE_SEED, arena_match.player1.as_ref()],
+        bump
+    )]
+    pub player1_profile: Box<Account<'info, PlayerProfile>>,
+    /// Required once player 2 joined
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = 8 + PlayerProfile::LEN,
+        seeds = [PROFILE_SEED, arena_match.player2.as_ref()],
+        bump
+    )]
+    pub player2_profile: Option<Box<Account<'info, PlayerProfile>>>,
+    #[account(seeds = [CONFIG_SEED], bump)]
+    pub config: Box<Account<'info, ArenaConfig>>,
+    /// The config's current season — None before the first season
+    #[account(seeds = [SEASON_SEED, &config.current_season.to_le_bytes()], bump)]
+    pub season: Option<Box<Account<'info, Season>>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(match_id: u64)]
+pub struct ClosePlayerState<'info> {
+    #[account(
+        seeds = [MATCH_SEED, &match_id.to_le_bytes()],
+        bump,
+        constraint = payer.key() == arena_match.game_server @ ArenaError::UnauthorizedServer,
+    )]
+    pub arena_match: Account<'info, ArenaMatchState>,
+    #[account(
+        mut,
+        seeds = [PLAYER_STATE_SEED, &match_id.to_le_bytes(), player_state.player.as_ref()],
+        bump,
+        close = rent_payer,
+    )]
+    pub player_state: Account<'info, PlayerState>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    /// CHECK: Receives the player state rent — must be the recorded rent payer
+    #[account(mut, address = player_state.rent_payer @ ArenaError::InvalidRentPayer)]
+    pub rent_payer: UncheckedAccount<'info>,
 }
 
-// ═══════════════════════════════════════════════════════════════════════════
-// ACCOUNT CONTEXTS
-// ═══════════════════════════════════════════════════════════════════════════
+#[derive(Accounts)]
+#[instruction(match_id: u64)]
+pub struct RaiseDispute<'info> {
+    #[account(
+        mut,
+        seeds = [MATCH_SEED, &match_id.to_le_bytes()],
+        bump,
+        constraint = challenger.key() == arena_match.player1
+            || challenger.key() == arena_match.player2 @ ArenaError::UnauthorizedPlayer,
+    )]
+    pub arena_match: Account<'info, ArenaMatchState>,
+    pub challenger: Signer<'info>,
+}
 
 #[derive(Accounts)]
 #[instruction(match_id: u64)]
-pub struct CreateMatch<'info> {
+pub struct ResolveDispute<'info> {
+    #[account(
+        mut,
+        seeds = [MATCH_SEED, &match_id.to_le_bytes()],
+        bump,
+        constraint = arbiter.key() == arena_match.arbiter @ ArenaError::UnauthorizedArbiter,
+    )]
+    pub arena_match: Account<'info, ArenaMatchState>,
+    pub arbiter: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(match_id: u64)]
+pub struct Payout<'info> {
+    #[account(
+        mut,
+        seeds = [MATCH_SEED, &match_id.to_le_bytes()],
+        bump
+    )]
+    pub arena_match: Account<'info, ArenaMatchState>,
+    #[account(
+        mut,
+        seeds = [ESCROW_SEED, &match_id.to_le_bytes()],
+        bump,
+        close = player1,
+    )]
+    pub escrow: Account<'info, MatchEscrow>,
+    /// CHECK: Receives player 1's share and the escrow rent
+    #[account(mut, address = arena_match.player1 @ ArenaError::UnauthorizedPlayer)]
+    pub player1: UncheckedAccount<'info>,
+    /// CHECK: Receives player 2's share — None if nobody joined
+    #[account(mut, address = arena_match.player2 @ ArenaError::UnauthorizedPlayer)]
+    pub player2: Option<UncheckedAccount<'info>>,
+    pub payer: Signer<'info>,
+    #[account(seeds = [CONFIG_SEED], bump)]
+    pub config: Account<'info, ArenaConfig>,
+    /// CHECK: Receives the protocol fee
+    #[account(mut, address = config.treasury @ ArenaError::InvalidTreasury)]
+    pub treasury: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(match_id: u64)]
+pub struct DepositMatchTokens<'info> {
+    #[account(
+        mut,
+        seeds = [MATCH_SEED, &match_id.to_le_bytes()],
+        bump
+    )]
+    pub arena_match: Account<'info, ArenaMatchState>,
+    #[account(address = arena_match.token_mint @ ArenaError::TokenMintMismatch)]
+    pub token_mint: Account<'info, Mint>,
+    #[account(
+        init_if_needed,
+        payer = depositor,
+        seeds = [MATCH_TOKEN_VAULT_SEED, &match_id.to_le_bytes()],
+        bump,
+        token::mint = token_mint,
+        token::authority = arena_match,
+    )]
+    pub token_vault: Account<'info, TokenAccount>,
+    #[account(mut, token::mint = token_mint, token::authority = depositor)]
+    pub depositor_token_account: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub depositor: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(match_id: u64)]
+pub struct PayoutMatchTokens<'info> {
+    #[account(
+        mut,
+        seeds = [MATCH_SEED, &match_id.to_le_bytes()],
+        bump
+    )]
+    pub arena_match: Account<'info, ArenaMatchState>,
+    #[account(address = arena_match.token_mint @ ArenaError::TokenMintMismatch)]
+    pub token_mint: Account<'info, Mint>,
+    #[account(mut, seeds = [MATCH_TOKEN_VAULT_SEED, &match_id.to_le_bytes()], bump)]
+    pub token_vault: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        associated_token::mint = token_mint,
+        associated_token::authority = player1,
+        associated_token::token_program = token_program,
+    )]
+    pub player1_token_account: Account<'info, TokenAccount>,
+    /// None if nobody joined
+    #[account(
+        mut,
+        associated_token::mint = token_mint,
+        associated_token::authority = arena_match.player2,
+        associated_token::token_program = token_program,
+    )]
+    pub player2_token_account: Option<Account<'info, TokenAccount>>,
+    /// CHECK: Owner of player1_token_account, receives the vault rent
+    #[account(mut, address = arena_match.player1 @ ArenaError::UnauthorizedPlayer)]
+    pub player1: UncheckedAccount<'info>,
+    pub payer: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    #[account(seeds = [CONFIG_SEED], bump)]
+    pub config: Account<'info, ArenaConfig>,
+    #[account(
+        mut,
+        associated_token::mint = token_mint,
+        associated_token::authority = config.treasury,
+        associated_token::token_program = token_program,
+    )]
+    pub treasury_token_account: Account<'info, TokenAccount>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeConfig<'info> {
     #[account(
         init,
-        payer = player1,
-        space = 8 + ArenaMatchState::LEN,
+        payer = admin,
+        space = 8 + ArenaConfig::LEN,
+        seeds = [CONFIG_SEED],
+        bump
+    )]
+    pub config: Account<'info, ArenaConfig>,
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(constraint = program.programdata_address()? == Some(program_data.key()))]
+    pub program: Program<'info, crate::program::ArenaMatch>,
+    #[account(constraint = program_data.upgrade_authority_address == Some(admin.key()) @ ArenaError::UnauthorizedAdmin)]
+    pub program_data: Account<'info, ProgramData>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetDerbyRewards<'info> {
+    #[account(
+        mut,
+        seeds = [CONFIG_SEED],
+        bump,
+        constraint = config.admin == admin.key() @ ArenaError::UnauthorizedAdmin,
+    )]
+    pub config: Account<'info, ArenaConfig>,
+    pub reward_mint: Account<'info, Mint>,
+    /// Funded by the admin; unused when the config PDA mints the rewards
+    #[account(
+        init_if_needed,
+        payer = admin,
+        seeds = [REWARD_VAULT_SEED, reward_mint.key().as_ref()],
+        bump,
+        token::mint = reward_mint,
+        token::authority = config,
+    )]
+    pub reward_vault: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(season_id: u32)]
+pub struct StartSeason<'info> {
+    #[account(
+        mut,
+        seeds = [CONFIG_SEED],
+        bump,
+        constraint = config.admin == admin.key() @ ArenaError::UnauthorizedAdmin,
+    )]
+    pub config: Account<'info, ArenaConfig>,
+    /// The running season, frozen here — None before the first season
+    #[account(mut, seeds = [SEASON_SEED, &config.current_season.to_le_bytes()], bump)]
+    pub previous_season: Option<Box<Account<'info, Season>>>,
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + Season::LEN,
+        seeds = [SEASON_SEED, &season_id.to_le_bytes()],
+        bump
+    )]
+    pub season: Box<Account<'info, Season>>,
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(match_id: u64)]
+pub struct RecordMatchSeason<'info> {
+    #[account(
+        mut,
         seeds = [MATCH_SEED, &match_id.to_le_bytes()],
+        bump,
+        constraint = payer.key() == arena_match.game_server @ ArenaError::UnauthorizedServer,
+    )]
+    pub arena_match: Account<'info, ArenaMatchState>,
+    #[account(mut, seeds = [SEASON_SEED, &season.season_id.to_le_bytes()], bump)]
+    pub season: Box<Account<'info, Season>>,
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = 8 + SeasonStats::LEN,
+        seeds = [SEASON_STATS_SEED, &season.season_id.to_le_bytes(), arena_match.player1.as_ref()],
+        bump
+    )]
+    pub player1_stats: Box<Account<'info, SeasonStats>>,
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = 8 + SeasonStats::LEN,
+        seeds = [SEASON_STATS_SEED, &season.season_id.to_le_bytes(), arena_match.player2.as_ref()],
+        bump
+    )]
+    pub player2_stats: Box<Account<'info, SeasonStats>>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(tournament_id: u64)]
+pub struct CreateTournament<'info> {
+    #[account(
+        init,
+        payer = game_server,
+        space = 8 + Tournament::LEN,
+        seeds = [TOURNAMENT_SEED, &tournament_id.to_le_bytes()],
+        bump
+    )]
+    pub tournament: Box<Account<'info, Tournament>>,
+    #[account(mut)]
+    pub game_server: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(tournament_id: u64)]
+pub struct JoinTournament<'info> {
+    #[account(mut, seeds = [TOURNAMENT_SEED, &tournament_id.to_le_bytes()], bump)]
+    pub tournament: Box<Account<'info, Tournament>>,
+    #[account(mut)]
+    pub entrant: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(tournament_id: u64)]
+pub struct TournamentServerAction<'info> {
+    #[account(
+        mut,
+        seeds = [TOURNAMENT_SEED, &tournament_id.to_le_bytes()],
+        bump,
+        constraint = tournament.game_server == game_server.key() @ ArenaError::UnauthorizedServer,
+    )]
+    pub tournament: Box<Account<'info, Tournament>>,
+    pub game_server: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(tournament_id: u64, node: u8)]
+pub struct CreateTournamentMatch<'info> {
+    #[account(
+        seeds = [TOURNAMENT_SEED, &tournament_id.to_le_bytes()],
+        bump,
+        constraint = tournament.game_server == game_server.key() @ ArenaError::UnauthorizedServer,
+    )]
+    pub tournament: Box<Account<'info, Tournament>>,
+    #[account(
+        init,
+        payer = game_server,
+        space = 8 + ArenaMatchState::LEN,
+        seeds = [MATCH_SEED, &tournament.match_id_for(node).to_le_bytes()],
+        bump
+    )]
+    pub arena_match: Account<'info, ArenaMatchState>,
+    #[account(mut)]
+    pub game_server: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(tournament_id: u64, node: u8)]
+pub struct ReportTournamentMatch<'info> {
+    #[account(mut, seeds = [TOURNAMENT_SEED, &tournament_id.to_le_bytes()], bump)]
+    pub tournament: Box<Account<'info, Tournament>>,
+    #[account(
+        mut,
+        seeds = [MATCH_SEED, &tournament.match_id_for(node).to_le_bytes()],
+        bump,
+        constraint = arena_match.tournament == tournament.key() @ ArenaError::TournamentMatchMismatch,
+    )]
+    pub arena_match: Account<'info, ArenaMatchState>,
+}
+
+#[derive(Accounts)]
+#[instruction(tournament_id: u64)]
+pub struct PayoutTournament<'info> {
+    #[account(mut, seeds = [TOURNAMENT_SEED, &tournament_id.to_le_bytes()], bump)]
+    pub tournament: Box<Account<'info, Tournament>>,
+    #[account(seeds = [CONFIG_SEED], bump)]
+    pub config: Account<'info, ArenaConfig>,
+    /// CHECK: Receives the protocol fee — must be the config's treasury
+    #[account(mut, address = config.treasury @ ArenaError::InvalidTreasury)]
+    pub treasury: UncheckedAccount<'info>,
+    /// CHECK: Receives the champion's share
+    #[account(mut, address = tournament.champion @ ArenaError::InvalidPrizeRecipient)]
+    pub champion: UncheckedAccount<'info>,
+    /// CHECK: Receives the runner-up's share
+    #[account(mut, address = tournament.runner_up @ ArenaError::InvalidPrizeRecipient)]
+    pub runner_up: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(tournament_id: u64)]
+pub struct CloseTournament<'info> {
+    #[account(
+        mut,
+        seeds = [TOURNAMENT_SEED, &tournament_id.to_le_bytes()],
+        bump,
+        close = game_server,
+        constraint = tournament.game_server == game_server.key() @ ArenaError::UnauthorizedServer,
+    )]
+    pub tournament: Box<Account<'info, Tournament>>,
+    #[account(mut)]
+    pub game_server: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateConfig<'info> {
+    #[account(
+        mut,
+        seeds = [CONFIG_SEED],
+        bump,
+        constraint = config.admin == admin.key() @ ArenaError::UnauthorizedAdmin,
+    )]
+    pub config: Account<'info, ArenaConfig>,
+    pub admin: Signer<'info>,
+}
+
+/// Accounts are created and delegated by hand in `setup_match` — the
+/// `#[delegate]` macro cannot express the optional player 2 accounts.
+#[derive(Accounts)]
+#[instruction(match_id: u64)]
+pub struct SetupMatch<'info> {
+    /// CHECK: Match PDA, created and delegated in the handler
+    #[account(mut, seeds = [MATCH_SEED, &match_id.to_le_bytes()], bump)]
+    pub arena_match: UncheckedAccount<'info>,
+    /// CHECK: Player 1 state PDA, created and delegated in the handler
+    #[account(
+        mut,
+        seeds = [PLAYER_STATE_SEED, &match_id.to_le_bytes(), player1.key().as_ref()],
+        bump
+    )]
+    pub player1_state: UncheckedAccount<'info>,
+    /// CHECK: Player 2 state PDA — seeds verified in the handler
+    #[account(mut)]
+    pub player2_state: Option<UncheckedAccount<'info>>,
+    #[account(mut)]
+    pub game_server: Signer<'info>,
+    #[account(mut)]
+    pub player1: Signer<'info>,
+    #[account(mut)]
+    pub player2: Option<Signer<'info>>,
+    /// CHECK: Delegation buffer — validated by the delegation program
+    #[account(mut)]
+    pub buffer_arena_match: UncheckedAccount<'info>,
+    /// CHECK: Delegation record — validated by the delegation program
+    #[account(mut)]
+    pub delegation_record_arena_match: UncheckedAccount<'info>,
+    /// CHECK: Delegation metadata — validated by the delegation program
+    #[account(mut)]
+    pub delegation_metadata_arena_match: UncheckedAccount<'info>,
+    /// CHECK: Delegation buffer — validated by the delegation program
+    #[account(mut)]
+    pub buffer_player1_state: UncheckedAccount<'info>,
+    /// CHECK: Delegation record — validated by the delegation program
+    #[account(mut)]
+    pub delegation_record_player1_state: UncheckedAccount<'info>,
+    /// CHECK: Delegation metadata — validated by the delegation program
+    #[account(mut)]
+    pub delegation_metadata_player1_state: UncheckedAccount<'info>,
+    /// CHECK: Delegation buffer — validated by the delegation program
+    #[account(mut)]
+    pub buffer_player2_state: Option<UncheckedAccount<'info>>,
+    /// CHECK: Delegation record — validated by the delegation program
+    #[account(mut)]
+    pub delegation_record_player2_state: Option<UncheckedAccount<'info>>,
+    /// CHECK: Delegation metadata — validated by the delegation program
+    #[account(mut)]
+    pub delegation_metadata_player2_state: Option<UncheckedAccount<'info>>,
+    /// CHECK: This program, owner of the delegated PDAs
+    #[account(address = crate::ID)]
+    pub owner_program: UncheckedAccount<'info>,
+    /// CHECK: MagicBlock delegation program
+    #[account(address = ephemeral_rollups_sdk::id())]
+    pub delegation_program: UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> SetupMatch<'info> {
+    fn create_program_account(
+        &self,
+        payer: &AccountInfo<'info>,
+        pda: &AccountInfo<'info>,
+        space: usize,
+        signer_seeds: &[&[u8]],
+    ) -> Result<()> {
+        let rent = Rent::get()?.minimum_balance(space);
+        let system_program = self.system_program.to_account_info();
+        if pda.lamports() == 0 {
+            return anchor_lang::system_program::create_account(
+                CpiContext::new_with_signer(
+                    system_program,
+                    anchor_lang::system_program::CreateAccount {
+                        from: payer.clone(),
+                        to: pda.clone(),
+                    },
+                    &[signer_seeds],
+                ),
+                rent,
+                space as u64,
+                &crate::ID,
+            );
+        }
+
+        // Someone pre-funded the PDA, so create_account would fail. Top it up
+        // to rent exemption, then allocate and assign it the way `init` does.
+        let shortfall = rent.saturating_sub(pda.lamports());
+        if shortfall > 0 {
+            anchor_lang::system_program::transfer(
+                CpiContext::new(
+                    system_program.clone(),
+                    anchor_lang::system_program::Transfer {
+                        from: payer.clone(),
+                        to: pda.clone(),
+                    },
+                ),
+                shortfall,
+            )?;
+        }
+        anchor_lang::system_program::allocate(
+            CpiContext::new_with_signer(
+                system_program.clone(),
+                anchor_lang::system_program::Allocate {
+                    account_to_allocate: pda.clone(),
+                },
+                &[signer_seeds],
+            ),
+            space as u64,
+        )?;
+        anchor_lang::system_program::assign(
+            CpiContext::new_with_signer(
+                system_program,
+                anchor_lang::system_program::Assign {
+                    account_to_assign: pda.clone(),
+                },
+                &[signer_seeds],
+            ),
+            &crate::ID,
+        )
+    }
+
+    fn delegate(
+        &self,
+        pda: &AccountInfo<'info>,
+        buffer: &AccountInfo<'info>,
+        delegation_record: &AccountInfo<'info>,
+        delegation_metadata: &AccountInfo<'info>,
+        seeds: &[&[u8]],
+        validator: Option<Pubkey>,
+    ) -> Result<()> {
+        delegate_account(
+            DelegateAccounts {
+                payer: &self.game_server.to_account_info(),
+                pda,
+                owner_program: &self.owner_program.to_account_info(),
+                buffer,
+                delegation_record,
+                delegation_metadata,
+                delegation_program: &self.delegation_program.to_account_info(),
+                system_program: &self.system_program.to_account_info(),
+            },
+            seeds,
+            DelegateConfig {
+                validator,
+                ..Default::default()
+            },
+        )?;
+        Ok(())
+    }
+}
+
+// ── Derby Contexts ────────────────────────────────────────────────────────
+
+#[derive(Accounts)]
+#[instruction(race_id: u64)]
+pub struct CreateDerby<'info> {
+    #[account(
+        init,
+        payer = player,
+        space = 8 + DerbyRaceState::LEN,
+        seeds = [DERBY_SEED, &race_id.to_le_bytes()],
         bump
     )]
-    pub arena_match: Account<'info, ArenaMatchState>,
+    pub derby_race: Account<'info, DerbyRaceState>,
     pub game_server: Signer<'info>,
     #[account(mut)]
-    pub player1: Signer<'info>,
+    pub player: Signer<'info>,
     pub system_program: Program<'info, System>,
+    /// Enters the race in a time-trial event — None for a standalone race
+    #[account(mut)]
+    pub derby_event: Option<Box<Account<'info, DerbyEvent>>>,
+    /// Races against the track's ghost — None outside ghost mode
+    pub track_ghost: Option<Box<Account<'info, TrackGhost>>>,
 }
 
-#[derive(Accounts, Session)]
-#[instruction(match_id: u64)]
-pub struct JoinMatch<'info> {
-    #[account(
-        mut,
-        seeds = [MATCH_SEED, &match_id.to_le_bytes()],
-        bump
-    )]
-    pub arena_match: Account<'info, ArenaMatchState>,
-    /// CHECK: The player joining
-    pub player2: AccountInfo<'info>,
+#[delegate]
+#[derive(Accounts)]
+pub struct DelegateDerby<'info> {
     #[account(mut)]
     pub payer: Signer<'info>,
-    #[session(signer = payer, authority = player2.key())]
-    pub session_token: Option<Account<'info, SessionToken>>,
+    /// CHECK: The derby PDA to delegate
+    #[account(mut, del)]
+    pub pda: Account<'info, DerbyRaceState>,
 }
 
 #[derive(Accounts)]
-#[instruction(match_id: u64)]
-pub struct ServerAction<'info> {
+#[instruction(race_id: u64)]
+pub struct DerbyServerAction<'info> {
     #[account(
         mut,
-        seeds = [MATCH_SEED, &match_id.to_le_bytes()],
+        seeds = [DERBY_SEED, &race_id.to_le_bytes()],
         bump
     )]
-    pub arena_match: Account<'info, ArenaMatchState>,
+    pub derby_race: Account<'info, DerbyRaceState>,
     #[account(
         mut,
-        constraint = game_server.key() == arena_match.game_server @ ArenaError::UnauthorizedServer
+        constraint = game_server.key() == derby_race.game_server @ DerbyError::UnauthorizedServer
     )]
     pub game_server: Signer<'info>,
 }
 
-#[derive(Accounts, Session)]
-#[instruction(match_id: u64)]
-pub struct SubmitInput<'info> {
+#[derive(Accounts)]
+#[instruction(race_id: u64)]
+pub struct DerbyServerUpdate<'info> {
     #[account(
         mut,
-        seeds = [MATCH_SEED, &match_id.to_le_bytes()],
+        seeds = [DERBY_SEED, &race_id.to_le_bytes()],
         bump
     )]
-    pub arena_match: Account<'info, ArenaMatchState>,
+    pub derby_race: Account<'info, DerbyRaceState>,
     #[account(
         mut,
-        seeds = [PLAYER_STATE_SEED, &match_id.to_le_bytes(), player.key().as_ref()],
-        bump
+        constraint = game_server.key() == derby_race.game_server @ DerbyError::UnauthorizedServer
     )]
-    pub player_state: Account<'info, PlayerState>,
+    pub game_server: Signer<'info>,
+    /// The racer the action applies to — required for multiplayer races, None otherwise
+    #[account(mut, constraint = derby_player.race_id == race_id @ DerbyError::InvalidDerbyPlayer)]
+    pub derby_player: Option<Account<'info, DerbyPlayerState>>,
+}
+
+#[derive(Accounts, Session)]
+#[instruction(race_id: u64)]
+pub struct DerbySubmitInput<'info> {
+    #[account(
+        mut,
+        seeds = [DERBY_SEED, &race_id.to_le_bytes()],
+        bump,
+        constraint = derby_race.is_racer(&player.key()) @ DerbyError::UnauthorizedPlayer
+    )]
+    pub derby_race: Account<'info, DerbyRaceState>,
     /// CHECK: The player submitting input
     pub player: AccountInfo<'info>,
     #[account(mut)]
@@ -713,201 +4676,361 @@ pub struct SubmitInput<'info> {
     pub session_token: Option<Account<'info, SessionToken>>,
 }
 
+#[commit]
 #[derive(Accounts)]
-#[instruction(match_id: u64)]
-pub struct CreatePlayerState<'info> {
+#[instruction(race_id: u64)]
+pub struct EndDerby<'info> {
     #[account(
-        init,
-        payer = player,
-        space = 8 + PlayerState::LEN,
-        seeds = [PLAYER_STATE_SEED, &match_id.to_le_bytes(), player.key().as_ref()],
+        mut,
+        seeds = [DERBY_SEED, &race_id.to_le_bytes()],
         bump
     )]
-    pub player_state: Account<'info, PlayerState>,
-    #[account(mut)]
-    pub player: Signer<'info>,
-    pub system_program: Program<'info, System>,
+    pub derby_race: Account<'info, DerbyRaceState>,
+    #[account(
+        mut,
+        constraint = payer.key() == derby_race.game_server @ DerbyError::UnauthorizedServer
+    )]
+    pub payer: Signer<'info>,
 }
 
-#[delegate]
 #[derive(Accounts)]
-pub struct DelegateMatch<'info> {
+#[instruction(race_id: u64)]
+pub struct CloseDerby<'info> {
+    #[account(
+        mut,
+        seeds = [DERBY_SEED, &race_id.to_le_bytes()],
+        bump,
+        close = rent_payer,
+        constraint = derby_race.game_server == payer.key() @ DerbyError::UnauthorizedServer
+    )]
+    pub derby_race: Account<'info, DerbyRaceState>,
+    #[account(mut)]
     pub payer: Signer<'info>,
-    /// CHECK: The match PDA to delegate — unchecked per MagicBlock pattern
-    #[account(mut, del)]
-    pub pda: AccountInfo<'info>,
+    /// CHECK: Receives the race rent — must be the recorded rent payer
+    #[account(mut, address = derby_race.rent_payer @ DerbyError::InvalidRentPayer)]
+    pub rent_payer: UncheckedAccount<'info>,
+    /// Required for single-racer races — None for multiplayer
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = 8 + PlayerProfile::LEN,
+        seeds = [PROFILE_SEED, derby_race.player.as_ref()],
+        bump
+    )]
+    pub player_profile: Option<Box<Account<'info, PlayerProfile>>>,
+    /// Required for Finished races on a shared track — must be None otherwise
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = 8 + TrackLeaderboard::LEN,
+        seeds = [TRACK_LEADERBOARD_SEED, derby_race.vrf_seed.as_ref()],
+        bump
+    )]
+    pub track_leaderboard: Option<Box<Account<'info, TrackLeaderboard>>>,
+    /// Required for Finished races on a shared track — must be None otherwise
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = 8 + TrackGhost::LEN,
+        seeds = [TRACK_GHOST_SEED, derby_race.vrf_seed.as_ref()],
+        bump
+    )]
+    pub track_ghost: Option<Box<Account<'info, TrackGhost>>>,
+    #[account(seeds = [CONFIG_SEED], bump)]
+    pub config: Box<Account<'info, ArenaConfig>>,
+    /// The config's current season — None before the first season
+    #[account(seeds = [SEASON_SEED, &config.current_season.to_le_bytes()], bump)]
+    pub season: Option<Box<Account<'info, Season>>>,
+    pub system_program: Program<'info, System>,
 }
 
-#[delegate]
 #[derive(Accounts)]
-pub struct DelegatePlayerState<'info> {
-    pub payer: Signer<'info>,
-    /// CHECK: The player state PDA to delegate — unchecked per MagicBlock pattern
-    #[account(mut, del)]
-    pub pda: AccountInfo<'info>,
+#[instruction(vrf_seed: [u8; 32])]
+pub struct CloseTrackLeaderboard<'info> {
+    #[account(
+        mut,
+        seeds = [TRACK_LEADERBOARD_SEED, vrf_seed.as_ref()],
+        bump,
+        close = rent_payer,
+        constraint = track_leaderboard.rent_payer == rent_payer.key() @ DerbyError::InvalidRentPayer
+    )]
+    pub track_leaderboard: Box<Account<'info, TrackLeaderboard>>,
+    #[account(mut)]
+    pub rent_payer: Signer<'info>,
 }
 
-#[commit]
 #[derive(Accounts)]
-#[instruction(match_id: u64)]
-pub struct EndMatch<'info> {
+#[instruction(vrf_seed: [u8; 32])]
+pub struct CloseTrackGhost<'info> {
     #[account(
         mut,
-        seeds = [MATCH_SEED, &match_id.to_le_bytes()],
-        bump
+        seeds = [TRACK_GHOST_SEED, vrf_seed.as_ref()],
+        bump,
+        close = rent_payer,
+        constraint = track_ghost.rent_payer == rent_payer.key() @ DerbyError::InvalidRentPayer
     )]
-    pub arena_match: Account<'info, ArenaMatchState>,
+    pub track_ghost: Box<Account<'info, TrackGhost>>,
+    #[account(mut)]
+    pub rent_payer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(race_id: u64)]
+pub struct ExpireDerby<'info> {
     #[account(
         mut,
-        constraint = payer.key() == arena_match.game_server @ ArenaError::UnauthorizedServer
+        seeds = [DERBY_SEED, &race_id.to_le_bytes()],
+        bump
     )]
-    pub payer: Signer<'info>,
+    pub derby_race: Account<'info, DerbyRaceState>,
+    pub caller: Signer<'info>,
 }
 
 #[derive(Accounts)]
-#[instruction(match_id: u64)]
-pub struct CancelMatch<'info> {
+#[instruction(race_id: u64)]
+pub struct DepositDerbyTokens<'info> {
     #[account(
         mut,
-        seeds = [MATCH_SEED, &match_id.to_le_bytes()],
+        seeds = [DERBY_SEED, &race_id.to_le_bytes()],
+        bump
+    )]
+    pub derby_race: Account<'info, DerbyRaceState>,
+    #[account(address = derby_race.token_mint @ DerbyError::TokenMintMismatch)]
+    pub token_mint: Account<'info, Mint>,
+    #[account(
+        init_if_needed,
+        payer = depositor,
+        seeds = [DERBY_TOKEN_VAULT_SEED, &race_id.to_le_bytes()],
         bump,
-        close = player1,
-        constraint = arena_match.player1 == player1.key() @ ArenaError::UnauthorizedPlayer,
+        token::mint = token_mint,
+        token::authority = derby_race,
     )]
-    pub arena_match: Account<'info, ArenaMatchState>,
+    pub token_vault: Account<'info, TokenAccount>,
+    #[account(mut, token::mint = token_mint, token::authority = depositor)]
+    pub depositor_token_account: Account<'info, TokenAccount>,
     #[account(mut)]
-    pub player1: Signer<'info>,
+    pub depositor: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-#[instruction(match_id: u64)]
-pub struct CloseMatch<'info> {
+#[instruction(race_id: u64)]
+pub struct RecordDerbySeason<'info> {
     #[account(
         mut,
-        seeds = [MATCH_SEED, &match_id.to_le_bytes()],
+        seeds = [DERBY_SEED, &race_id.to_le_bytes()],
         bump,
-        close = payer,
-        // No status check — game server is trusted to close at any time.
-        // On-chain status may lag behind relay state due to fire-and-forget ER actions.
-        constraint = payer.key() == arena_match.game_server @ ArenaError::UnauthorizedServer,
+        constraint = derby_race.game_server == payer.key() @ DerbyError::UnauthorizedServer
     )]
-    pub arena_match: Account<'info, ArenaMatchState>,
+    pub derby_race: Account<'info, DerbyRaceState>,
+    #[account(mut, seeds = [SEASON_SEED, &season.season_id.to_le_bytes()], bump)]
+    pub season: Box<Account<'info, Season>>,
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = 8 + SeasonStats::LEN,
+        seeds = [SEASON_STATS_SEED, &season.season_id.to_le_bytes(), derby_race.player.as_ref()],
+        bump
+    )]
+    pub player_stats: Box<Account<'info, SeasonStats>>,
     #[account(mut)]
     pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-#[instruction(match_id: u64)]
-pub struct ClosePlayerState<'info> {
-    #[account(
-        seeds = [MATCH_SEED, &match_id.to_le_bytes()],
-        bump,
-        constraint = payer.key() == arena_match.game_server @ ArenaError::UnauthorizedServer,
-    )]
-    pub arena_match: Account<'info, ArenaMatchState>,
+#[instruction(race_id: u64)]
+pub struct ContributeDerbySeed<'info> {
     #[account(
         mut,
-        seeds = [PLAYER_STATE_SEED, &match_id.to_le_bytes(), player_state.player.as_ref()],
+        seeds = [DERBY_SEED, &race_id.to_le_bytes()],
         bump,
-        close = payer,
+        constraint = derby_race.player == player.key() @ DerbyError::UnauthorizedPlayer
     )]
-    pub player_state: Account<'info, PlayerState>,
-    #[account(mut)]
-    pub payer: Signer<'info>,
+    pub derby_race: Account<'info, DerbyRaceState>,
+    pub player: Signer<'info>,
 }
 
-// ── Derby Contexts ────────────────────────────────────────────────────────
-
 #[derive(Accounts)]
 #[instruction(race_id: u64)]
-pub struct CreateDerby<'info> {
+pub struct JoinDerby<'info> {
     #[account(
-        init,
-        payer = player,
-        space = 8 + DerbyRaceState::LEN,
+        mut,
         seeds = [DERBY_SEED, &race_id.to_le_bytes()],
-        bump
+        bump,
+        constraint = derby_race.game_server == game_server.key() @ DerbyError::UnauthorizedServer
     )]
     pub derby_race: Account<'info, DerbyRaceState>,
+    #[account(
+        init,
+        payer = racer,
+        space = 8 + DerbyPlayerState::LEN,
+        seeds = [DERBY_PLAYER_SEED, &race_id.to_le_bytes(), racer.key().as_ref()],
+        bump
+    )]
+    pub derby_player: Account<'info, DerbyPlayerState>,
     pub game_server: Signer<'info>,
     #[account(mut)]
-    pub player: Signer<'info>,
+    pub racer: Signer<'info>,
     pub system_program: Program<'info, System>,
 }
 
 #[delegate]
 #[derive(Accounts)]
-pub struct DelegateDerby<'info> {
-    #[account(mut)]
+pub struct DelegateDerbyPlayer<'info> {
     pub payer: Signer<'info>,
-    /// CHECK: The derby PDA to delegate
+    /// CHECK: The derby player PDA to delegate — unchecked per MagicBlock pattern
     #[account(mut, del)]
-    pub pda: Account<'info, DerbyRaceState>,
+    pub pda: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(event_id: u64)]
+pub struct CreateDerbyEvent<'info> {
+    #[account(
+        init,
+        payer = game_server,
+        space = 8 + DerbyEvent::LEN,
+        seeds = [DERBY_EVENT_SEED, &event_id.to_le_bytes()],
+        bump
+    )]
+    pub derby_event: Box<Account<'info, DerbyEvent>>,
+    #[account(mut)]
+    pub game_server: Signer<'info>,
+    pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
 #[instruction(race_id: u64)]
-pub struct DerbyServerAction<'info> {
+pub struct RecordDerbyEvent<'info> {
     #[account(
         mut,
         seeds = [DERBY_SEED, &race_id.to_le_bytes()],
-        bump
+        bump,
+        constraint = derby_race.game_server == payer.key() @ DerbyError::UnauthorizedServer
     )]
     pub derby_race: Account<'info, DerbyRaceState>,
+    #[account(mut, address = derby_race.event @ DerbyError::EventMismatch)]
+    pub derby_event: Box<Account<'info, DerbyEvent>>,
+    pub payer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(event_id: u64)]
+pub struct FinalizeDerbyEvent<'info> {
     #[account(
         mut,
-        constraint = game_server.key() == derby_race.game_server @ DerbyError::UnauthorizedServer
+        seeds = [DERBY_EVENT_SEED, &event_id.to_le_bytes()],
+        bump,
+        constraint = derby_event.game_server == game_server.key() @ DerbyError::UnauthorizedServer
     )]
+    pub derby_event: Box<Account<'info, DerbyEvent>>,
+    #[account(seeds = [CONFIG_SEED], bump)]
+    pub config: Account<'info, ArenaConfig>,
+    /// CHECK: Receives the protocol fee — must be the config's treasury
+    #[account(mut, address = config.treasury @ DerbyError::InvalidTreasury)]
+    pub treasury: UncheckedAccount<'info>,
+    #[account(mut)]
     pub game_server: Signer<'info>,
 }
 
-#[derive(Accounts, Session)]
+#[derive(Accounts)]
 #[instruction(race_id: u64)]
-pub struct DerbySubmitInput<'info> {
+pub struct RewardDerby<'info> {
     #[account(
         mut,
         seeds = [DERBY_SEED, &race_id.to_le_bytes()],
         bump
     )]
     pub derby_race: Account<'info, DerbyRaceState>,
-    /// CHECK: The player submitting input
-    pub player: AccountInfo<'info>,
-    #[account(mut)]
+    #[account(seeds = [CONFIG_SEED], bump)]
+    pub config: Account<'info, ArenaConfig>,
+    #[account(mut, address = config.reward_mint @ DerbyError::RewardMintMismatch)]
+    pub reward_mint: Account<'info, Mint>,
+    /// Required unless the config PDA is the reward mint's authority
+    #[account(mut, seeds = [REWARD_VAULT_SEED, reward_mint.key().as_ref()], bump)]
+    pub reward_vault: Option<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        associated_token::mint = reward_mint,
+        associated_token::authority = derby_race.player,
+        associated_token::token_program = token_program,
+    )]
+    pub player_token_account: Account<'info, TokenAccount>,
     pub payer: Signer<'info>,
-    #[session(signer = payer, authority = player.key())]
-    pub session_token: Option<Account<'info, SessionToken>>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
 }
 
-#[commit]
 #[derive(Accounts)]
 #[instruction(race_id: u64)]
-pub struct EndDerby<'info> {
+pub struct PayoutDerbyTokens<'info> {
     #[account(
         mut,
         seeds = [DERBY_SEED, &race_id.to_le_bytes()],
         bump
     )]
     pub derby_race: Account<'info, DerbyRaceState>,
+    #[account(address = derby_race.token_mint @ DerbyError::TokenMintMismatch)]
+    pub token_mint: Account<'info, Mint>,
+    #[account(mut, seeds = [DERBY_TOKEN_VAULT_SEED, &race_id.to_le_bytes()], bump)]
+    pub token_vault: Account<'info, TokenAccount>,
     #[account(
         mut,
-        constraint = payer.key() == derby_race.game_server @ DerbyError::UnauthorizedServer
+        associated_token::mint = token_mint,
+        associated_token::authority = player,
+        associated_token::token_program = token_program,
+    )]
+    pub player_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        associated_token::mint = token_mint,
+        associated_token::authority = derby_race.game_server,
+        associated_token::token_program = token_program,
     )]
+    pub server_token_account: Account<'info, TokenAccount>,
+    /// CHECK: Owner of player_token_account, receives the vault rent
+    #[account(mut, address = derby_race.player @ DerbyError::UnauthorizedPlayer)]
+    pub player: UncheckedAccount<'info>,
     pub payer: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    #[account(seeds = [CONFIG_SEED], bump)]
+    pub config: Account<'info, ArenaConfig>,
+    #[account(
+        mut,
+        associated_token::mint = token_mint,
+        associated_token::authority = config.treasury,
+        associated_token::token_program = token_program,
+    )]
+    pub treasury_token_account: Account<'info, TokenAccount>,
 }
 
 #[derive(Accounts)]
 #[instruction(race_id: u64)]
-pub struct CloseDerby<'info> {
+pub struct RaiseDerbyDispute<'info> {
     #[account(
         mut,
         seeds = [DERBY_SEED, &race_id.to_le_bytes()],
         bump,
-        close = payer,
-        constraint = derby_race.game_server == payer.key() @ DerbyError::UnauthorizedServer
+        constraint = derby_race.player == player.key() @ DerbyError::UnauthorizedPlayer
     )]
     pub derby_race: Account<'info, DerbyRaceState>,
-    #[account(mut)]
-    pub payer: Signer<'info>,
+    pub player: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(race_id: u64)]
+pub struct ResolveDerbyDispute<'info> {
+    #[account(
+        mut,
+        seeds = [DERBY_SEED, &race_id.to_le_bytes()],
+        bump,
+        constraint = derby_race.arbiter == arbiter.key() @ DerbyError::UnauthorizedArbiter
+    )]
+    pub derby_race: Account<'info, DerbyRaceState>,
+    pub arbiter: Signer<'info>,
 }
 
 // ═══════════════════════════════════════════════════════════════════════════
@@ -936,6 +5059,114 @@ pub enum ArenaError {
     UnauthorizedServer,
     #[msg("Unauthorized player")]
     UnauthorizedPlayer,
+    #[msg("Server tick must contain between 1 and 32 events")]
+    InvalidEventBatch,
+    #[msg("Player state account does not match the expected PDA")]
+    InvalidPlayerState,
+    #[msg("Player 2 was provided without all of their accounts")]
+    MissingPlayerAccounts,
+    #[msg("Expected player state account was not provided")]
+    MissingPlayerState,
+    #[msg("Rent refund account does not match the recorded rent payer")]
+    InvalidRentPayer,
+    #[msg("Player state rent payer account was not provided")]
+    MissingRentPayer,
+    #[msg("Close fee exceeds the account's rent deposit")]
+    CloseFeeTooHigh,
+    #[msg("Dispute window requires an arbiter and must not exceed one week")]
+    InvalidDisputeParams,
+    #[msg("Match has no arbiter, disputes are disabled")]
+    DisputesDisabled,
+    #[msg("Dispute window has closed")]
+    DisputeWindowClosed,
+    #[msg("Dispute window is still open")]
+    DisputeWindowOpen,
+    #[msg("A dispute is pending arbiter resolution")]
+    DisputePending,
+    #[msg("Invalid dispute state for this action")]
+    InvalidDisputeState,
+    #[msg("Unauthorized arbiter")]
+    UnauthorizedArbiter,
+    #[msg("Winner must be player1, player2 or the default pubkey (draw)")]
+    InvalidWinner,
+    #[msg("Escrow account must be provided exactly when the match is staked")]
+    StakeMismatch,
+    #[msg("Staked matches must be joined by the player's own wallet")]
+    StakeRequiresWallet,
+    #[msg("Match has no stake")]
+    NoStake,
+    #[msg("Match stake was already paid out")]
+    AlreadyPaidOut,
+    #[msg("Match stake has not been paid out")]
+    PayoutPending,
+    #[msg("Token wager amount must be greater than zero")]
+    InvalidTokenWager,
+    #[msg("Match has no token wager")]
+    NoTokenStake,
+    #[msg("Token mint does not match the wager mint")]
+    TokenMintMismatch,
+    #[msg("Tokens were already deposited")]
+    TokensAlreadyDeposited,
+    #[msg("Player 1 must deposit tokens before player 2")]
+    TokenDepositOrder,
+    #[msg("Both players must deposit their token stake first")]
+    TokenStakePending,
+    #[msg("Token accounts required to refund the deposit were not provided")]
+    MissingTokenAccounts,
+    #[msg("Match tokens were already paid out")]
+    TokensAlreadyPaidOut,
+    #[msg("Match tokens have not been paid out")]
+    TokenPayoutPending,
+    #[msg("Protocol fee exceeds the maximum (1000 bps)")]
+    FeeTooHigh,
+    #[msg("Unauthorized config admin")]
+    UnauthorizedAdmin,
+    #[msg("Treasury account does not match the config")]
+    InvalidTreasury,
+    #[msg("Season id must follow the current season and end after it starts")]
+    InvalidSeason,
+    #[msg("Season is frozen or outside its start / end time")]
+    SeasonNotActive,
+    #[msg("Result was already recorded in a season")]
+    SeasonAlreadyRecorded,
+    #[msg("Tournament needs 2-32 entrants, a prize split summing to 10000 bps, rounds only for Swiss (fewer than entrants) and a valid match id range")]
+    InvalidTournamentParams,
+    #[msg("Tournament registration is closed")]
+    TournamentNotOpen,
+    #[msg("Tournament is full")]
+    TournamentFull,
+    #[msg("Wallet already entered this tournament")]
+    AlreadyEntered,
+    #[msg("Tournament needs at least 2 entrants to start")]
+    NotEnoughEntrants,
+    #[msg("Tournament is not running")]
+    TournamentNotRunning,
+    #[msg("Wrong tournament status for this action")]
+    InvalidTournamentState,
+    #[msg("Bracket node is not an undecided match")]
+    InvalidBracketNode,
+    #[msg("Bracket node's players are not decided yet")]
+    BracketNodeNotReady,
+    #[msg("Match was not spawned by this tournament")]
+    TournamentMatchMismatch,
+    #[msg("Match result was already reported to the tournament")]
+    TournamentMatchReported,
+    #[msg("Tournament match must be reported before close")]
+    TournamentReportPending,
+    #[msg("Tournament has no champion yet")]
+    TournamentNotComplete,
+    #[msg("Tournament prizes were already paid out")]
+    TournamentAlreadyPaidOut,
+    #[msg("Prize account is not the champion / runner-up")]
+    InvalidPrizeRecipient,
+    #[msg("Entrant refund account missing from remaining accounts")]
+    MissingEntrant,
+    #[msg("Player state is still delegated or not owned by this program")]
+    PlayerStateNotClosable,
+    #[msg("Tournament prizes have not been paid out")]
+    TournamentPayoutPending,
+    #[msg("Result settled in the current season must be recorded there before close")]
+    SeasonReportPending,
 }
 
 #[error_code]
@@ -960,4 +5191,134 @@ pub enum DerbyError {
     LapsNotComplete,
     #[msg("Unauthorized game server")]
     UnauthorizedServer,
+    #[msg("Rent refund account does not match the recorded rent payer")]
+    InvalidRentPayer,
+    #[msg("Close fee exceeds the account's rent deposit")]
+    CloseFeeTooHigh,
+    #[msg("Dispute window requires an arbiter and must not exceed one week")]
+    InvalidDisputeParams,
+    #[msg("Race has no arbiter, disputes are disabled")]
+    DisputesDisabled,
+    #[msg("Dispute window has closed")]
+    DisputeWindowClosed,
+    #[msg("Dispute window is still open")]
+    DisputeWindowOpen,
+    #[msg("A dispute is pending arbiter resolution")]
+    DisputePending,
+    #[msg("Invalid dispute state for this action")]
+    InvalidDisputeState,
+    #[msg("Unauthorized arbiter")]
+    UnauthorizedArbiter,
+    #[msg("Derby disputes can only be confirmed or voided")]
+    InvalidDisputeResolution,
+    #[msg("Unauthorized player")]
+    UnauthorizedPlayer,
+    #[msg("Token wager amount must be greater than zero")]
+    InvalidTokenWager,
+    #[msg("Race has no token wager")]
+    NoTokenStake,
+    #[msg("Token mint does not match the wager mint")]
+    TokenMintMismatch,
+    #[msg("Tokens were already deposited")]
+    TokensAlreadyDeposited,
+    #[msg("Player must deposit tokens before the game server")]
+    TokenDepositOrder,
+    #[msg("Player entry and server prize must be deposited first")]
+    TokenStakePending,
+    #[msg("Race tokens were already paid out")]
+    TokensAlreadyPaidOut,
+    #[msg("Race tokens have not been paid out")]
+    TokenPayoutPending,
+    #[msg("Derby gold rewards are disabled")]
+    RewardsDisabled,
+    #[msg("Race gold was already rewarded")]
+    AlreadyRewarded,
+    #[msg("Reward mint does not match the config")]
+    RewardMintMismatch,
+    #[msg("Reward vault is required when the program can't mint rewards")]
+    MissingRewardVault,
+    #[msg("Season is frozen or outside its start / end time")]
+    SeasonNotActive,
+    #[msg("Result was already recorded in a season")]
+    SeasonAlreadyRecorded,
+    #[msg("Track leaderboard is required to close a Finished race")]
+    MissingTrackLeaderboard,
+    #[msg("Event window must end after it starts and prize splits must sum to 10000 bps")]
+    InvalidEventParams,
+    #[msg("Event is finalized or outside its entry window")]
+    EventNotOpen,
+    #[msg("Race seed does not match the event track")]
+    EventSeedMismatch,
+    #[msg("Event does not match the race")]
+    EventMismatch,
+    #[msg("Race was already recorded in its event")]
+    EventAlreadyRecorded,
+    #[msg("Event was already finalized")]
+    EventFinalized,
+    #[msg("Event window or its dispute window is still open")]
+    EventWindowOpen,
+    #[msg("Finished event race must be recorded in its event first")]
+    EventReportPending,
+    #[msg("A podium wallet is missing from the remaining accounts")]
+    MissingPrizeAccount,
+    #[msg("Treasury does not match the config")]
+    InvalidTreasury,
+    #[msg("A race takes between 1 and 8 racers")]
+    InvalidRacerCount,
+    #[msg("Multiplayer races don't support token wagers, events, rewards or seasons")]
+    MultiplayerUnsupported,
+    #[msg("Race is not a multiplayer race")]
+    NotMultiplayer,
+    #[msg("Race is full")]
+    RaceFull,
+    #[msg("A multiplayer race needs at least 2 racers")]
+    NotEnoughRacers,
+    #[msg("Derby player account is required for a multiplayer race")]
+    MissingDerbyPlayer,
+    #[msg("Derby player account does not belong to this race")]
+    InvalidDerbyPlayer,
+    #[msg("Racer has already finished")]
+    RacerAlreadyFinished,
+    #[msg("A derby player's rent payer is missing from the remaining accounts")]
+    MissingRentPayer,
+    #[msg("Track ghost is required to close a Finished race")]
+    MissingTrackGhost,
+    #[msg("Track has no ghost run yet")]
+    NoGhostRecord,
+    #[msg("Ghost races need the ghost's fixed seed")]
+    GhostSeedMismatch,
+    #[msg("Token-wagered races need a commit-reveal seed")]
+    VerifiableSeedRequired,
+    #[msg("Race seed is not commit-reveal")]
+    SeedNotCommitReveal,
+    #[msg("Player seed was already contributed")]
+    SeedAlreadyContributed,
+    #[msg("Player must contribute a seed before the reveal")]
+    SeedNotContributed,
+    #[msg("Revealed secret does not match the commitment")]
+    SeedCommitmentMismatch,
+    #[msg("Race seed was already fulfilled")]
+    SeedAlreadyFulfilled,
+    #[msg("Race seed has not been fulfilled")]
+    SeedNotFulfilled,
+    #[msg("Item or obstacle is not on the racer's current track segment")]
+    ItemNotReachable,
+    #[msg("Obstacle index is not on this track's layout")]
+    InvalidObstacleIndex,
+    #[msg("Obstacle was already hit within the debounce window")]
+    CollisionDebounced,
+    #[msg("Checkpoint passed faster than the minimum split")]
+    CheckpointTooSoon,
+    #[msg("Commit-reveal races have no shared track — pass no track accounts")]
+    NoSharedTrack,
+    #[msg("No racer has finished — cancel the race instead")]
+    NoFinishers,
+    #[msg("Player profile is required to close a single-racer race")]
+    MissingPlayerProfile,
+    #[msg("Track accounts are only taken when closing a Finished race")]
+    UnexpectedTrackAccount,
+    #[msg("Race can't be expired before DERBY_EXPIRY_SECS have passed")]
+    RaceNotExpired,
+    #[msg("Result settled in the current season must be recorded there before close")]
+    SeasonReportPending,
 }
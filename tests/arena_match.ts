@@ -1,21 +1,39 @@
 import * as anchor from "@coral-xyz/anchor";
 import { PublicKey, Keypair, SystemProgram } from "@solana/web3.js";
 import { expect } from "chai";
+import {
+  ASSOCIATED_TOKEN_PROGRAM_ID,
+  TOKEN_PROGRAM_ID,
+  createMint,
+  getAccount,
+  getAssociatedTokenAddressSync,
+  getOrCreateAssociatedTokenAccount,
+  mintTo,
+} from "@solana/spl-token";
 import { createHash } from "crypto";
+import { GetCommitmentSignature, MAGIC_CONTEXT_ID, MAGIC_PROGRAM_ID } from "@magicblock-labs/ephemeral-rollups-sdk";
 
 // Program ID
 const PROGRAM_ID = new PublicKey("45A9Qb4YVeWwL35aBCTcT4bcfsgcFUW3GUHAbvhNJJGi");
 
 // Session Keys program ID (used as placeholder for None)
+const DELEGATION_PROGRAM_ID = new PublicKey("DELeGGvXpWV2fqJUhqcF5ZSYMS4JTLjteaAMARRSaeSh");
 const SESSION_KEYS_PROGRAM_ID = new PublicKey("KeyspBbvfpjBRDMu6FJR3bTkfvBsGNHPJBXoKPmecnT");
 
 // Seeds
 const MATCH_SEED = Buffer.from("arena_match");
 const PLAYER_STATE_SEED = Buffer.from("player_state");
+const ESCROW_SEED = Buffer.from("match_escrow");
+const MATCH_TOKEN_VAULT_SEED = Buffer.from("match_token_vault");
+const PROFILE_SEED = Buffer.from("player_profile");
+const SEASON_SEED = Buffer.from("season");
+const SEASON_STATS_SEED = Buffer.from("season_stats");
+const TOURNAMENT_SEED = Buffer.from("tournament");
 
 // Game constants (must match program)
 const HP_PER_ROUND = 3;
 const DAMAGE_COOLDOWN_TICKS = 10;
+const ACHIEVEMENT_FLAWLESS_ROUND = 1 << 0;
 
 // ── Helpers ─────────────────────────────────────────────────────────────────
 
@@ -32,6 +50,45 @@ function findMatchPda(matchId: number): [PublicKey, number] {
   );
 }
 
+function findEscrowPda(matchId: number): [PublicKey, number] {
+  return PublicKey.findProgramAddressSync(
+    [ESCROW_SEED, matchIdToBytes(matchId)],
+    PROGRAM_ID
+  );
+}
+
+function findTokenVaultPda(matchId: number): [PublicKey, number] {
+  return PublicKey.findProgramAddressSync(
+    [MATCH_TOKEN_VAULT_SEED, matchIdToBytes(matchId)],
+    PROGRAM_ID
+  );
+}
+
+function findProfilePda(wallet: PublicKey): [PublicKey, number] {
+  return PublicKey.findProgramAddressSync([PROFILE_SEED, wallet.toBuffer()], PROGRAM_ID);
+}
+
+function seasonIdToBytes(seasonId: number): Buffer {
+  const buf = Buffer.alloc(4);
+  buf.writeUInt32LE(seasonId);
+  return buf;
+}
+
+function findSeasonPda(seasonId: number): [PublicKey, number] {
+  return PublicKey.findProgramAddressSync([SEASON_SEED, seasonIdToBytes(seasonId)], PROGRAM_ID);
+}
+
+function findSeasonStatsPda(seasonId: number, wallet: PublicKey): [PublicKey, number] {
+  return PublicKey.findProgramAddressSync(
+    [SEASON_STATS_SEED, seasonIdToBytes(seasonId), wallet.toBuffer()],
+    PROGRAM_ID
+  );
+}
+
+function findTournamentPda(tournamentId: number): [PublicKey, number] {
+  return PublicKey.findProgramAddressSync([TOURNAMENT_SEED, matchIdToBytes(tournamentId)], PROGRAM_ID);
+}
+
 function findPlayerStatePda(matchId: number, player: PublicKey): [PublicKey, number] {
   return PublicKey.findProgramAddressSync(
     [PLAYER_STATE_SEED, matchIdToBytes(matchId), player.toBuffer()],
@@ -59,6 +116,26 @@ interface ArenaMatchState {
   winner: PublicKey;
   createdAt: bigint;
   settledAt: bigint;
+  rentPayer: PublicKey;
+  closeFee: bigint;
+  strict: boolean;
+  arbiter: PublicKey;
+  disputeWindowSecs: number;
+  disputeStatus: number;
+  stakeLamports: bigint;
+  paidOut: boolean;
+  tokenMint: PublicKey;
+  tokenStake: bigint;
+  player1TokensDeposited: boolean;
+  player2TokensDeposited: boolean;
+  tokensPaidOut: boolean;
+  forfeitedBy: PublicKey;
+  ranked: boolean;
+  seasonRecorded: boolean;
+  player1FlawlessRounds: number;
+  player2FlawlessRounds: number;
+  tournament: PublicKey;
+  tournamentReported: boolean;
 }
 
 function decodeMatchState(data: Buffer): ArenaMatchState {
@@ -79,12 +156,36 @@ function decodeMatchState(data: Buffer): ArenaMatchState {
   const lastP2DamageTick = data.readUInt32LE(offset); offset += 4;
   const winner = new PublicKey(data.subarray(offset, offset + 32)); offset += 32;
   const createdAt = data.readBigInt64LE(offset); offset += 8;
-  const settledAt = data.readBigInt64LE(offset);
+  const settledAt = data.readBigInt64LE(offset); offset += 8;
+  const rentPayer = new PublicKey(data.subarray(offset, offset + 32)); offset += 32;
+  const closeFee = data.readBigUInt64LE(offset); offset += 8;
+  const strict = data.readUInt8(offset) === 1; offset += 1;
+  const arbiter = new PublicKey(data.subarray(offset, offset + 32)); offset += 32;
+  const disputeWindowSecs = data.readUInt32LE(offset); offset += 4;
+  const disputeStatus = data.readUInt8(offset); offset += 1;
+  const stakeLamports = data.readBigUInt64LE(offset); offset += 8;
+  const paidOut = data.readUInt8(offset) === 1; offset += 1;
+  const tokenMint = new PublicKey(data.subarray(offset, offset + 32)); offset += 32;
+  const tokenStake = data.readBigUInt64LE(offset); offset += 8;
+  const player1TokensDeposited = data.readUInt8(offset) === 1; offset += 1;
+  const player2TokensDeposited = data.readUInt8(offset) === 1; offset += 1;
+  const tokensPaidOut = data.readUInt8(offset) === 1; offset += 1;
+  const forfeitedBy = new PublicKey(data.subarray(offset, offset + 32)); offset += 32;
+  const ranked = data.readUInt8(offset) === 1; offset += 1;
+  const seasonRecorded = data.readUInt8(offset) === 1; offset += 1;
+  const player1FlawlessRounds = data.readUInt8(offset); offset += 1;
+  const player2FlawlessRounds = data.readUInt8(offset); offset += 1;
+  const tournament = new PublicKey(data.subarray(offset, offset + 32)); offset += 32;
+  const tournamentReported = data.readUInt8(offset) === 1;
   return {
     matchId, gameServer, player1, player2, status, currentRound,
     player1RoundsWon, player2RoundsWon, player1Hp, player2Hp,
     currentTick, roundStartTick, lastP1DamageTick, lastP2DamageTick,
-    winner, createdAt, settledAt,
+    winner, createdAt, settledAt, rentPayer, closeFee, strict,
+    arbiter, disputeWindowSecs, disputeStatus, stakeLamports, paidOut,
+    tokenMint, tokenStake, player1TokensDeposited, player2TokensDeposited, tokensPaidOut,
+    forfeitedBy, ranked, seasonRecorded, player1FlawlessRounds, player2FlawlessRounds,
+    tournament, tournamentReported,
   };
 }
 
@@ -96,6 +197,7 @@ interface PlayerStateData {
   attacking: boolean;
   lastTick: number;
   inputCount: bigint;
+  rentPayer: PublicKey;
 }
 
 function decodePlayerState(data: Buffer): PlayerStateData {
@@ -106,8 +208,141 @@ function decodePlayerState(data: Buffer): PlayerStateData {
   const dy = data.readInt8(offset); offset += 1;
   const attacking = data.readUInt8(offset) === 1; offset += 1;
   const lastTick = data.readUInt32LE(offset); offset += 4;
-  const inputCount = data.readBigUInt64LE(offset);
-  return { matchId, player, dx, dy, attacking, lastTick, inputCount };
+  const inputCount = data.readBigUInt64LE(offset); offset += 8;
+  const rentPayer = new PublicKey(data.subarray(offset, offset + 32));
+  return { matchId, player, dx, dy, attacking, lastTick, inputCount, rentPayer };
+}
+
+interface PlayerProfileData {
+  wallet: PublicKey;
+  arenaWins: number;
+  arenaLosses: number;
+  arenaDraws: number;
+  arenaForfeits: number;
+  arenaRoundsWon: number;
+  derbyRacesFinished: number;
+  derbyBestFinishTick: number;
+  derbyTotalGold: number;
+  derbyTotalCollisions: number;
+  arenaRating: number;
+  arenaRankedGames: number;
+  achievements: number;
+  arenaWinStreak: number;
+}
+
+function decodePlayerProfile(data: Buffer): PlayerProfileData {
+  let offset = 8;
+  const wallet = new PublicKey(data.subarray(offset, offset + 32)); offset += 32;
+  const arenaWins = data.readUInt32LE(offset); offset += 4;
+  const arenaLosses = data.readUInt32LE(offset); offset += 4;
+  const arenaDraws = data.readUInt32LE(offset); offset += 4;
+  const arenaForfeits = data.readUInt32LE(offset); offset += 4;
+  const arenaRoundsWon = data.readUInt32LE(offset); offset += 4;
+  const derbyRacesFinished = data.readUInt32LE(offset); offset += 4;
+  const derbyBestFinishTick = data.readUInt32LE(offset); offset += 4;
+  const derbyTotalGold = data.readUInt32LE(offset); offset += 4;
+  const derbyTotalCollisions = data.readUInt32LE(offset); offset += 4;
+  const arenaRating = data.readUInt16LE(offset); offset += 2;
+  const arenaRankedGames = data.readUInt32LE(offset); offset += 4;
+  const achievements = data.readUInt32LE(offset); offset += 4;
+  const arenaWinStreak = data.readUInt32LE(offset);
+  return {
+    wallet, arenaWins, arenaLosses, arenaDraws, arenaForfeits, arenaRoundsWon,
+    derbyRacesFinished, derbyBestFinishTick, derbyTotalGold, derbyTotalCollisions,
+    arenaRating, arenaRankedGames, achievements, arenaWinStreak,
+  };
+}
+
+interface SeasonStatsData {
+  seasonId: number;
+  wallet: PublicKey;
+  arenaWins: number;
+  arenaLosses: number;
+  arenaDraws: number;
+  derbyRacesFinished: number;
+  derbyBestFinishTick: number;
+}
+
+function decodeSeasonStats(data: Buffer): SeasonStatsData {
+  let offset = 8;
+  const seasonId = data.readUInt32LE(offset); offset += 4;
+  const wallet = new PublicKey(data.subarray(offset, offset + 32)); offset += 32;
+  const arenaWins = data.readUInt32LE(offset); offset += 4;
+  const arenaLosses = data.readUInt32LE(offset); offset += 4;
+  const arenaDraws = data.readUInt32LE(offset); offset += 4;
+  const derbyRacesFinished = data.readUInt32LE(offset); offset += 4;
+  const derbyBestFinishTick = data.readUInt32LE(offset);
+  return { seasonId, wallet, arenaWins, arenaLosses, arenaDraws, derbyRacesFinished, derbyBestFinishTick };
+}
+
+const SEASON_LEADERBOARD_SIZE = 10;
+
+// Season header + arena leaderboard (player, wins); derby board not needed here
+function decodeSeasonArenaLeaderboard(data: Buffer): { frozen: boolean; entries: { player: PublicKey; wins: number }[] } {
+  let offset = 8 + 4 + 8 + 8;
+  const frozen = data.readUInt8(offset) === 1; offset += 1;
+  const entries = [];
+  for (let i = 0; i < SEASON_LEADERBOARD_SIZE; i++) {
+    const player = new PublicKey(data.subarray(offset, offset + 32)); offset += 32;
+    const wins = data.readUInt32LE(offset); offset += 4;
+    if (!player.equals(PublicKey.default)) entries.push({ player, wins });
+  }
+  return { frozen, entries };
+}
+
+interface TournamentData {
+  tournamentId: bigint;
+  status: number;
+  entrantCount: number;
+  bracketSize: number;
+  champion: PublicKey;
+  runnerUp: PublicKey;
+  paidOut: boolean;
+  format: number;
+  totalRounds: number;
+  round: number;
+  pairings: [number, number][];
+  points: number[];
+  buchholz: number[];
+}
+
+const TOURNAMENT_MAX_ENTRANTS = 32;
+const TOURNAMENT_BRACKET_NODES = 2 * TOURNAMENT_MAX_ENTRANTS - 1;
+const TOURNAMENT_MAX_PAIRINGS = TOURNAMENT_MAX_ENTRANTS / 2;
+const TOURNAMENT_NO_ENTRANT = 255;
+const TournamentFormat = { SingleElimination: 0, Swiss: 1, RoundRobin: 2 };
+
+function decodeTournament(data: Buffer): TournamentData {
+  let offset = 8;
+  const tournamentId = data.readBigUInt64LE(offset); offset += 8;
+  offset += 32 + 8; // game_server, base_match_id
+  const status = data.readUInt8(offset); offset += 1;
+  offset += 1; // max_entrants
+  const entrantCount = data.readUInt8(offset); offset += 1;
+  const bracketSize = data.readUInt8(offset); offset += 1;
+  offset += 8 + 4 + 45; // entry_fee, prize_split_bps, params
+  offset += 32 * (TOURNAMENT_MAX_ENTRANTS + TOURNAMENT_BRACKET_NODES); // entrants, bracket
+  const champion = new PublicKey(data.subarray(offset, offset + 32)); offset += 32;
+  const runnerUp = new PublicKey(data.subarray(offset, offset + 32)); offset += 32;
+  const paidOut = data.readUInt8(offset) === 1; offset += 1;
+  const format = data.readUInt8(offset); offset += 1;
+  const totalRounds = data.readUInt8(offset); offset += 1;
+  const round = data.readUInt8(offset); offset += 1;
+  const pairingCount = data.readUInt8(offset); offset += 1;
+  offset += 2 + 4; // reported_mask, byes
+  const pairings: [number, number][] = [];
+  for (let i = 0; i < TOURNAMENT_MAX_PAIRINGS; i++) {
+    if (i < pairingCount) pairings.push([data.readUInt8(offset), data.readUInt8(offset + 1)]);
+    offset += 2;
+  }
+  const points = [...data.subarray(offset, offset + entrantCount)]; offset += TOURNAMENT_MAX_ENTRANTS;
+  offset += 4 * TOURNAMENT_MAX_ENTRANTS; // played
+  const buchholz = [];
+  for (let i = 0; i < entrantCount; i++) buchholz.push(data.readUInt16LE(offset + 2 * i));
+  return {
+    tournamentId, status, entrantCount, bracketSize, champion, runnerUp, paidOut,
+    format, totalRounds, round, pairings, points, buchholz,
+  };
 }
 
 // ── Instruction Builders ────────────────────────────────────────────────────
@@ -116,15 +351,178 @@ function disc(name: string): Buffer {
   return createHash("sha256").update(`global:${name}`).digest().subarray(0, 8);
 }
 
+interface SettlementParams {
+  closeFee: number;
+  strict: boolean;
+  arbiter: PublicKey;
+  disputeWindowSecs: number;
+}
+
+function encodeSettlementParams(params: Partial<SettlementParams> = {}): Buffer {
+  const buf = Buffer.alloc(8 + 1 + 32 + 4);
+  let off = 0;
+  buf.writeBigUInt64LE(BigInt(params.closeFee ?? 0), off); off += 8;
+  buf.writeUInt8(params.strict ? 1 : 0, off); off += 1;
+  (params.arbiter ?? PublicKey.default).toBuffer().copy(buf, off); off += 32;
+  buf.writeUInt32LE(params.disputeWindowSecs ?? 0, off);
+  return buf;
+}
+
+interface TokenWager {
+  mint: PublicKey;
+  amount: number;
+}
+
+// Option<TokenWager>: 0 = None, 1 + mint + u64 amount = Some
+function encodeTokenWager(wager?: TokenWager): Buffer {
+  if (!wager) return Buffer.from([0]);
+  const buf = Buffer.alloc(1 + 32 + 8);
+  buf.writeUInt8(1, 0);
+  wager.mint.toBuffer().copy(buf, 1);
+  buf.writeBigUInt64LE(BigInt(wager.amount), 33);
+  return buf;
+}
+
+const CONFIG_SEED = Buffer.from("arena_config");
+const BPF_LOADER_UPGRADEABLE_ID = new PublicKey("BPFLoaderUpgradeab1e11111111111111111111111");
+const TEST_FEE_BPS = 250;
+
+function findConfigPda(): [PublicKey, number] {
+  return PublicKey.findProgramAddressSync([CONFIG_SEED], PROGRAM_ID);
+}
+
+function buildInitializeConfigIx(
+  admin: PublicKey,
+  treasury: PublicKey,
+  feeBps: number,
+): anchor.web3.TransactionInstruction {
+  const [configPda] = findConfigPda();
+  const [programData] = PublicKey.findProgramAddressSync([PROGRAM_ID.toBuffer()], BPF_LOADER_UPGRADEABLE_ID);
+  const data = Buffer.alloc(8 + 32 + 2);
+  disc("initialize_config").copy(data, 0);
+  treasury.toBuffer().copy(data, 8);
+  data.writeUInt16LE(feeBps, 40);
+
+  return new anchor.web3.TransactionInstruction({
+    programId: PROGRAM_ID,
+    keys: [
+      { pubkey: configPda, isSigner: false, isWritable: true },
+      { pubkey: admin, isSigner: true, isWritable: true },
+      { pubkey: PROGRAM_ID, isSigner: false, isWritable: false },
+      { pubkey: programData, isSigner: false, isWritable: false },
+      { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+    ],
+    data,
+  });
+}
+
+function buildUpdateConfigIx(
+  admin: PublicKey,
+  newAdmin: PublicKey,
+  treasury: PublicKey,
+  feeBps: number,
+): anchor.web3.TransactionInstruction {
+  const [configPda] = findConfigPda();
+  const data = Buffer.alloc(8 + 32 + 32 + 2);
+  disc("update_config").copy(data, 0);
+  newAdmin.toBuffer().copy(data, 8);
+  treasury.toBuffer().copy(data, 40);
+  data.writeUInt16LE(feeBps, 72);
+
+  return new anchor.web3.TransactionInstruction({
+    programId: PROGRAM_ID,
+    keys: [
+      { pubkey: configPda, isSigner: false, isWritable: true },
+      { pubkey: admin, isSigner: true, isWritable: false },
+    ],
+    data,
+  });
+}
+
+// Creates the config on first run, otherwise points it at this run's treasury.
+async function setupConfig(
+  provider: anchor.AnchorProvider,
+  admin: Keypair,
+  treasury: PublicKey,
+): Promise<void> {
+  const [configPda] = findConfigPda();
+  const existing = await provider.connection.getAccountInfo(configPda);
+  const ix = existing
+    ? buildUpdateConfigIx(admin.publicKey, admin.publicKey, treasury, TEST_FEE_BPS)
+    : buildInitializeConfigIx(admin.publicKey, treasury, TEST_FEE_BPS);
+  await provider.sendAndConfirm(new anchor.web3.Transaction().add(ix), [admin]);
+}
+
+// ArenaConfig.current_season sits after admin, treasury, fee_bps, reward_mint, reward_per_gold
+async function currentSeason(provider: anchor.AnchorProvider): Promise<number> {
+  const acct = await provider.connection.getAccountInfo(findConfigPda()[0]);
+  return acct!.data.readUInt32LE(8 + 32 + 32 + 2 + 32 + 8);
+}
+
+// The config's current season, which the close builders pass — kept in step
+// by the before hook and the season test
+let runningSeason = 0;
+
+function seasonAccounts(): { pubkey: PublicKey; isSigner: boolean; isWritable: boolean }[] {
+  return [
+    { pubkey: findConfigPda()[0], isSigner: false, isWritable: false },
+    // season = None before the first season
+    runningSeason > 0
+      ? { pubkey: findSeasonPda(runningSeason)[0], isSigner: false, isWritable: false }
+      : { pubkey: PROGRAM_ID, isSigner: false, isWritable: false },
+  ];
+}
+
+function buildStartSeasonIx(
+  admin: PublicKey,
+  currentSeasonId: number,
+  startTs: number,
+  endTs: number,
+): anchor.web3.TransactionInstruction {
+  const seasonId = currentSeasonId + 1;
+  const data = Buffer.alloc(8 + 4 + 8 + 8);
+  disc("start_season").copy(data, 0);
+  data.writeUInt32LE(seasonId, 8);
+  data.writeBigInt64LE(BigInt(startTs), 12);
+  data.writeBigInt64LE(BigInt(endTs), 20);
+
+  return new anchor.web3.TransactionInstruction({
+    programId: PROGRAM_ID,
+    keys: [
+      { pubkey: findConfigPda()[0], isSigner: false, isWritable: true },
+      // previous_season = None before the first season
+      currentSeasonId > 0
+        ? { pubkey: findSeasonPda(currentSeasonId)[0], isSigner: false, isWritable: true }
+        : { pubkey: PROGRAM_ID, isSigner: false, isWritable: false },
+      { pubkey: findSeasonPda(seasonId)[0], isSigner: false, isWritable: true },
+      { pubkey: admin, isSigner: true, isWritable: true },
+      { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+    ],
+    data,
+  });
+}
+
+function protocolFee(pot: number): number {
+  return Math.floor((pot * TEST_FEE_BPS) / 10_000);
+}
+
 function buildCreateMatchIx(
   matchId: number,
   gameServer: PublicKey,
   player1: PublicKey,
+  params: Partial<SettlementParams> = {},
+  stakeLamports = 0,
+  tokenWager?: TokenWager,
+  ranked = false,
 ): anchor.web3.TransactionInstruction {
   const [matchPda] = findMatchPda(matchId);
-  const data = Buffer.alloc(8 + 8);
-  disc("create_match").copy(data, 0);
-  data.writeBigUInt64LE(BigInt(matchId), 8);
+  const [escrowPda] = findEscrowPda(matchId);
+  const matchIdBuf = Buffer.alloc(8);
+  matchIdBuf.writeBigUInt64LE(BigInt(matchId));
+  const stakeBuf = Buffer.alloc(8);
+  stakeBuf.writeBigUInt64LE(BigInt(stakeLamports));
+  const data = Buffer.concat([disc("create_match"), matchIdBuf, encodeSettlementParams(params), stakeBuf,
+    encodeTokenWager(tokenWager), Buffer.from([ranked ? 1 : 0])]);
 
   return new anchor.web3.TransactionInstruction({
     programId: PROGRAM_ID,
@@ -133,6 +531,10 @@ function buildCreateMatchIx(
       { pubkey: gameServer, isSigner: false, isWritable: false },
       { pubkey: player1, isSigner: true, isWritable: true },
       { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+      // escrow = None unless staked — pass program ID as placeholder
+      stakeLamports > 0
+        ? { pubkey: escrowPda, isSigner: false, isWritable: true }
+        : { pubkey: PROGRAM_ID, isSigner: false, isWritable: false },
     ],
     data,
   });
@@ -162,8 +564,10 @@ function buildJoinMatchIx(
   matchId: number,
   player2: PublicKey,
   payer: PublicKey,
+  staked = false,
 ): anchor.web3.TransactionInstruction {
   const [matchPda] = findMatchPda(matchId);
+  const [escrowPda] = findEscrowPda(matchId);
   const data = Buffer.alloc(8 + 8);
   disc("join_match").copy(data, 0);
   data.writeBigUInt64LE(BigInt(matchId), 8);
@@ -176,6 +580,45 @@ function buildJoinMatchIx(
       { pubkey: payer, isSigner: true, isWritable: true },
       // session_token = None — pass program ID as placeholder
       { pubkey: PROGRAM_ID, isSigner: false, isWritable: false },
+      // escrow + system_program — only for staked matches
+      ...(staked
+        ? [
+            { pubkey: escrowPda, isSigner: false, isWritable: true },
+            { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+          ]
+        : [
+            { pubkey: PROGRAM_ID, isSigner: false, isWritable: false },
+            { pubkey: PROGRAM_ID, isSigner: false, isWritable: false },
+          ]),
+    ],
+    data,
+  });
+}
+
+function buildPayoutIx(
+  matchId: number,
+  player1: PublicKey,
+  player2: PublicKey,
+  payer: PublicKey,
+  treasury: PublicKey,
+): anchor.web3.TransactionInstruction {
+  const [matchPda] = findMatchPda(matchId);
+  const [escrowPda] = findEscrowPda(matchId);
+  const [configPda] = findConfigPda();
+  const data = Buffer.alloc(8 + 8);
+  disc("payout").copy(data, 0);
+  data.writeBigUInt64LE(BigInt(matchId), 8);
+
+  return new anchor.web3.TransactionInstruction({
+    programId: PROGRAM_ID,
+    keys: [
+      { pubkey: matchPda, isSigner: false, isWritable: true },
+      { pubkey: escrowPda, isSigner: false, isWritable: true },
+      { pubkey: player1, isSigner: false, isWritable: true },
+      { pubkey: player2, isSigner: false, isWritable: true },
+      { pubkey: payer, isSigner: true, isWritable: false },
+      { pubkey: configPda, isSigner: false, isWritable: false },
+      { pubkey: treasury, isSigner: false, isWritable: true },
     ],
     data,
   });
@@ -202,6 +645,26 @@ function buildServerActionIx(
   });
 }
 
+// end_match: #[commit] appends magic_program and magic_context; the
+// delegated player states ride along as remaining accounts
+function buildEndMatchIx(
+  matchId: number,
+  gameServer: PublicKey,
+  playerStates: PublicKey[],
+): anchor.web3.TransactionInstruction {
+  return new anchor.web3.TransactionInstruction({
+    programId: PROGRAM_ID,
+    keys: [
+      { pubkey: findMatchPda(matchId)[0], isSigner: false, isWritable: true },
+      { pubkey: gameServer, isSigner: true, isWritable: true },
+      { pubkey: MAGIC_PROGRAM_ID, isSigner: false, isWritable: false },
+      { pubkey: MAGIC_CONTEXT_ID, isSigner: false, isWritable: true },
+      ...playerStates.map((pubkey) => ({ pubkey, isSigner: false, isWritable: true })),
+    ],
+    data: Buffer.concat([disc("end_match"), matchIdToBytes(matchId)]),
+  });
+}
+
 function buildApplyDamageIx(
   matchId: number,
   gameServer: PublicKey,
@@ -222,6 +685,88 @@ function buildForfeitIx(
   return buildServerActionIx("forfeit", matchId, gameServer, extra);
 }
 
+// ServerEvent enum variants (borsh: u8 tag + fields)
+type ServerEvent =
+  | { kind: "advanceTick"; tick: number }
+  | { kind: "startRound" }
+  | { kind: "applyDamage"; targetSlot: number }
+  | { kind: "endRound" }
+  | { kind: "forfeit"; forfeiterSlot: number };
+
+function encodeServerEvent(event: ServerEvent): Buffer {
+  switch (event.kind) {
+    case "advanceTick": {
+      const buf = Buffer.alloc(5);
+      buf.writeUInt8(0, 0);
+      buf.writeUInt32LE(event.tick, 1);
+      return buf;
+    }
+    case "startRound":
+      return Buffer.from([1]);
+    case "applyDamage":
+      return Buffer.from([2, event.targetSlot]);
+    case "endRound":
+      return Buffer.from([3]);
+    case "forfeit":
+      return Buffer.from([4, event.forfeiterSlot]);
+  }
+}
+
+function buildServerTickIx(
+  matchId: number,
+  gameServer: PublicKey,
+  events: ServerEvent[],
+): anchor.web3.TransactionInstruction {
+  const len = Buffer.alloc(4);
+  len.writeUInt32LE(events.length);
+  const extra = Buffer.concat([len, ...events.map(encodeServerEvent)]);
+  return buildServerActionIx("server_tick", matchId, gameServer, extra);
+}
+
+function buildRaiseDisputeIx(
+  matchId: number,
+  challenger: PublicKey,
+): anchor.web3.TransactionInstruction {
+  const [matchPda] = findMatchPda(matchId);
+  const data = Buffer.alloc(8 + 8);
+  disc("raise_dispute").copy(data, 0);
+  data.writeBigUInt64LE(BigInt(matchId), 8);
+
+  return new anchor.web3.TransactionInstruction({
+    programId: PROGRAM_ID,
+    keys: [
+      { pubkey: matchPda, isSigner: false, isWritable: true },
+      { pubkey: challenger, isSigner: true, isWritable: false },
+    ],
+    data,
+  });
+}
+
+// DisputeResolution: 0 = Confirm, 1 = OverrideWinner { winner }, 2 = Void
+function buildResolveDisputeIx(
+  matchId: number,
+  arbiter: PublicKey,
+  resolution: { kind: "confirm" } | { kind: "overrideWinner"; winner: PublicKey } | { kind: "void" },
+): anchor.web3.TransactionInstruction {
+  const [matchPda] = findMatchPda(matchId);
+  const matchIdBuf = Buffer.alloc(8);
+  matchIdBuf.writeBigUInt64LE(BigInt(matchId));
+  const resolutionBuf =
+    resolution.kind === "confirm" ? Buffer.from([0])
+    : resolution.kind === "overrideWinner" ? Buffer.concat([Buffer.from([1]), resolution.winner.toBuffer()])
+    : Buffer.from([2]);
+  const data = Buffer.concat([disc("resolve_dispute"), matchIdBuf, resolutionBuf]);
+
+  return new anchor.web3.TransactionInstruction({
+    programId: PROGRAM_ID,
+    keys: [
+      { pubkey: matchPda, isSigner: false, isWritable: true },
+      { pubkey: arbiter, isSigner: true, isWritable: false },
+    ],
+    data,
+  });
+}
+
 function buildSubmitInputIx(
   matchId: number,
   player: PublicKey,
@@ -271,6 +816,73 @@ function buildCancelMatchIx(
     keys: [
       { pubkey: matchPda, isSigner: false, isWritable: true },
       { pubkey: player1, isSigner: true, isWritable: true },
+      // escrow = None (unstaked match)
+      { pubkey: PROGRAM_ID, isSigner: false, isWritable: false },
+      // token_mint, token_vault, player1_token_account, token_program = None
+      { pubkey: PROGRAM_ID, isSigner: false, isWritable: false },
+      { pubkey: PROGRAM_ID, isSigner: false, isWritable: false },
+      { pubkey: PROGRAM_ID, isSigner: false, isWritable: false },
+      { pubkey: PROGRAM_ID, isSigner: false, isWritable: false },
+    ],
+    data,
+  });
+}
+
+function buildDepositMatchTokensIx(
+  matchId: number,
+  mint: PublicKey,
+  depositor: PublicKey,
+): anchor.web3.TransactionInstruction {
+  const [matchPda] = findMatchPda(matchId);
+  const [vaultPda] = findTokenVaultPda(matchId);
+  const data = Buffer.alloc(8 + 8);
+  disc("deposit_match_tokens").copy(data, 0);
+  data.writeBigUInt64LE(BigInt(matchId), 8);
+
+  return new anchor.web3.TransactionInstruction({
+    programId: PROGRAM_ID,
+    keys: [
+      { pubkey: matchPda, isSigner: false, isWritable: true },
+      { pubkey: mint, isSigner: false, isWritable: false },
+      { pubkey: vaultPda, isSigner: false, isWritable: true },
+      { pubkey: getAssociatedTokenAddressSync(mint, depositor), isSigner: false, isWritable: true },
+      { pubkey: depositor, isSigner: true, isWritable: true },
+      { pubkey: TOKEN_PROGRAM_ID, isSigner: false, isWritable: false },
+      { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+    ],
+    data,
+  });
+}
+
+function buildPayoutMatchTokensIx(
+  matchId: number,
+  mint: PublicKey,
+  player1: PublicKey,
+  player2: PublicKey,
+  payer: PublicKey,
+  treasury: PublicKey,
+): anchor.web3.TransactionInstruction {
+  const [matchPda] = findMatchPda(matchId);
+  const [vaultPda] = findTokenVaultPda(matchId);
+  const [configPda] = findConfigPda();
+  const data = Buffer.alloc(8 + 8);
+  disc("payout_match_tokens").copy(data, 0);
+  data.writeBigUInt64LE(BigInt(matchId), 8);
+
+  return new anchor.web3.TransactionInstruction({
+    programId: PROGRAM_ID,
+    keys: [
+      { pubkey: matchPda, isSigner: false, isWritable: true },
+      { pubkey: mint, isSigner: false, isWritable: false },
+      { pubkey: vaultPda, isSigner: false, isWritable: true },
+      { pubkey: getAssociatedTokenAddressSync(mint, player1), isSigner: false, isWritable: true },
+      { pubkey: getAssociatedTokenAddressSync(mint, player2), isSigner: false, isWritable: true },
+      { pubkey: player1, isSigner: false, isWritable: true },
+      { pubkey: payer, isSigner: true, isWritable: false },
+      { pubkey: TOKEN_PROGRAM_ID, isSigner: false, isWritable: false },
+      { pubkey: ASSOCIATED_TOKEN_PROGRAM_ID, isSigner: false, isWritable: false },
+      { pubkey: configPda, isSigner: false, isWritable: false },
+      { pubkey: getAssociatedTokenAddressSync(mint, treasury), isSigner: false, isWritable: true },
     ],
     data,
   });
@@ -279,6 +891,10 @@ function buildCancelMatchIx(
 function buildCloseMatchIx(
   matchId: number,
   payer: PublicKey,
+  rentPayer: PublicKey,
+  players: PublicKey[],
+  // The match's player1 and player2 (if joined), for their profiles
+  matchPlayers: PublicKey[] = players,
 ): anchor.web3.TransactionInstruction {
   const [matchPda] = findMatchPda(matchId);
   const data = Buffer.alloc(8 + 8);
@@ -290,6 +906,181 @@ function buildCloseMatchIx(
     keys: [
       { pubkey: matchPda, isSigner: false, isWritable: true },
       { pubkey: payer, isSigner: true, isWritable: true },
+      { pubkey: rentPayer, isSigner: false, isWritable: true },
+      { pubkey: findProfilePda(matchPlayers[0])[0], isSigner: false, isWritable: true },
+      // player2_profile = None when nobody joined
+      matchPlayers[1]
+        ? { pubkey: findProfilePda(matchPlayers[1])[0], isSigner: false, isWritable: true }
+        : { pubkey: PROGRAM_ID, isSigner: false, isWritable: false },
+      ...seasonAccounts(),
+      { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+      // remaining accounts: the match's PlayerState PDAs, then any other
+      // player-state rent payers
+      ...players
+        .filter((player) => !player.equals(rentPayer))
+        .map((player) => ({ pubkey: player, isSigner: false, isWritable: true })),
+      ...players.map((player) => ({
+        pubkey: findPlayerStatePda(matchId, player)[0],
+        isSigner: false,
+        isWritable: true,
+      })),
+    ],
+    data,
+  });
+}
+
+function buildRecordMatchSeasonIx(
+  matchId: number,
+  seasonId: number,
+  player1: PublicKey,
+  player2: PublicKey,
+  payer: PublicKey,
+): anchor.web3.TransactionInstruction {
+  const data = Buffer.alloc(8 + 8);
+  disc("record_match_season").copy(data, 0);
+  data.writeBigUInt64LE(BigInt(matchId), 8);
+
+  return new anchor.web3.TransactionInstruction({
+    programId: PROGRAM_ID,
+    keys: [
+      { pubkey: findMatchPda(matchId)[0], isSigner: false, isWritable: true },
+      { pubkey: findSeasonPda(seasonId)[0], isSigner: false, isWritable: true },
+      { pubkey: findSeasonStatsPda(seasonId, player1)[0], isSigner: false, isWritable: true },
+      { pubkey: findSeasonStatsPda(seasonId, player2)[0], isSigner: false, isWritable: true },
+      { pubkey: payer, isSigner: true, isWritable: true },
+      { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+    ],
+    data,
+  });
+}
+
+function buildCreateTournamentIx(
+  tournamentId: number,
+  gameServer: PublicKey,
+  baseMatchId: number,
+  maxEntrants: number,
+  entryFee: number,
+  prizeSplitBps: [number, number],
+  params: Partial<SettlementParams> = {},
+  format = TournamentFormat.SingleElimination,
+  rounds = 0,
+): anchor.web3.TransactionInstruction {
+  const args = Buffer.alloc(8 + 8 + 1 + 8 + 4);
+  let off = 0;
+  args.writeBigUInt64LE(BigInt(tournamentId), off); off += 8;
+  args.writeBigUInt64LE(BigInt(baseMatchId), off); off += 8;
+  args.writeUInt8(maxEntrants, off); off += 1;
+  args.writeBigUInt64LE(BigInt(entryFee), off); off += 8;
+  args.writeUInt16LE(prizeSplitBps[0], off); off += 2;
+  args.writeUInt16LE(prizeSplitBps[1], off);
+  const data = Buffer.concat([
+    disc("create_tournament"), args, encodeSettlementParams(params), Buffer.from([format, rounds]),
+  ]);
+
+  return new anchor.web3.TransactionInstruction({
+    programId: PROGRAM_ID,
+    keys: [
+      { pubkey: findTournamentPda(tournamentId)[0], isSigner: false, isWritable: true },
+      { pubkey: gameServer, isSigner: true, isWritable: true },
+      { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+    ],
+    data,
+  });
+}
+
+function buildJoinTournamentIx(tournamentId: number, entrant: PublicKey): anchor.web3.TransactionInstruction {
+  const data = Buffer.concat([disc("join_tournament"), matchIdToBytes(tournamentId)]);
+  return new anchor.web3.TransactionInstruction({
+    programId: PROGRAM_ID,
+    keys: [
+      { pubkey: findTournamentPda(tournamentId)[0], isSigner: false, isWritable: true },
+      { pubkey: entrant, isSigner: true, isWritable: true },
+      { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+    ],
+    data,
+  });
+}
+
+// start_tournament / cancel_tournament (cancel takes entrants as remaining accounts)
+function buildTournamentServerActionIx(
+  instructionName: string,
+  tournamentId: number,
+  gameServer: PublicKey,
+  entrants: PublicKey[] = [],
+): anchor.web3.TransactionInstruction {
+  const data = Buffer.concat([disc(instructionName), matchIdToBytes(tournamentId)]);
+  return new anchor.web3.TransactionInstruction({
+    programId: PROGRAM_ID,
+    keys: [
+      { pubkey: findTournamentPda(tournamentId)[0], isSigner: false, isWritable: true },
+      { pubkey: gameServer, isSigner: true, isWritable: false },
+      ...entrants.map((entrant) => ({ pubkey: entrant, isSigner: false, isWritable: true })),
+    ],
+    data,
+  });
+}
+
+function buildCreateTournamentMatchIx(
+  tournamentId: number,
+  node: number,
+  matchId: number,
+  gameServer: PublicKey,
+): anchor.web3.TransactionInstruction {
+  const data = Buffer.concat([disc("create_tournament_match"), matchIdToBytes(tournamentId), Buffer.from([node])]);
+  return new anchor.web3.TransactionInstruction({
+    programId: PROGRAM_ID,
+    keys: [
+      { pubkey: findTournamentPda(tournamentId)[0], isSigner: false, isWritable: false },
+      { pubkey: findMatchPda(matchId)[0], isSigner: false, isWritable: true },
+      { pubkey: gameServer, isSigner: true, isWritable: true },
+      { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+    ],
+    data,
+  });
+}
+
+function buildReportTournamentMatchIx(
+  tournamentId: number,
+  node: number,
+  matchId: number,
+): anchor.web3.TransactionInstruction {
+  const data = Buffer.concat([disc("report_tournament_match"), matchIdToBytes(tournamentId), Buffer.from([node])]);
+  return new anchor.web3.TransactionInstruction({
+    programId: PROGRAM_ID,
+    keys: [
+      { pubkey: findTournamentPda(tournamentId)[0], isSigner: false, isWritable: true },
+      { pubkey: findMatchPda(matchId)[0], isSigner: false, isWritable: true },
+    ],
+    data,
+  });
+}
+
+function buildCloseTournamentIx(tournamentId: number, gameServer: PublicKey): anchor.web3.TransactionInstruction {
+  return new anchor.web3.TransactionInstruction({
+    programId: PROGRAM_ID,
+    keys: [
+      { pubkey: findTournamentPda(tournamentId)[0], isSigner: false, isWritable: true },
+      { pubkey: gameServer, isSigner: true, isWritable: true },
+    ],
+    data: Buffer.concat([disc("close_tournament"), matchIdToBytes(tournamentId)]),
+  });
+}
+
+function buildPayoutTournamentIx(
+  tournamentId: number,
+  treasury: PublicKey,
+  champion: PublicKey,
+  runnerUp: PublicKey,
+): anchor.web3.TransactionInstruction {
+  const data = Buffer.concat([disc("payout_tournament"), matchIdToBytes(tournamentId)]);
+  return new anchor.web3.TransactionInstruction({
+    programId: PROGRAM_ID,
+    keys: [
+      { pubkey: findTournamentPda(tournamentId)[0], isSigner: false, isWritable: true },
+      { pubkey: findConfigPda()[0], isSigner: false, isWritable: false },
+      { pubkey: treasury, isSigner: false, isWritable: true },
+      { pubkey: champion, isSigner: false, isWritable: true },
+      { pubkey: runnerUp, isSigner: false, isWritable: true },
     ],
     data,
   });
@@ -312,6 +1103,58 @@ function buildClosePlayerStateIx(
       { pubkey: matchPda, isSigner: false, isWritable: false },
       { pubkey: playerStatePda, isSigner: false, isWritable: true },
       { pubkey: payer, isSigner: true, isWritable: true },
+      // rent payer — the player who created the state
+      { pubkey: player, isSigner: false, isWritable: true },
+    ],
+    data,
+  });
+}
+
+// Buffer (owned by this program), delegation record and metadata for one
+// delegated PDA, in the order setup_match expects them.
+function delegationAccounts(pda: PublicKey): PublicKey[] {
+  const [buffer] = PublicKey.findProgramAddressSync([Buffer.from("buffer"), pda.toBuffer()], PROGRAM_ID);
+  const [record] = PublicKey.findProgramAddressSync([Buffer.from("delegation"), pda.toBuffer()], DELEGATION_PROGRAM_ID);
+  const [metadata] = PublicKey.findProgramAddressSync(
+    [Buffer.from("delegation-metadata"), pda.toBuffer()], DELEGATION_PROGRAM_ID,
+  );
+  return [buffer, record, metadata];
+}
+
+function buildSetupMatchIx(
+  matchId: number,
+  gameServer: PublicKey,
+  player1: PublicKey,
+  player2?: PublicKey,
+  params: Partial<SettlementParams> = {},
+  ranked = false,
+): anchor.web3.TransactionInstruction {
+  const [matchPda] = findMatchPda(matchId);
+  const [ps1Pda] = findPlayerStatePda(matchId, player1);
+  const ps2Pda = player2 ? findPlayerStatePda(matchId, player2)[0] : undefined;
+  const matchIdBuf = Buffer.alloc(8);
+  matchIdBuf.writeBigUInt64LE(BigInt(matchId));
+  const data = Buffer.concat([disc("setup_match"), matchIdBuf, encodeSettlementParams(params), Buffer.from([ranked ? 1 : 0])]);
+  // None → program ID placeholder
+  const optional = (key: PublicKey | undefined, isSigner = false) =>
+    key ? { pubkey: key, isSigner, isWritable: true } : { pubkey: PROGRAM_ID, isSigner: false, isWritable: false };
+  const writable = (key: PublicKey) => ({ pubkey: key, isSigner: false, isWritable: true });
+
+  return new anchor.web3.TransactionInstruction({
+    programId: PROGRAM_ID,
+    keys: [
+      writable(matchPda),
+      writable(ps1Pda),
+      optional(ps2Pda),
+      { pubkey: gameServer, isSigner: true, isWritable: true },
+      { pubkey: player1, isSigner: true, isWritable: true },
+      optional(player2, true),
+      ...delegationAccounts(matchPda).map(writable),
+      ...delegationAccounts(ps1Pda).map(writable),
+      ...(ps2Pda ? delegationAccounts(ps2Pda).map(writable) : [0, 1, 2].map(() => optional(undefined))),
+      { pubkey: PROGRAM_ID, isSigner: false, isWritable: false },
+      { pubkey: DELEGATION_PROGRAM_ID, isSigner: false, isWritable: false },
+      { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
     ],
     data,
   });
@@ -329,6 +1172,7 @@ describe("arena-match", () => {
   const gameServer = (provider.wallet as anchor.Wallet).payer;
   const player1 = gameServer; // game server acts as player1 (same as production flow)
   const player2 = Keypair.generate();
+  const treasury = Keypair.generate();
 
   let matchId: number;
 
@@ -343,6 +1187,18 @@ describe("arena-match", () => {
     });
     const tx = new anchor.web3.Transaction().add(transferIx);
     await provider.sendAndConfirm(tx, [gameServer]);
+
+    // Fresh treasury for this run — funded so small SOL fees keep it rent-exempt
+    await provider.sendAndConfirm(
+      new anchor.web3.Transaction().add(SystemProgram.transfer({
+        fromPubkey: gameServer.publicKey,
+        toPubkey: treasury.publicKey,
+        lamports: 0.01 * anchor.web3.LAMPORTS_PER_SOL,
+      })),
+      [gameServer]
+    );
+    await setupConfig(provider, gameServer, treasury.publicKey);
+    runningSeason = await currentSeason(provider);
   });
 
   // ── 1. Create match ───────────────────────────────────────────────────
@@ -512,6 +1368,8 @@ describe("arena-match", () => {
     const state = decodeMatchState(acct!.data);
 
     expect(state.player1RoundsWon).to.equal(2);
+    expect(state.player1FlawlessRounds).to.equal(2); // P1 never took damage
+    expect(state.player2FlawlessRounds).to.equal(0);
     expect(state.status).to.equal(4); // Complete
     expect(state.winner.toBase58()).to.equal(player1.publicKey.toBase58());
     expect(state.settledAt).to.not.equal(BigInt(0));
@@ -571,6 +1429,58 @@ describe("arena-match", () => {
     }
   });
 
+  // ── 12b. Server tick applies a whole frame atomically ───────────────
+
+  it("server_tick applies batched events in order", async () => {
+    const tickMatchId = matchId + 5;
+    const createIx = buildCreateMatchIx(tickMatchId, gameServer.publicKey, player1.publicKey);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(createIx), [player1]);
+
+    const joinIx = buildJoinMatchIx(tickMatchId, player2.publicKey, player2.publicKey);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(joinIx), [player2]);
+
+    const events: ServerEvent[] = [{ kind: "startRound" }];
+    for (let i = 1; i <= HP_PER_ROUND; i++) {
+      events.push({ kind: "advanceTick", tick: i * (DAMAGE_COOLDOWN_TICKS + 1) });
+      events.push({ kind: "applyDamage", targetSlot: 2 });
+    }
+    events.push({ kind: "endRound" });
+
+    const ix = buildServerTickIx(tickMatchId, gameServer.publicKey, events);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(ix), [gameServer]);
+
+    const [matchPda] = findMatchPda(tickMatchId);
+    const state = decodeMatchState((await provider.connection.getAccountInfo(matchPda))!.data);
+    expect(state.player1RoundsWon).to.equal(1);
+    expect(state.status).to.equal(3); // RoundEnd
+    expect(state.currentRound).to.equal(2);
+  });
+
+  it("server_tick reverts the whole frame if one event fails", async () => {
+    const tickMatchId = matchId + 5;
+    const [matchPda] = findMatchPda(tickMatchId);
+    const before = decodeMatchState((await provider.connection.getAccountInfo(matchPda))!.data);
+
+    // Second hit lands inside the damage cooldown
+    const ix = buildServerTickIx(tickMatchId, gameServer.publicKey, [
+      { kind: "startRound" },
+      { kind: "advanceTick", tick: before.currentTick + DAMAGE_COOLDOWN_TICKS + 1 },
+      { kind: "applyDamage", targetSlot: 1 },
+      { kind: "applyDamage", targetSlot: 1 },
+    ]);
+    try {
+      await provider.sendAndConfirm(new anchor.web3.Transaction().add(ix), [gameServer]);
+      expect.fail("Should have failed with DamageCooldown");
+    } catch (err: any) {
+      expect(err.toString()).to.include("Simulation failed");
+    }
+
+    const after = decodeMatchState((await provider.connection.getAccountInfo(matchPda))!.data);
+    expect(after.status).to.equal(before.status);
+    expect(after.player1Hp).to.equal(before.player1Hp);
+    expect(after.currentTick).to.equal(before.currentTick);
+  });
+
   // ── 13. Game server can close match in any state (no status check) ──
 
   it("game server can close match in any state", async () => {
@@ -583,7 +1493,7 @@ describe("arena-match", () => {
     expect(acct).to.not.be.null;
 
     // close_match has no status check — server is trusted
-    const closeIx = buildCloseMatchIx(closeTestId, gameServer.publicKey);
+    const closeIx = buildCloseMatchIx(closeTestId, gameServer.publicKey, gameServer.publicKey, [gameServer.publicKey]);
     await provider.sendAndConfirm(new anchor.web3.Transaction().add(closeIx), [gameServer]);
 
     acct = await provider.connection.getAccountInfo(matchPda);
@@ -610,7 +1520,7 @@ describe("arena-match", () => {
     // Close player states first (reads arena_match for auth)
     const closePs1 = buildClosePlayerStateIx(matchId, player1.publicKey, gameServer.publicKey);
     const closePs2 = buildClosePlayerStateIx(matchId, player2.publicKey, gameServer.publicKey);
-    const closeMatch = buildCloseMatchIx(matchId, gameServer.publicKey);
+    const closeMatch = buildCloseMatchIx(matchId, gameServer.publicKey, player1.publicKey, [player1.publicKey, player2.publicKey]);
 
     // All three in one transaction
     const tx = new anchor.web3.Transaction().add(closePs1).add(closePs2).add(closeMatch);
@@ -628,6 +1538,13 @@ describe("arena-match", () => {
     // Verify rent was reclaimed (balance increased minus tx fee)
     const balAfter = await provider.connection.getBalance(gameServer.publicKey);
     expect(balAfter).to.be.greaterThan(balBefore - 10000); // Allow for tx fee
+
+    // P1 won rounds without losing HP → flawless achievement on the profile
+    const p1Profile = decodePlayerProfile(
+      (await provider.connection.getAccountInfo(findProfilePda(player1.publicKey)[0]))!.data
+    );
+    expect(p1Profile.achievements & ACHIEVEMENT_FLAWLESS_ROUND).to.equal(ACHIEVEMENT_FLAWLESS_ROUND);
+    expect(p1Profile.arenaWinStreak).to.be.greaterThan(0);
   });
 
   // ── 15. Close match after forfeit ───────────────────────────────────
@@ -640,10 +1557,823 @@ describe("arena-match", () => {
     let acct = await provider.connection.getAccountInfo(matchPda);
     expect(acct).to.not.be.null;
 
-    const closeIx = buildCloseMatchIx(forfeitMatchId, gameServer.publicKey);
+    const closeIx = buildCloseMatchIx(forfeitMatchId, gameServer.publicKey, player1.publicKey, [player1.publicKey, player2.publicKey]);
     await provider.sendAndConfirm(new anchor.web3.Transaction().add(closeIx), [gameServer]);
 
     acct = await provider.connection.getAccountInfo(matchPda);
     expect(acct).to.be.null;
+
+    // Player 2 lost the main match (#14) and forfeited this one
+    const profile = decodePlayerProfile(
+      (await provider.connection.getAccountInfo(findProfilePda(player2.publicKey)[0]))!.data
+    );
+    expect(profile.wallet.toBase58()).to.equal(player2.publicKey.toBase58());
+    expect(profile.arenaWins).to.equal(0);
+    expect(profile.arenaLosses).to.equal(2);
+    expect(profile.arenaForfeits).to.equal(1);
+  });
+
+  // ── 16. close_match closes player states in the same instruction ───
+
+  it("close_match closes the match and its player states together", async () => {
+    const closeAllId = matchId + 6;
+    const createIx = buildCreateMatchIx(closeAllId, gameServer.publicKey, player1.publicKey);
+    const ps1Ix = buildCreatePlayerStateIx(closeAllId, player1.publicKey);
+    const ps2Ix = buildCreatePlayerStateIx(closeAllId, player2.publicKey);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(createIx).add(ps1Ix), [player1]);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(ps2Ix), [player2]);
+
+    const joinIx = buildJoinMatchIx(closeAllId, player2.publicKey, player2.publicKey);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(joinIx), [player2]);
+
+    // Missing player2's state → rejected
+    const partialIx = buildCloseMatchIx(closeAllId, gameServer.publicKey, player1.publicKey, [player1.publicKey]);
+    try {
+      await provider.sendAndConfirm(new anchor.web3.Transaction().add(partialIx), [gameServer]);
+      expect.fail("Should have failed with MissingPlayerState");
+    } catch (err: any) {
+      expect(err.toString()).to.include("Simulation failed");
+    }
+
+    const closeIx = buildCloseMatchIx(closeAllId, gameServer.publicKey, player1.publicKey, [player1.publicKey, player2.publicKey]);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(closeIx), [gameServer]);
+
+    const [matchPda] = findMatchPda(closeAllId);
+    const [ps1Pda] = findPlayerStatePda(closeAllId, player1.publicKey);
+    const [ps2Pda] = findPlayerStatePda(closeAllId, player2.publicKey);
+    expect(await provider.connection.getAccountInfo(matchPda)).to.be.null;
+    expect(await provider.connection.getAccountInfo(ps1Pda)).to.be.null;
+    expect(await provider.connection.getAccountInfo(ps2Pda)).to.be.null;
+  });
+
+  // ── 17. Rent goes back to the player who paid it ──────────────────────
+
+  it("close_match refunds rent to the rent payer minus the close fee", async () => {
+    const refundId = matchId + 7;
+    const closeFee = 100_000;
+
+    // player2 opens this match, so player2 pays (and gets back) the rent
+    const createIx = buildCreateMatchIx(refundId, gameServer.publicKey, player2.publicKey, { closeFee });
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(createIx), [gameServer, player2]);
+
+    const [matchPda] = findMatchPda(refundId);
+    const matchAcct = await provider.connection.getAccountInfo(matchPda);
+    const state = decodeMatchState(matchAcct!.data);
+    expect(state.rentPayer.toBase58()).to.equal(player2.publicKey.toBase58());
+    expect(state.closeFee).to.equal(BigInt(closeFee));
+
+    const p2Before = await provider.connection.getBalance(player2.publicKey);
+    const closeIx = buildCloseMatchIx(refundId, gameServer.publicKey, player2.publicKey, [player2.publicKey]);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(closeIx), [gameServer]);
+    const p2After = await provider.connection.getBalance(player2.publicKey);
+
+    expect(p2After - p2Before).to.equal(matchAcct!.lamports - closeFee);
+  });
+
+  // ── 18. Strict matches only settle from a final status ───────────────
+
+  it("strict match cannot be closed before it is complete", async () => {
+    const strictId = matchId + 8;
+    const createIx = buildCreateMatchIx(strictId, gameServer.publicKey, player1.publicKey, { strict: true });
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(createIx), [player1]);
+
+    const [matchPda] = findMatchPda(strictId);
+    expect(decodeMatchState((await provider.connection.getAccountInfo(matchPda))!.data).strict).to.be.true;
+
+    const closeIx = buildCloseMatchIx(strictId, gameServer.publicKey, player1.publicKey, [player1.publicKey]);
+    try {
+      await provider.sendAndConfirm(new anchor.web3.Transaction().add(closeIx), [gameServer]);
+      expect.fail("Should have failed with MatchNotComplete");
+    } catch (err: any) {
+      // Custom error for MatchNotComplete
+      expect(err.toString()).to.include("0x1773");
+    }
+
+    const joinIx = buildJoinMatchIx(strictId, player2.publicKey, player2.publicKey);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(joinIx), [player2]);
+    const forfeitIx = buildForfeitIx(strictId, gameServer.publicKey, 2);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(forfeitIx), [gameServer]);
+
+    const closeAfterIx = buildCloseMatchIx(strictId, gameServer.publicKey, player1.publicKey, [player1.publicKey, player2.publicKey]);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(closeAfterIx), [gameServer]);
+    expect(await provider.connection.getAccountInfo(matchPda)).to.be.null;
+  });
+
+  // ── 19. Disputes block closing until the arbiter resolves them ────────
+
+  it("dispute window blocks close until the arbiter resolves", async () => {
+    const disputeId = matchId + 9;
+    const arbiter = Keypair.generate();
+    const createIx = buildCreateMatchIx(disputeId, gameServer.publicKey, player1.publicKey, {
+      arbiter: arbiter.publicKey,
+      disputeWindowSecs: 3600,
+    });
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(createIx), [player1]);
+
+    const joinIx = buildJoinMatchIx(disputeId, player2.publicKey, player2.publicKey);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(joinIx), [player2]);
+    const forfeitIx = buildForfeitIx(disputeId, gameServer.publicKey, 2);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(forfeitIx), [gameServer]);
+
+    const players = [player1.publicKey, player2.publicKey];
+    const closeIx = () => buildCloseMatchIx(disputeId, gameServer.publicKey, player1.publicKey, players);
+    try {
+      await provider.sendAndConfirm(new anchor.web3.Transaction().add(closeIx()), [gameServer]);
+      expect.fail("Should have failed with DisputeWindowOpen");
+    } catch (err: any) {
+      expect(err.toString()).to.include("Simulation failed");
+    }
+
+    // Player states wait out the same window
+    try {
+      await provider.sendAndConfirm(
+        new anchor.web3.Transaction().add(buildClosePlayerStateIx(disputeId, player2.publicKey, gameServer.publicKey)),
+        [gameServer],
+      );
+      expect.fail("Should have failed with DisputeWindowOpen");
+    } catch (err: any) {
+      // ArenaError::DisputeWindowOpen = 6020 = 0x1784
+      expect(err.toString()).to.include("0x1784");
+    }
+
+    const raiseIx = buildRaiseDisputeIx(disputeId, player2.publicKey);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(raiseIx), [player2]);
+
+    const [matchPda] = findMatchPda(disputeId);
+    let state = decodeMatchState((await provider.connection.getAccountInfo(matchPda))!.data);
+    expect(state.disputeStatus).to.equal(1); // Raised
+
+    const resolveIx = buildResolveDisputeIx(disputeId, arbiter.publicKey, {
+      kind: "overrideWinner",
+      winner: player2.publicKey,
+    });
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(resolveIx), [arbiter]);
+
+    state = decodeMatchState((await provider.connection.getAccountInfo(matchPda))!.data);
+    expect(state.disputeStatus).to.equal(2); // Resolved
+    expect(state.winner.toBase58()).to.equal(player2.publicKey.toBase58());
+
+    // Resolution ends the window early
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(closeIx()), [gameServer]);
+    expect(await provider.connection.getAccountInfo(matchPda)).to.be.null;
+  });
+
+  // ── 20. Staked match pays the pot to the winner exactly once ──────────
+
+  it("staked match escrows both stakes and pays the winner once", async () => {
+    const stakeId = matchId + 10;
+    const stake = 0.01 * anchor.web3.LAMPORTS_PER_SOL;
+
+    const createIx = buildCreateMatchIx(stakeId, gameServer.publicKey, player1.publicKey, {}, stake);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(createIx), [player1]);
+
+    const joinIx = buildJoinMatchIx(stakeId, player2.publicKey, player2.publicKey, true);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(joinIx), [player2]);
+
+    const [escrowPda] = findEscrowPda(stakeId);
+    const escrowAcct = await provider.connection.getAccountInfo(escrowPda);
+    const escrowRent = await provider.connection.getMinimumBalanceForRentExemption(escrowAcct!.data.length);
+    expect(escrowAcct!.lamports).to.equal(escrowRent + 2 * stake);
+
+    // P1 forfeits → P2 wins the pot
+    const forfeitIx = buildForfeitIx(stakeId, gameServer.publicKey, 1);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(forfeitIx), [gameServer]);
+
+    const p2Before = await provider.connection.getBalance(player2.publicKey);
+    const treasuryBefore = await provider.connection.getBalance(treasury.publicKey);
+    const payoutIx = buildPayoutIx(stakeId, player1.publicKey, player2.publicKey, gameServer.publicKey, treasury.publicKey);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(payoutIx), [gameServer]);
+    const p2After = await provider.connection.getBalance(player2.publicKey);
+    const treasuryAfter = await provider.connection.getBalance(treasury.publicKey);
+
+    const fee = protocolFee(2 * stake);
+    expect(p2After - p2Before).to.equal(2 * stake - fee);
+    expect(treasuryAfter - treasuryBefore).to.equal(fee);
+    expect(await provider.connection.getAccountInfo(escrowPda)).to.be.null;
+
+    const [matchPda] = findMatchPda(stakeId);
+    expect(decodeMatchState((await provider.connection.getAccountInfo(matchPda))!.data).paidOut).to.be.true;
+
+    // Second payout is rejected
+    const againIx = buildPayoutIx(stakeId, player1.publicKey, player2.publicKey, gameServer.publicKey, treasury.publicKey);
+    try {
+      await provider.sendAndConfirm(new anchor.web3.Transaction().add(againIx), [gameServer]);
+      expect.fail("Should have failed — escrow already paid out");
+    } catch (err: any) {
+      expect(err.toString()).to.include("Simulation failed");
+    }
+  });
+
+  // ── 21. Token wager: both deposit, winner's ATA receives the pot ──────
+
+  it("token wager pays the pot to the winner's token account", async () => {
+    const tokenId = matchId + 11;
+    const amount = 1_000_000;
+
+    const mint = await createMint(provider.connection, gameServer, gameServer.publicKey, null, 6);
+    const p1Ata = await getOrCreateAssociatedTokenAccount(provider.connection, gameServer, mint, player1.publicKey);
+    const p2Ata = await getOrCreateAssociatedTokenAccount(provider.connection, gameServer, mint, player2.publicKey);
+    const treasuryAta = await getOrCreateAssociatedTokenAccount(provider.connection, gameServer, mint, treasury.publicKey);
+    await mintTo(provider.connection, gameServer, mint, p1Ata.address, gameServer, amount);
+    await mintTo(provider.connection, gameServer, mint, p2Ata.address, gameServer, amount);
+
+    const createIx = buildCreateMatchIx(tokenId, gameServer.publicKey, player1.publicKey, {}, 0, { mint, amount });
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(createIx), [player1]);
+
+    // Player 2 can't deposit before joining
+    const earlyIx = buildDepositMatchTokensIx(tokenId, mint, player2.publicKey);
+    try {
+      await provider.sendAndConfirm(new anchor.web3.Transaction().add(earlyIx), [player2]);
+      expect.fail("Should have failed — player 2 has not joined");
+    } catch (err: any) {
+      // ArenaError::UnauthorizedPlayer = 6009 = 0x1779
+      expect(err.toString()).to.include("0x1779");
+    }
+
+    const p1DepositIx = buildDepositMatchTokensIx(tokenId, mint, player1.publicKey);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(p1DepositIx), [player1]);
+
+    const joinIx = buildJoinMatchIx(tokenId, player2.publicKey, player2.publicKey);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(joinIx), [player2]);
+
+    const p2DepositIx = buildDepositMatchTokensIx(tokenId, mint, player2.publicKey);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(p2DepositIx), [player2]);
+
+    const [vaultPda] = findTokenVaultPda(tokenId);
+    expect((await getAccount(provider.connection, vaultPda)).amount).to.equal(BigInt(2 * amount));
+
+    // P1 forfeits → P2 wins the token pot
+    const forfeitIx = buildForfeitIx(tokenId, gameServer.publicKey, 1);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(forfeitIx), [gameServer]);
+
+    const payoutIx = buildPayoutMatchTokensIx(
+      tokenId, mint, player1.publicKey, player2.publicKey, gameServer.publicKey, treasury.publicKey,
+    );
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(payoutIx), [gameServer]);
+
+    const fee = protocolFee(2 * amount);
+    expect((await getAccount(provider.connection, p2Ata.address)).amount).to.equal(BigInt(2 * amount - fee));
+    expect((await getAccount(provider.connection, treasuryAta.address)).amount).to.equal(BigInt(fee));
+    expect((await getAccount(provider.connection, p1Ata.address)).amount).to.equal(BigInt(0));
+    expect(await provider.connection.getAccountInfo(vaultPda)).to.be.null;
+
+    const [matchPda] = findMatchPda(tokenId);
+    const state = decodeMatchState((await provider.connection.getAccountInfo(matchPda))!.data);
+    expect(state.tokenMint.toBase58()).to.equal(mint.toBase58());
+    expect(state.tokensPaidOut).to.be.true;
+  });
+
+  // ── 22. Ranked match moves both Elo ratings by the same amount ────────
+
+  it("ranked match updates both ratings at close", async () => {
+    const rankedId = matchId + 12;
+    const ELO_INITIAL_RATING = 1500;
+
+    const profileRating = async (wallet: PublicKey): Promise<number> => {
+      const acct = await provider.connection.getAccountInfo(findProfilePda(wallet)[0]);
+      const rating = acct ? decodePlayerProfile(acct.data).arenaRating : 0;
+      return rating === 0 ? ELO_INITIAL_RATING : rating;
+    };
+    const p1Before = await profileRating(player1.publicKey);
+    const p2Before = await profileRating(player2.publicKey);
+
+    const createIx = buildCreateMatchIx(rankedId, gameServer.publicKey, player1.publicKey, {}, 0, undefined, true);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(createIx), [player1]);
+    const joinIx = buildJoinMatchIx(rankedId, player2.publicKey, player2.publicKey);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(joinIx), [player2]);
+
+    // P2 forfeits → P1 wins
+    const forfeitIx = buildForfeitIx(rankedId, gameServer.publicKey, 2);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(forfeitIx), [gameServer]);
+
+    const closeIx = buildCloseMatchIx(rankedId, gameServer.publicKey, player1.publicKey, [player1.publicKey, player2.publicKey]);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(closeIx), [gameServer]);
+
+    const p1After = await profileRating(player1.publicKey);
+    const p2After = await profileRating(player2.publicKey);
+    expect(p1After).to.be.greaterThan(p1Before);
+    expect(p1After - p1Before).to.equal(p2Before - p2After);
+
+    const p2Profile = decodePlayerProfile(
+      (await provider.connection.getAccountInfo(findProfilePda(player2.publicKey)[0]))!.data
+    );
+    expect(p2Profile.arenaRankedGames).to.equal(1);
+  });
+
+  // ── 23. Season stats and leaderboard are kept apart from the profile ──
+
+  it("records a match in the current season once", async () => {
+    const seasonMatchId = matchId + 13;
+    const previous = await currentSeason(provider);
+    const now = Math.floor(Date.now() / 1000);
+    const startIx = buildStartSeasonIx(gameServer.publicKey, previous, now - 60, now + 3600);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(startIx), [gameServer]);
+    const seasonId = previous + 1;
+    expect(await currentSeason(provider)).to.equal(seasonId);
+    runningSeason = seasonId;
+    if (previous > 0) {
+      const old = await provider.connection.getAccountInfo(findSeasonPda(previous)[0]);
+      expect(decodeSeasonArenaLeaderboard(old!.data).frozen).to.be.true;
+    }
+
+    const createIx = buildCreateMatchIx(seasonMatchId, gameServer.publicKey, player1.publicKey);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(createIx), [player1]);
+    const joinIx = buildJoinMatchIx(seasonMatchId, player2.publicKey, player2.publicKey);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(joinIx), [player2]);
+    const forfeitIx = buildForfeitIx(seasonMatchId, gameServer.publicKey, 2);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(forfeitIx), [gameServer]);
+
+    // Settled inside the season → it can't close before it's recorded
+    const players = [player1.publicKey, player2.publicKey];
+    const closeIx = buildCloseMatchIx(seasonMatchId, gameServer.publicKey, player1.publicKey, players);
+    try {
+      await provider.sendAndConfirm(new anchor.web3.Transaction().add(closeIx), [gameServer]);
+      expect.fail("Should have failed with SeasonReportPending");
+    } catch (err: any) {
+      expect(err.toString()).to.include("0x17af"); // SeasonReportPending = 6063
+    }
+
+    const recordIx = () =>
+      buildRecordMatchSeasonIx(seasonMatchId, seasonId, player1.publicKey, player2.publicKey, gameServer.publicKey);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(recordIx()), [gameServer]);
+
+    const p1Stats = decodeSeasonStats(
+      (await provider.connection.getAccountInfo(findSeasonStatsPda(seasonId, player1.publicKey)[0]))!.data
+    );
+    const p2Stats = decodeSeasonStats(
+      (await provider.connection.getAccountInfo(findSeasonStatsPda(seasonId, player2.publicKey)[0]))!.data
+    );
+    expect(p1Stats.arenaWins).to.equal(1);
+    expect(p2Stats.arenaLosses).to.equal(1);
+
+    const board = decodeSeasonArenaLeaderboard(
+      (await provider.connection.getAccountInfo(findSeasonPda(seasonId)[0]))!.data
+    );
+    expect(board.frozen).to.be.false;
+    expect(board.entries[0].player.toBase58()).to.equal(player1.publicKey.toBase58());
+    expect(board.entries[0].wins).to.equal(1);
+
+    // A second record of the same match is rejected
+    try {
+      await provider.sendAndConfirm(new anchor.web3.Transaction().add(recordIx()), [gameServer]);
+      expect.fail("Should have failed with SeasonAlreadyRecorded");
+    } catch (err: any) {
+      expect(err.toString()).to.include("0x179c"); // SeasonAlreadyRecorded = 6044
+    }
+
+    const [matchPda] = findMatchPda(seasonMatchId);
+    const state = decodeMatchState((await provider.connection.getAccountInfo(matchPda))!.data);
+    expect(state.seasonRecorded).to.be.true;
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(closeIx), [gameServer]);
+    expect(await provider.connection.getAccountInfo(matchPda)).to.be.null;
+
+    // Roll on to a season that hasn't begun, so later results close unrecorded
+    const nextIx = buildStartSeasonIx(gameServer.publicKey, seasonId, now + 365 * 86_400, now + 366 * 86_400);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(nextIx), [gameServer]);
+    runningSeason = seasonId + 1;
+  });
+
+  // ── 24. Two-player tournament: spawn, report, crown, pay ──────────────
+
+  it("tournament advances the reported winner and pays the prize split", async () => {
+    const tournamentId = matchId + 14;
+    const baseMatchId = matchId + 100;
+    const entryFee = 0.01 * anchor.web3.LAMPORTS_PER_SOL;
+    const [tournamentPda] = findTournamentPda(tournamentId);
+
+    const createIx = buildCreateTournamentIx(tournamentId, gameServer.publicKey, baseMatchId, 8, entryFee, [7000, 3000]);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(createIx), [gameServer]);
+    await provider.sendAndConfirm(
+      new anchor.web3.Transaction().add(buildJoinTournamentIx(tournamentId, player1.publicKey)), [player1]
+    );
+    await provider.sendAndConfirm(
+      new anchor.web3.Transaction().add(buildJoinTournamentIx(tournamentId, player2.publicKey)), [player2]
+    );
+
+    const startIx = buildTournamentServerActionIx("start_tournament", tournamentId, gameServer.publicKey);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(startIx), [gameServer]);
+    let t = decodeTournament((await provider.connection.getAccountInfo(tournamentPda))!.data);
+    expect(t.status).to.equal(1); // Running
+    expect(t.bracketSize).to.equal(2);
+
+    // Two entrants → the final (node 0) is the only match
+    const spawnIx = buildCreateTournamentMatchIx(tournamentId, 0, baseMatchId, gameServer.publicKey);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(spawnIx), [gameServer]);
+    const spawned = decodeMatchState((await provider.connection.getAccountInfo(findMatchPda(baseMatchId)[0]))!.data);
+    expect(spawned.status).to.equal(1); // Countdown
+    expect(spawned.tournament.toBase58()).to.equal(tournamentPda.toBase58());
+
+    // Can't close the match before its result reaches the bracket
+    const forfeitIx = buildForfeitIx(baseMatchId, gameServer.publicKey, 1); // P1 forfeits → P2 wins
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(forfeitIx), [gameServer]);
+    const closeIx = buildCloseMatchIx(baseMatchId, gameServer.publicKey, gameServer.publicKey, [player1.publicKey, player2.publicKey]);
+    try {
+      await provider.sendAndConfirm(new anchor.web3.Transaction().add(closeIx), [gameServer]);
+      expect.fail("Should have failed with TournamentReportPending");
+    } catch (err: any) {
+      expect(err.toString()).to.include("0x17a8"); // TournamentReportPending = 6056
+    }
+
+    const reportIx = buildReportTournamentMatchIx(tournamentId, 0, baseMatchId);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(reportIx), [gameServer]);
+    t = decodeTournament((await provider.connection.getAccountInfo(tournamentPda))!.data);
+    expect(t.status).to.equal(2); // Complete
+    expect(t.champion.toBase58()).to.equal(player2.publicKey.toBase58());
+    expect(t.runnerUp.toBase58()).to.equal(player1.publicKey.toBase58());
+
+    const closeTournamentIx = buildCloseTournamentIx(tournamentId, gameServer.publicKey);
+    try {
+      await provider.sendAndConfirm(new anchor.web3.Transaction().add(closeTournamentIx), [gameServer]);
+      expect.fail("Should have failed with TournamentPayoutPending");
+    } catch (err: any) {
+      expect(err.toString()).to.include("0x17ae"); // TournamentPayoutPending = 6062
+    }
+
+    const p2Before = await provider.connection.getBalance(player2.publicKey);
+    const payoutIx = buildPayoutTournamentIx(tournamentId, treasury.publicKey, player2.publicKey, player1.publicKey);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(payoutIx), [gameServer]);
+
+    const pot = 2 * entryFee;
+    const championAmount = Math.floor(((pot - protocolFee(pot)) * 7000) / 10_000);
+    expect(await provider.connection.getBalance(player2.publicKey)).to.equal(p2Before + championAmount);
+    expect(decodeTournament((await provider.connection.getAccountInfo(tournamentPda))!.data).paidOut).to.be.true;
+
+    // Reported → the match can be closed now
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(closeIx), [gameServer]);
+    expect(await provider.connection.getAccountInfo(findMatchPda(baseMatchId)[0])).to.be.null;
+
+    // Paid out → the tournament's rent goes back to the game server
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(closeTournamentIx), [gameServer]);
+    expect(await provider.connection.getAccountInfo(tournamentPda)).to.be.null;
+  });
+
+  // ── 25. Round-robin: every pair meets once, standings pick the champion
+
+  it("round-robin pairs each round once the previous one is reported", async () => {
+    const tournamentId = matchId + 15;
+    const baseMatchId = matchId + 1_000;
+    const player3 = Keypair.generate();
+    await provider.sendAndConfirm(
+      new anchor.web3.Transaction().add(SystemProgram.transfer({
+        fromPubkey: gameServer.publicKey,
+        toPubkey: player3.publicKey,
+        lamports: 0.05 * anchor.web3.LAMPORTS_PER_SOL,
+      })),
+      [gameServer]
+    );
+    const [tournamentPda] = findTournamentPda(tournamentId);
+    const readTournament = async () =>
+      decodeTournament((await provider.connection.getAccountInfo(tournamentPda))!.data);
+
+    const createIx = buildCreateTournamentIx(
+      tournamentId, gameServer.publicKey, baseMatchId, 4, 0, [10_000, 0], {}, TournamentFormat.RoundRobin,
+    );
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(createIx), [gameServer]);
+    for (const entrant of [player1, player2, player3]) {
+      await provider.sendAndConfirm(
+        new anchor.web3.Transaction().add(buildJoinTournamentIx(tournamentId, entrant.publicKey)), [entrant]
+      );
+    }
+    const startIx = buildTournamentServerActionIx("start_tournament", tournamentId, gameServer.publicKey);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(startIx), [gameServer]);
+
+    let t = await readTournament();
+    expect(t.totalRounds).to.equal(3); // 3 entrants + a bye seat → 3 rounds
+    const met = new Set<string>();
+
+    for (let round = 1; round <= 3; round++) {
+      expect(t.round).to.equal(round);
+      // One real pairing and one bye per round
+      const node = t.pairings.findIndex(([, b]) => b !== TOURNAMENT_NO_ENTRANT);
+      const [a, b] = t.pairings[node];
+      met.add([a, b].sort().join("-"));
+      const roundMatchId = baseMatchId + round * TOURNAMENT_MAX_PAIRINGS + node;
+
+      const spawnIx = buildCreateTournamentMatchIx(tournamentId, node, roundMatchId, gameServer.publicKey);
+      await provider.sendAndConfirm(new anchor.web3.Transaction().add(spawnIx), [gameServer]);
+      const forfeitIx = buildForfeitIx(roundMatchId, gameServer.publicKey, 2); // pairing's first entrant wins
+      await provider.sendAndConfirm(new anchor.web3.Transaction().add(forfeitIx), [gameServer]);
+      const reportIx = buildReportTournamentMatchIx(tournamentId, node, roundMatchId);
+      await provider.sendAndConfirm(new anchor.web3.Transaction().add(reportIx), [gameServer]);
+      t = await readTournament();
+    }
+
+    expect(met.size).to.equal(3); // no rematches
+    expect(t.status).to.equal(2); // Complete
+    expect(t.points.reduce((sum, p) => sum + p, 0)).to.equal(3 * 2);
+    const best = Math.max(...t.points);
+    const entrants = [player1.publicKey, player2.publicKey, player3.publicKey];
+    expect(t.points[entrants.findIndex((e) => e.equals(t.champion))]).to.equal(best);
+  });
+
+  // ── 26. One-shot setup without player 2, over a pre-funded PDA ────────
+
+  it("setup_match creates and delegates an open match even if a PDA was pre-funded", async () => {
+    const setupMatchId = matchId + 2_000;
+    const [matchPda] = findMatchPda(setupMatchId);
+    const [ps1Pda] = findPlayerStatePda(setupMatchId, player1.publicKey);
+
+    // Griefing attempt: lamports sent to the player state before it exists
+    await provider.sendAndConfirm(
+      new anchor.web3.Transaction().add(SystemProgram.transfer({
+        fromPubkey: gameServer.publicKey,
+        toPubkey: ps1Pda,
+        lamports: 1_000,
+      })),
+      [gameServer]
+    );
+
+    const ix = buildSetupMatchIx(setupMatchId, gameServer.publicKey, player1.publicKey);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(ix), [gameServer]);
+
+    // Both PDAs are now owned by the delegation program, with records
+    for (const pda of [matchPda, ps1Pda]) {
+      const acct = await provider.connection.getAccountInfo(pda);
+      expect(acct!.owner.toBase58()).to.equal(DELEGATION_PROGRAM_ID.toBase58());
+      const [, record] = delegationAccounts(pda);
+      expect(await provider.connection.getAccountInfo(record)).to.not.be.null;
+    }
+    const state = decodeMatchState((await provider.connection.getAccountInfo(matchPda))!.data);
+    expect(state.matchId).to.equal(BigInt(setupMatchId));
+    expect(state.status).to.equal(0); // WaitingForPlayer
+    expect(state.player2.toBase58()).to.equal(PublicKey.default.toBase58());
+    const ps1 = decodePlayerState((await provider.connection.getAccountInfo(ps1Pda))!.data);
+    expect(ps1.player.toBase58()).to.equal(player1.publicKey.toBase58());
+  });
+
+  // ── 27. One-shot setup with both players ──────────────────────────────
+
+  it("setup_match with player 2 starts the countdown and delegates all three PDAs", async () => {
+    const setupMatchId = matchId + 2_001;
+    const [matchPda] = findMatchPda(setupMatchId);
+    const [ps1Pda] = findPlayerStatePda(setupMatchId, player1.publicKey);
+    const [ps2Pda] = findPlayerStatePda(setupMatchId, player2.publicKey);
+
+    const ix = buildSetupMatchIx(
+      setupMatchId, gameServer.publicKey, player1.publicKey, player2.publicKey, { strict: true }, true,
+    );
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(ix), [gameServer, player2]);
+
+    for (const pda of [matchPda, ps1Pda, ps2Pda]) {
+      const acct = await provider.connection.getAccountInfo(pda);
+      expect(acct!.owner.toBase58()).to.equal(DELEGATION_PROGRAM_ID.toBase58());
+      const [, record] = delegationAccounts(pda);
+      expect(await provider.connection.getAccountInfo(record)).to.not.be.null;
+    }
+    const state = decodeMatchState((await provider.connection.getAccountInfo(matchPda))!.data);
+    expect(state.player2.toBase58()).to.equal(player2.publicKey.toBase58());
+    expect(state.status).to.equal(1); // Countdown
+    expect(state.currentRound).to.equal(1);
+    expect(state.ranked).to.be.true;
+    expect(state.strict).to.be.true;
+    const ps2 = decodePlayerState((await provider.connection.getAccountInfo(ps2Pda))!.data);
+    expect(ps2.player.toBase58()).to.equal(player2.publicKey.toBase58());
+  });
+  // ── 28. Aborted mid-game staked match refunds both stakes ─────────────
+
+  it("abort_match cancels a staked match mid-game so payout refunds it", async () => {
+    const abortId = matchId + 16;
+    const stake = 0.01 * anchor.web3.LAMPORTS_PER_SOL;
+
+    const createIx = buildCreateMatchIx(abortId, gameServer.publicKey, player1.publicKey, {}, stake);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(createIx), [player1]);
+    const joinIx = buildJoinMatchIx(abortId, player2.publicKey, player2.publicKey, true);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(joinIx), [player2]);
+    const startIx = buildServerActionIx("start_round", abortId, gameServer.publicKey);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(startIx), [gameServer]);
+
+    // Active → no final status to pay out from
+    const payoutIx = () =>
+      buildPayoutIx(abortId, player1.publicKey, player2.publicKey, gameServer.publicKey, treasury.publicKey);
+    try {
+      await provider.sendAndConfirm(new anchor.web3.Transaction().add(payoutIx()), [gameServer]);
+      expect.fail("Should have failed with MatchNotComplete");
+    } catch (err: any) {
+      expect(err.toString()).to.include("Simulation failed");
+    }
+
+    const abortIx = buildServerActionIx("abort_match", abortId, gameServer.publicKey);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(abortIx), [gameServer]);
+    const [matchPda] = findMatchPda(abortId);
+    const state = decodeMatchState((await provider.connection.getAccountInfo(matchPda))!.data);
+    expect(state.status).to.equal(5); // Cancelled
+    expect(state.winner.toBase58()).to.equal(PublicKey.default.toBase58());
+
+    const p2Before = await provider.connection.getBalance(player2.publicKey);
+    const treasuryBefore = await provider.connection.getBalance(treasury.publicKey);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(payoutIx()), [gameServer]);
+    expect((await provider.connection.getBalance(player2.publicKey)) - p2Before).to.equal(stake);
+    expect(await provider.connection.getBalance(treasury.publicKey)).to.equal(treasuryBefore); // no fee on refunds
+    expect(await provider.connection.getAccountInfo(findEscrowPda(abortId)[0])).to.be.null;
+  });
+  // ── 29. Token wager of an aborted match is refunded to both players ───
+
+  it("aborted token-wager match refunds both deposits", async () => {
+    const abortId = matchId + 17;
+    const amount = 1_000_000;
+
+    const mint = await createMint(provider.connection, gameServer, gameServer.publicKey, null, 6);
+    const p1Ata = await getOrCreateAssociatedTokenAccount(provider.connection, gameServer, mint, player1.publicKey);
+    const p2Ata = await getOrCreateAssociatedTokenAccount(provider.connection, gameServer, mint, player2.publicKey);
+    const treasuryAta = await getOrCreateAssociatedTokenAccount(provider.connection, gameServer, mint, treasury.publicKey);
+    await mintTo(provider.connection, gameServer, mint, p1Ata.address, gameServer, amount);
+    await mintTo(provider.connection, gameServer, mint, p2Ata.address, gameServer, amount);
+
+    const createIx = buildCreateMatchIx(abortId, gameServer.publicKey, player1.publicKey, {}, 0, { mint, amount });
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(createIx), [player1]);
+    const p1DepositIx = buildDepositMatchTokensIx(abortId, mint, player1.publicKey);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(p1DepositIx), [player1]);
+    const joinIx = buildJoinMatchIx(abortId, player2.publicKey, player2.publicKey);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(joinIx), [player2]);
+    const p2DepositIx = buildDepositMatchTokensIx(abortId, mint, player2.publicKey);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(p2DepositIx), [player2]);
+    const startIx = buildServerActionIx("start_round", abortId, gameServer.publicKey);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(startIx), [gameServer]);
+
+    const abortIx = buildServerActionIx("abort_match", abortId, gameServer.publicKey);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(abortIx), [gameServer]);
+    const payoutIx = buildPayoutMatchTokensIx(
+      abortId, mint, player1.publicKey, player2.publicKey, gameServer.publicKey, treasury.publicKey,
+    );
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(payoutIx), [gameServer]);
+
+    expect((await getAccount(provider.connection, p1Ata.address)).amount).to.equal(BigInt(amount));
+    expect((await getAccount(provider.connection, p2Ata.address)).amount).to.equal(BigInt(amount));
+    expect((await getAccount(provider.connection, treasuryAta.address)).amount).to.equal(BigInt(0));
+    expect(await provider.connection.getAccountInfo(findTokenVaultPda(abortId)[0])).to.be.null;
+  });
+
+  // ── 30. Cancelled mid-run: spawned matches still settle and close ──────
+
+  it("releases a cancelled tournament's spawned match for close", async () => {
+    const tournamentId = matchId + 18;
+    const baseMatchId = matchId + 3_000;
+    const entryFee = 0.01 * anchor.web3.LAMPORTS_PER_SOL;
+    const [tournamentPda] = findTournamentPda(tournamentId);
+
+    const createIx = buildCreateTournamentIx(tournamentId, gameServer.publicKey, baseMatchId, 8, entryFee, [7000, 3000]);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(createIx), [gameServer]);
+    for (const entrant of [player1, player2]) {
+      await provider.sendAndConfirm(
+        new anchor.web3.Transaction().add(buildJoinTournamentIx(tournamentId, entrant.publicKey)), [entrant]
+      );
+    }
+    const startIx = buildTournamentServerActionIx("start_tournament", tournamentId, gameServer.publicKey);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(startIx), [gameServer]);
+    const spawnIx = buildCreateTournamentMatchIx(tournamentId, 0, baseMatchId, gameServer.publicKey);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(spawnIx), [gameServer]);
+
+    // Cancel while the final is still in play
+    const p1Before = await provider.connection.getBalance(player1.publicKey);
+    const cancelIx = buildTournamentServerActionIx(
+      "cancel_tournament", tournamentId, gameServer.publicKey, [player1.publicKey, player2.publicKey],
+    );
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(cancelIx), [gameServer]);
+    expect(await provider.connection.getBalance(player1.publicKey)).to.equal(p1Before + entryFee);
+
+    const forfeitIx = buildForfeitIx(baseMatchId, gameServer.publicKey, 1);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(forfeitIx), [gameServer]);
+    const reportIx = buildReportTournamentMatchIx(tournamentId, 0, baseMatchId);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(reportIx), [gameServer]);
+
+    // The result is dropped — the tournament stays cancelled with no champion
+    const t = decodeTournament((await provider.connection.getAccountInfo(tournamentPda))!.data);
+    expect(t.status).to.equal(3); // Cancelled
+    expect(t.champion.toBase58()).to.equal(PublicKey.default.toBase58());
+
+    const closeIx = buildCloseMatchIx(baseMatchId, gameServer.publicKey, gameServer.publicKey, [player1.publicKey, player2.publicKey]);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(closeIx), [gameServer]);
+    expect(await provider.connection.getAccountInfo(findMatchPda(baseMatchId)[0])).to.be.null;
+  });
+
+  // ── 31. setup_match → end_match on ER → close_match on L1 ─────────────
+
+  it("end_match undelegates the player states so close_match can close them", async function () {
+    // Needs a MagicBlock ER in front of the cluster under test
+    const erEndpoint = process.env.EPHEMERAL_PROVIDER_ENDPOINT;
+    if (!erEndpoint) this.skip();
+    const er = new anchor.web3.Connection(erEndpoint!, "confirmed");
+    const sendEr = async (ix: anchor.web3.TransactionInstruction, signers: Keypair[]) => {
+      const tx = new anchor.web3.Transaction().add(ix);
+      tx.feePayer = signers[0].publicKey;
+      tx.recentBlockhash = (await er.getLatestBlockhash()).blockhash;
+      tx.sign(...signers);
+      return anchor.web3.sendAndConfirmRawTransaction(er, tx.serialize(), { commitment: "confirmed" });
+    };
+
+    const setupMatchId = matchId + 2_002;
+    const [matchPda] = findMatchPda(setupMatchId);
+    const [ps1Pda] = findPlayerStatePda(setupMatchId, player1.publicKey);
+    const [ps2Pda] = findPlayerStatePda(setupMatchId, player2.publicKey);
+    const setupIx = buildSetupMatchIx(setupMatchId, gameServer.publicKey, player1.publicKey, player2.publicKey);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(setupIx), [gameServer, player2]);
+
+    await sendEr(buildForfeitIx(setupMatchId, gameServer.publicKey, 1), [gameServer]);
+
+    // Only the match's own player states can ride along
+    try {
+      const [strayPda] = findPlayerStatePda(setupMatchId, Keypair.generate().publicKey);
+      await sendEr(buildEndMatchIx(setupMatchId, gameServer.publicKey, [ps1Pda, strayPda]), [gameServer]);
+      expect.fail("Should have failed with InvalidPlayerState");
+    } catch (err: any) {
+      expect(err.toString()).to.include("0x177b"); // InvalidPlayerState = 6011
+    }
+
+    const endSig = await sendEr(buildEndMatchIx(setupMatchId, gameServer.publicKey, [ps1Pda, ps2Pda]), [gameServer]);
+    await GetCommitmentSignature(endSig, er);
+    for (const pda of [matchPda, ps1Pda, ps2Pda]) {
+      const acct = await provider.connection.getAccountInfo(pda);
+      expect(acct!.owner.toBase58()).to.equal(PROGRAM_ID.toBase58());
+    }
+
+    const closeIx = buildCloseMatchIx(setupMatchId, gameServer.publicKey, gameServer.publicKey, [player1.publicKey, player2.publicKey]);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(closeIx), [gameServer]);
+    for (const pda of [matchPda, ps1Pda, ps2Pda]) {
+      expect(await provider.connection.getAccountInfo(pda)).to.be.null;
+    }
+  });
+
+  // ── 32. Swiss: score groups pair without rematches, Buchholz breaks ties
+
+  it("Swiss pairs round 2 by points and lets Buchholz break the final tie", async () => {
+    const tournamentId = matchId + 19;
+    const baseMatchId = matchId + 4_000;
+    const player3 = Keypair.generate();
+    const player4 = Keypair.generate();
+    for (const entrant of [player3, player4]) {
+      await provider.sendAndConfirm(
+        new anchor.web3.Transaction().add(SystemProgram.transfer({
+          fromPubkey: gameServer.publicKey,
+          toPubkey: entrant.publicKey,
+          lamports: 0.05 * anchor.web3.LAMPORTS_PER_SOL,
+        })),
+        [gameServer]
+      );
+    }
+    const [tournamentPda] = findTournamentPda(tournamentId);
+    const readTournament = async () =>
+      decodeTournament((await provider.connection.getAccountInfo(tournamentPda))!.data);
+
+    const createIx = buildCreateTournamentIx(
+      tournamentId, gameServer.publicKey, baseMatchId, 4, 0, [10_000, 0], {}, TournamentFormat.Swiss, 2,
+    );
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(createIx), [gameServer]);
+    const entrants = [player1, player2, player3, player4];
+    for (const entrant of entrants) {
+      await provider.sendAndConfirm(
+        new anchor.web3.Transaction().add(buildJoinTournamentIx(tournamentId, entrant.publicKey)), [entrant]
+      );
+    }
+    const startIx = buildTournamentServerActionIx("start_tournament", tournamentId, gameServer.publicKey);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(startIx), [gameServer]);
+
+    // Spawns pairing `node`, plays it out and reports it. Forfeiting slot 2
+    // makes the pairing's first entrant win, slot 1 the second; null draws
+    // all three rounds.
+    const play = async (round: number, node: number, forfeiter: number | null) => {
+      const roundMatchId = baseMatchId + round * TOURNAMENT_MAX_PAIRINGS + node;
+      const send = (ix: anchor.web3.TransactionInstruction) =>
+        provider.sendAndConfirm(new anchor.web3.Transaction().add(ix), [gameServer]);
+      await send(buildCreateTournamentMatchIx(tournamentId, node, roundMatchId, gameServer.publicKey));
+      if (forfeiter === null) {
+        for (let r = 0; r < 3; r++) {
+          await send(buildServerActionIx("start_round", roundMatchId, gameServer.publicKey));
+          await send(buildServerActionIx("end_round", roundMatchId, gameServer.publicKey));
+        }
+      } else {
+        await send(buildForfeitIx(roundMatchId, gameServer.publicKey, forfeiter));
+      }
+      await send(buildReportTournamentMatchIx(tournamentId, node, roundMatchId));
+    };
+    const pairKey = ([a, b]: [number, number]) => [a, b].sort().join("-");
+
+    // Round 1: everyone on 0 points, paired down the join order
+    let t = await readTournament();
+    expect(t.round).to.equal(1);
+    expect(t.pairings).to.deep.equal([[0, 1], [2, 3]]);
+    const round1 = new Set(t.pairings.map(pairKey));
+    await play(1, 0, 2); // entrant 0 beats 1
+    await play(1, 1, 2); // entrant 2 beats 3
+
+    // Round 2: winners meet winners, losers meet losers, no rematches
+    t = await readTournament();
+    expect(t.round).to.equal(2);
+    expect(t.points).to.deep.equal([2, 0, 2, 0]);
+    expect(t.pairings).to.deep.equal([[0, 2], [1, 3]]);
+    for (const [a, b] of t.pairings) {
+      expect(t.points[a]).to.equal(t.points[b]);
+      expect(round1.has(pairKey([a, b]))).to.be.false;
+    }
+    await play(2, 0, null); // entrants 0 and 2 draw
+    await play(2, 1, 1); // entrant 3 beats 1
+
+    // 0 and 2 tie on points. 2's opponents (3 and 0) outscored 0's (1 and
+    // 2), so Buchholz crowns 2 even though 0 joined first.
+    t = await readTournament();
+    expect(t.status).to.equal(2); // Complete
+    expect(t.points).to.deep.equal([3, 0, 3, 2]);
+    expect(t.buchholz).to.deep.equal([3, 5, 5, 3]);
+    expect(t.champion.toBase58()).to.equal(player3.publicKey.toBase58());
+    expect(t.runnerUp.toBase58()).to.equal(player1.publicKey.toBase58());
   });
 });
@@ -1,6 +1,15 @@
 import * as anchor from "@coral-xyz/anchor";
 import { PublicKey, Keypair, SystemProgram } from "@solana/web3.js";
 import { expect } from "chai";
+import {
+  ASSOCIATED_TOKEN_PROGRAM_ID,
+  TOKEN_PROGRAM_ID,
+  createMint,
+  getAccount,
+  getAssociatedTokenAddressSync,
+  getOrCreateAssociatedTokenAccount,
+  mintTo,
+} from "@solana/spl-token";
 import { createHash } from "crypto";
 
 // Program ID
@@ -8,12 +17,31 @@ const PROGRAM_ID = new PublicKey("45A9Qb4YVeWwL35aBCTcT4bcfsgcFUW3GUHAbvhNJJGi")
 
 // Seeds
 const DERBY_SEED = Buffer.from("derby_race");
+const DERBY_PLAYER_SEED = Buffer.from("derby_player");
+const DERBY_TOKEN_VAULT_SEED = Buffer.from("derby_token_vault");
+const TRACK_LEADERBOARD_SEED = Buffer.from("track_leaderboard");
+const PROFILE_SEED = Buffer.from("player_profile");
+const DERBY_EVENT_SEED = Buffer.from("derby_event");
+const TRACK_GHOST_SEED = Buffer.from("track_ghost");
 
 // Derby constants (must match program)
 const DERBY_MAX_LAPS = 3;
 const DERBY_CHECKPOINT_COUNT = 4;
 const DERBY_MAX_TICKS = 6000;
 const DERBY_BOOST_DURATION_TICKS = 100;
+const DERBY_MIN_CHECKPOINT_GAP_TICKS = 40;
+const DERBY_MIN_BOOSTED_CHECKPOINT_GAP_TICKS = 20;
+const DERBY_MAX_RACERS = 8;
+const DERBY_MAX_OBSTACLES = 10;
+const DERBY_MAX_BOOSTS = 8;
+const DERBY_MAX_GOLD = 15;
+const DERBY_MIN_OBSTACLES = 4;
+const DERBY_MIN_BOOSTS = 4;
+const DERBY_MIN_GOLD = 8;
+const DERBY_LAYOUT_DOMAIN = Buffer.from("derby_layout");
+const DERBY_SPLIT_COUNT = DERBY_MAX_LAPS * DERBY_CHECKPOINT_COUNT;
+const TRACK_LEADERBOARD_SIZE = 10;
+const DERBY_EVENT_LEADERBOARD_SIZE = 10;
 
 // ── Helpers ─────────────────────────────────────────────────────────────────
 
@@ -30,6 +58,25 @@ function findDerbyPda(raceId: number): [PublicKey, number] {
   );
 }
 
+function findDerbyPlayerPda(raceId: number, player: PublicKey): [PublicKey, number] {
+  return PublicKey.findProgramAddressSync(
+    [DERBY_PLAYER_SEED, raceIdToBytes(raceId), player.toBuffer()],
+    PROGRAM_ID
+  );
+}
+
+function findTrackLeaderboardPda(vrfSeed: Buffer): [PublicKey, number] {
+  return PublicKey.findProgramAddressSync([TRACK_LEADERBOARD_SEED, vrfSeed], PROGRAM_ID);
+}
+
+function findTrackGhostPda(vrfSeed: Buffer): [PublicKey, number] {
+  return PublicKey.findProgramAddressSync([TRACK_GHOST_SEED, vrfSeed], PROGRAM_ID);
+}
+
+function findDerbyEventPda(eventId: number): [PublicKey, number] {
+  return PublicKey.findProgramAddressSync([DERBY_EVENT_SEED, raceIdToBytes(eventId)], PROGRAM_ID);
+}
+
 // ── Account Deserialization ─────────────────────────────────────────────────
 
 interface DerbyRaceStateData {
@@ -50,6 +97,89 @@ interface DerbyRaceStateData {
   boostBitmask: number;
   createdAt: bigint;
   settledAt: bigint;
+  rentPayer: PublicKey;
+  closeFee: bigint;
+  strict: boolean;
+  arbiter: PublicKey;
+  disputeWindowSecs: number;
+  disputeStatus: number;
+  tokenMint: PublicKey;
+  tokenStake: bigint;
+  playerTokensDeposited: boolean;
+  serverTokensDeposited: boolean;
+  tokensPaidOut: boolean;
+  rewarded: boolean;
+  rewardAmount: bigint;
+  seasonRecorded: boolean;
+  event: PublicKey;
+  eventRecorded: boolean;
+  maxRacers: number;
+  racerCount: number;
+  racers: PublicKey[];
+  finishedCount: number;
+  ghostRaceId: bigint;
+  ghostFinishTick: number;
+  ghostMargin: number;
+  seedSource: number;
+  seedCommitment: Buffer;
+  playerSeed: Buffer;
+  serverSecret: Buffer;
+  seedContributed: boolean;
+  seedFulfilled: boolean;
+  layout: TrackLayoutData;
+  obstacleHits: number[];
+  obstacleHitTicks: number[];
+  boostedTicks: number;
+  lastSplitTick: number;
+  lapTicks: number[];
+  checkpointTicks: number[];
+  trackOrigin: number;
+}
+
+interface TrackLayoutData {
+  obstacleCount: number;
+  goldCount: number;
+  boostCount: number;
+  obstacleSegments: number[];
+  goldSegments: number[];
+  boostSegments: number[];
+}
+
+// Mirrors TrackLayout::derive: sha256(domain ‖ vrf_seed), counts from bytes
+// 0–2, then 2 bits per item slot (obstacles, gold, boosts) from byte 3.
+function deriveTrackLayout(vrfSeed: Buffer): TrackLayoutData {
+  const h = createHash("sha256").update(Buffer.concat([DERBY_LAYOUT_DOMAIN, vrfSeed])).digest();
+  const segment = (slot: number) => (h[3 + (slot >> 2)] >> (2 * (slot % 4))) & 0b11;
+  const obstacleCount = DERBY_MIN_OBSTACLES + (h[0] % (DERBY_MAX_OBSTACLES - DERBY_MIN_OBSTACLES + 1));
+  const goldCount = DERBY_MIN_GOLD + (h[1] % (DERBY_MAX_GOLD - DERBY_MIN_GOLD + 1));
+  const boostCount = DERBY_MIN_BOOSTS + (h[2] % (DERBY_MAX_BOOSTS - DERBY_MIN_BOOSTS + 1));
+  const slots = (base: number, count: number, max: number) =>
+    Array.from({ length: max }, (_, i) => (i < count ? segment(base + i) : 0));
+  return {
+    obstacleCount, goldCount, boostCount,
+    obstacleSegments: slots(0, obstacleCount, DERBY_MAX_OBSTACLES),
+    goldSegments: slots(DERBY_MAX_OBSTACLES, goldCount, DERBY_MAX_GOLD),
+    boostSegments: slots(DERBY_MAX_OBSTACLES + DERBY_MAX_GOLD, boostCount, DERBY_MAX_BOOSTS),
+  };
+}
+
+function decodeTrackLayout(data: Buffer, offset: number): TrackLayoutData {
+  const obstacleCount = data.readUInt8(offset);
+  const goldCount = data.readUInt8(offset + 1);
+  const boostCount = data.readUInt8(offset + 2);
+  offset += 3;
+  const obstacleSegments = Array.from(data.subarray(offset, offset + DERBY_MAX_OBSTACLES)); offset += DERBY_MAX_OBSTACLES;
+  const goldSegments = Array.from(data.subarray(offset, offset + DERBY_MAX_GOLD)); offset += DERBY_MAX_GOLD;
+  const boostSegments = Array.from(data.subarray(offset, offset + DERBY_MAX_BOOSTS));
+  return { obstacleCount, goldCount, boostCount, obstacleSegments, goldSegments, boostSegments };
+}
+
+function readU16Array(data: Buffer, offset: number, count: number): number[] {
+  return Array.from({ length: count }, (_, i) => data.readUInt16LE(offset + 2 * i));
+}
+
+function readU32Array(data: Buffer, offset: number, count: number): number[] {
+  return Array.from({ length: count }, (_, i) => data.readUInt32LE(offset + 4 * i));
 }
 
 function decodeDerbyRaceState(data: Buffer): DerbyRaceStateData {
@@ -70,11 +200,178 @@ function decodeDerbyRaceState(data: Buffer): DerbyRaceStateData {
   const goldBitmask = data.readUInt16LE(offset); offset += 2;
   const boostBitmask = data.readUInt8(offset); offset += 1;
   const createdAt = data.readBigInt64LE(offset); offset += 8;
-  const settledAt = data.readBigInt64LE(offset);
+  const settledAt = data.readBigInt64LE(offset); offset += 8;
+  const rentPayer = new PublicKey(data.subarray(offset, offset + 32)); offset += 32;
+  const closeFee = data.readBigUInt64LE(offset); offset += 8;
+  const strict = data.readUInt8(offset) === 1; offset += 1;
+  const arbiter = new PublicKey(data.subarray(offset, offset + 32)); offset += 32;
+  const disputeWindowSecs = data.readUInt32LE(offset); offset += 4;
+  const disputeStatus = data.readUInt8(offset); offset += 1;
+  const tokenMint = new PublicKey(data.subarray(offset, offset + 32)); offset += 32;
+  const tokenStake = data.readBigUInt64LE(offset); offset += 8;
+  const playerTokensDeposited = data.readUInt8(offset) === 1; offset += 1;
+  const serverTokensDeposited = data.readUInt8(offset) === 1; offset += 1;
+  const tokensPaidOut = data.readUInt8(offset) === 1; offset += 1;
+  const rewarded = data.readUInt8(offset) === 1; offset += 1;
+  const rewardAmount = data.readBigUInt64LE(offset); offset += 8;
+  const seasonRecorded = data.readUInt8(offset) === 1; offset += 1;
+  const event = new PublicKey(data.subarray(offset, offset + 32)); offset += 32;
+  const eventRecorded = data.readUInt8(offset) === 1; offset += 1;
+  const maxRacers = data.readUInt8(offset); offset += 1;
+  const racerCount = data.readUInt8(offset); offset += 1;
+  const racers: PublicKey[] = [];
+  for (let i = 0; i < DERBY_MAX_RACERS; i++) {
+    racers.push(new PublicKey(data.subarray(offset, offset + 32))); offset += 32;
+  }
+  const finishedCount = data.readUInt8(offset); offset += 1;
+  const ghostRaceId = data.readBigUInt64LE(offset); offset += 8;
+  const ghostFinishTick = data.readUInt32LE(offset); offset += 4;
+  const ghostMargin = data.readInt32LE(offset); offset += 4;
+  const seedSource = data.readUInt8(offset); offset += 1;
+  const seedCommitment = data.subarray(offset, offset + 32); offset += 32;
+  const playerSeed = data.subarray(offset, offset + 32); offset += 32;
+  const serverSecret = data.subarray(offset, offset + 32); offset += 32;
+  const seedContributed = data.readUInt8(offset) === 1; offset += 1;
+  const seedFulfilled = data.readUInt8(offset) === 1; offset += 1;
+  const layout = decodeTrackLayout(data, offset);
+  offset += 3 + DERBY_MAX_OBSTACLES + DERBY_MAX_GOLD + DERBY_MAX_BOOSTS;
+  const obstacleHits = readU16Array(data, offset, DERBY_MAX_LAPS); offset += 2 * DERBY_MAX_LAPS;
+  const obstacleHitTicks = readU32Array(data, offset, DERBY_MAX_OBSTACLES); offset += 4 * DERBY_MAX_OBSTACLES;
+  const boostedTicks = data.readUInt32LE(offset); offset += 4 + 4;
+  const lastSplitTick = data.readUInt32LE(offset); offset += 4;
+  const lapTicks = readU32Array(data, offset, DERBY_MAX_LAPS); offset += 4 * DERBY_MAX_LAPS;
+  const checkpointTicks = readU32Array(data, offset, DERBY_SPLIT_COUNT); offset += 4 * DERBY_SPLIT_COUNT;
+  const trackOrigin = data.readUInt8(offset);
   return {
     raceId, gameServer, player, vrfSeed, status, currentTick, currentLap,
     checkpointsPassed, collisions, goldCollected, boostsCollected,
     boostEndTick, finishTick, goldBitmask, boostBitmask, createdAt, settledAt,
+    rentPayer, closeFee, strict, arbiter, disputeWindowSecs, disputeStatus,
+    tokenMint, tokenStake, playerTokensDeposited, serverTokensDeposited, tokensPaidOut,
+    rewarded, rewardAmount, seasonRecorded, event, eventRecorded, maxRacers, racerCount,
+    racers: racers.slice(0, racerCount), finishedCount, ghostRaceId, ghostFinishTick,
+    ghostMargin, seedSource, seedCommitment, playerSeed, serverSecret, seedContributed, seedFulfilled,
+    layout, obstacleHits, obstacleHitTicks, boostedTicks, lastSplitTick, lapTicks, checkpointTicks, trackOrigin,
+  };
+}
+
+// Rebuilds close_derby's single-racer result hash (base58, as logged).
+function derbyResultHash(race: DerbyRaceStateData): string {
+  const u16 = (v: number) => { const b = Buffer.alloc(2); b.writeUInt16LE(v); return b; };
+  const u32 = (v: number) => { const b = Buffer.alloc(4); b.writeUInt32LE(v); return b; };
+  const u64 = (v: bigint) => { const b = Buffer.alloc(8); b.writeBigUInt64LE(v); return b; };
+  const data = Buffer.concat([
+    u64(race.raceId), race.player.toBuffer(), race.vrfSeed, u32(race.finishTick), Buffer.from([race.currentLap]),
+    u16(race.collisions), Buffer.from([race.goldCollected, race.boostsCollected]), u16(race.goldBitmask),
+    Buffer.from([race.boostBitmask, race.rewarded ? 1 : 0]), u64(race.rewardAmount), u32(race.boostedTicks),
+    ...race.lapTicks.map(u32), ...race.checkpointTicks.map(u32),
+  ]);
+  return anchor.utils.bytes.bs58.encode(createHash("sha256").update(data).digest());
+}
+
+interface DerbyPlayerStateData {
+  raceId: bigint;
+  player: PublicKey;
+  currentLap: number;
+  collisions: number;
+  goldCollected: number;
+  finishTick: number;
+  goldBitmask: number;
+  placement: number;
+  rentPayer: PublicKey;
+}
+
+function decodeDerbyPlayerState(data: Buffer): DerbyPlayerStateData {
+  let offset = 8; // discriminator
+  const raceId = data.readBigUInt64LE(offset); offset += 8;
+  const player = new PublicKey(data.subarray(offset, offset + 32)); offset += 32;
+  // progress: lap, checkpoints, collisions, gold, boosts, boost_end_tick, finish_tick, gold / boost bitmasks,
+  // obstacle hits, boosted ticks, splits
+  const currentLap = data.readUInt8(offset); offset += 2;
+  const collisions = data.readUInt16LE(offset); offset += 2;
+  const goldCollected = data.readUInt8(offset); offset += 2 + 4;
+  const finishTick = data.readUInt32LE(offset); offset += 4;
+  const goldBitmask = data.readUInt16LE(offset);
+  offset += 2 + 1 + 2 * DERBY_MAX_LAPS + 4 * DERBY_MAX_OBSTACLES + 4 + 4 + 4 + 4 * DERBY_MAX_LAPS + 4 * DERBY_SPLIT_COUNT;
+  const placement = data.readUInt8(offset); offset += 1;
+  const rentPayer = new PublicKey(data.subarray(offset, offset + 32));
+  return { raceId, player, currentLap, collisions, goldCollected, finishTick, goldBitmask, placement, rentPayer };
+}
+
+interface TrackRecordData {
+  player: PublicKey;
+  raceId: bigint;
+  finishTick: number;
+  collisions: number;
+  goldCollected: number;
+}
+
+function decodeTrackRecords(data: Buffer, offset: number, count: number): TrackRecordData[] {
+  const records: TrackRecordData[] = [];
+  for (let i = 0; i < count; i++) {
+    const player = new PublicKey(data.subarray(offset, offset + 32)); offset += 32;
+    const raceId = data.readBigUInt64LE(offset); offset += 8;
+    const finishTick = data.readUInt32LE(offset); offset += 4;
+    const collisions = data.readUInt16LE(offset); offset += 2;
+    const goldCollected = data.readUInt8(offset); offset += 1;
+    if (!player.equals(PublicKey.default)) records.push({ player, raceId, finishTick, collisions, goldCollected });
+  }
+  return records;
+}
+
+function decodeTrackLeaderboard(data: Buffer): TrackRecordData[] {
+  return decodeTrackRecords(data, 8 + 32 + 32, TRACK_LEADERBOARD_SIZE); // discriminator + vrf_seed + rent_payer
+}
+
+interface TrackGhostData {
+  player: PublicKey;
+  raceId: bigint;
+  finishTick: number;
+  lapTicks: number[];
+  checkpointTicks: number[];
+}
+
+function decodeTrackGhost(data: Buffer): TrackGhostData {
+  let offset = 8 + 32 + 32; // discriminator + vrf_seed + rent_payer
+  const player = new PublicKey(data.subarray(offset, offset + 32)); offset += 32;
+  const raceId = data.readBigUInt64LE(offset); offset += 8;
+  const finishTick = data.readUInt32LE(offset); offset += 4 + 8; // + recorded_at
+  const lapTicks = readU32Array(data, offset, DERBY_MAX_LAPS); offset += 4 * DERBY_MAX_LAPS;
+  const checkpointTicks = readU32Array(data, offset, DERBY_SPLIT_COUNT);
+  return { player, raceId, finishTick, lapTicks, checkpointTicks };
+}
+
+interface DerbyEventData {
+  eventId: bigint;
+  gameServer: PublicKey;
+  vrfSeed: Uint8Array;
+  startTs: bigint;
+  endTs: bigint;
+  entryFee: bigint;
+  prizeSplitBps: number[];
+  entrantCount: number;
+  leaderboard: TrackRecordData[];
+  finalized: boolean;
+  disputeWindowSecs: number;
+}
+
+function decodeDerbyEvent(data: Buffer): DerbyEventData {
+  let offset = 8; // discriminator
+  const eventId = data.readBigUInt64LE(offset); offset += 8;
+  const gameServer = new PublicKey(data.subarray(offset, offset + 32)); offset += 32;
+  const vrfSeed = data.subarray(offset, offset + 32); offset += 32;
+  const startTs = data.readBigInt64LE(offset); offset += 8;
+  const endTs = data.readBigInt64LE(offset); offset += 8;
+  const entryFee = data.readBigUInt64LE(offset); offset += 8;
+  const prizeSplitBps = [0, 1, 2].map((i) => data.readUInt16LE(offset + 2 * i)); offset += 6;
+  const entrantCount = data.readUInt32LE(offset); offset += 4;
+  const leaderboard = decodeTrackRecords(data, offset, DERBY_EVENT_LEADERBOARD_SIZE);
+  offset += DERBY_EVENT_LEADERBOARD_SIZE * 47;
+  const finalized = data.readUInt8(offset) === 1; offset += 1;
+  const disputeWindowSecs = data.readUInt32LE(offset);
+  return {
+    eventId, gameServer, vrfSeed, startTs, endTs, entryFee, prizeSplitBps, entrantCount,
+    leaderboard, finalized, disputeWindowSecs,
   };
 }
 
@@ -84,16 +381,350 @@ function disc(name: string): Buffer {
   return createHash("sha256").update(`global:${name}`).digest().subarray(0, 8);
 }
 
+interface SettlementParams {
+  closeFee: number;
+  strict: boolean;
+  arbiter: PublicKey;
+  disputeWindowSecs: number;
+}
+
+function encodeSettlementParams(params: Partial<SettlementParams> = {}): Buffer {
+  const buf = Buffer.alloc(8 + 1 + 32 + 4);
+  let off = 0;
+  buf.writeBigUInt64LE(BigInt(params.closeFee ?? 0), off); off += 8;
+  buf.writeUInt8(params.strict ? 1 : 0, off); off += 1;
+  (params.arbiter ?? PublicKey.default).toBuffer().copy(buf, off); off += 32;
+  buf.writeUInt32LE(params.disputeWindowSecs ?? 0, off);
+  return buf;
+}
+
+interface TokenWager {
+  mint: PublicKey;
+  amount: number;
+}
+
+// Option<TokenWager>: 0 = None, 1 + mint + u64 amount = Some
+function encodeTokenWager(wager?: TokenWager): Buffer {
+  if (!wager) return Buffer.from([0]);
+  const buf = Buffer.alloc(1 + 32 + 8);
+  buf.writeUInt8(1, 0);
+  wager.mint.toBuffer().copy(buf, 1);
+  buf.writeBigUInt64LE(BigInt(wager.amount), 33);
+  return buf;
+}
+
+// SeedRequest: Fixed { vrf_seed } = 0, CommitReveal { commitment } = 1
+type SeedRequest = Buffer | { commitment: Buffer };
+
+function encodeSeedRequest(seed: SeedRequest): Buffer {
+  return Buffer.isBuffer(seed)
+    ? Buffer.concat([Buffer.from([0]), seed])
+    : Buffer.concat([Buffer.from([1]), seed.commitment]);
+}
+
+const CONFIG_SEED = Buffer.from("arena_config");
+const BPF_LOADER_UPGRADEABLE_ID = new PublicKey("BPFLoaderUpgradeab1e11111111111111111111111");
+const TEST_FEE_BPS = 250;
+
+function findConfigPda(): [PublicKey, number] {
+  return PublicKey.findProgramAddressSync([CONFIG_SEED], PROGRAM_ID);
+}
+
+function buildInitializeConfigIx(
+  admin: PublicKey,
+  treasury: PublicKey,
+  feeBps: number,
+): anchor.web3.TransactionInstruction {
+  const [configPda] = findConfigPda();
+  const [programData] = PublicKey.findProgramAddressSync([PROGRAM_ID.toBuffer()], BPF_LOADER_UPGRADEABLE_ID);
+  const data = Buffer.alloc(8 + 32 + 2);
+  disc("initialize_config").copy(data, 0);
+  treasury.toBuffer().copy(data, 8);
+  data.writeUInt16LE(feeBps, 40);
+
+  return new anchor.web3.TransactionInstruction({
+    programId: PROGRAM_ID,
+    keys: [
+      { pubkey: configPda, isSigner: false, isWritable: true },
+      { pubkey: admin, isSigner: true, isWritable: true },
+      { pubkey: PROGRAM_ID, isSigner: false, isWritable: false },
+      { pubkey: programData, isSigner: false, isWritable: false },
+      { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+    ],
+    data,
+  });
+}
+
+function buildUpdateConfigIx(
+  admin: PublicKey,
+  newAdmin: PublicKey,
+  treasury: PublicKey,
+  feeBps: number,
+): anchor.web3.TransactionInstruction {
+  const [configPda] = findConfigPda();
+  const data = Buffer.alloc(8 + 32 + 32 + 2);
+  disc("update_config").copy(data, 0);
+  newAdmin.toBuffer().copy(data, 8);
+  treasury.toBuffer().copy(data, 40);
+  data.writeUInt16LE(feeBps, 72);
+
+  return new anchor.web3.TransactionInstruction({
+    programId: PROGRAM_ID,
+    keys: [
+      { pubkey: configPda, isSigner: false, isWritable: true },
+      { pubkey: admin, isSigner: true, isWritable: false },
+    ],
+    data,
+  });
+}
+
+// Creates the config on first run, otherwise points it at this run's treasury.
+async function setupConfig(
+  provider: anchor.AnchorProvider,
+  admin: Keypair,
+  treasury: PublicKey,
+): Promise<void> {
+  const [configPda] = findConfigPda();
+  const existing = await provider.connection.getAccountInfo(configPda);
+  const ix = existing
+    ? buildUpdateConfigIx(admin.publicKey, admin.publicKey, treasury, TEST_FEE_BPS)
+    : buildInitializeConfigIx(admin.publicKey, treasury, TEST_FEE_BPS);
+  await provider.sendAndConfirm(new anchor.web3.Transaction().add(ix), [admin]);
+}
+
+const REWARD_VAULT_SEED = Buffer.from("reward_vault");
+
+function findRewardVaultPda(mint: PublicKey): [PublicKey, number] {
+  return PublicKey.findProgramAddressSync([REWARD_VAULT_SEED, mint.toBuffer()], PROGRAM_ID);
+}
+
+function buildSetDerbyRewardsIx(
+  admin: PublicKey,
+  mint: PublicKey,
+  rewardPerGold: number,
+): anchor.web3.TransactionInstruction {
+  const [configPda] = findConfigPda();
+  const [vaultPda] = findRewardVaultPda(mint);
+  const data = Buffer.alloc(8 + 8);
+  disc("set_derby_rewards").copy(data, 0);
+  data.writeBigUInt64LE(BigInt(rewardPerGold), 8);
+
+  return new anchor.web3.TransactionInstruction({
+    programId: PROGRAM_ID,
+    keys: [
+      { pubkey: configPda, isSigner: false, isWritable: true },
+      { pubkey: mint, isSigner: false, isWritable: false },
+      { pubkey: vaultPda, isSigner: false, isWritable: true },
+      { pubkey: admin, isSigner: true, isWritable: true },
+      { pubkey: TOKEN_PROGRAM_ID, isSigner: false, isWritable: false },
+      { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+    ],
+    data,
+  });
+}
+
+function buildRewardDerbyIx(
+  raceId: number,
+  mint: PublicKey,
+  player: PublicKey,
+  payer: PublicKey,
+  useVault: boolean,
+): anchor.web3.TransactionInstruction {
+  const [derbyPda] = findDerbyPda(raceId);
+  const [configPda] = findConfigPda();
+  const [vaultPda] = findRewardVaultPda(mint);
+  const data = Buffer.concat([disc("reward_derby"), raceIdToBytes(raceId)]);
+
+  return new anchor.web3.TransactionInstruction({
+    programId: PROGRAM_ID,
+    keys: [
+      { pubkey: derbyPda, isSigner: false, isWritable: true },
+      { pubkey: configPda, isSigner: false, isWritable: false },
+      { pubkey: mint, isSigner: false, isWritable: true },
+      // reward_vault = None when the config PDA mints the rewards
+      useVault
+        ? { pubkey: vaultPda, isSigner: false, isWritable: true }
+        : { pubkey: PROGRAM_ID, isSigner: false, isWritable: false },
+      { pubkey: getAssociatedTokenAddressSync(mint, player), isSigner: false, isWritable: true },
+      { pubkey: payer, isSigner: true, isWritable: false },
+      { pubkey: TOKEN_PROGRAM_ID, isSigner: false, isWritable: false },
+      { pubkey: ASSOCIATED_TOKEN_PROGRAM_ID, isSigner: false, isWritable: false },
+    ],
+    data,
+  });
+}
+
+const SEASON_SEED = Buffer.from("season");
+const SEASON_STATS_SEED = Buffer.from("season_stats");
+
+function seasonIdToBytes(seasonId: number): Buffer {
+  const buf = Buffer.alloc(4);
+  buf.writeUInt32LE(seasonId);
+  return buf;
+}
+
+function findSeasonPda(seasonId: number): [PublicKey, number] {
+  return PublicKey.findProgramAddressSync([SEASON_SEED, seasonIdToBytes(seasonId)], PROGRAM_ID);
+}
+
+function findSeasonStatsPda(seasonId: number, wallet: PublicKey): [PublicKey, number] {
+  return PublicKey.findProgramAddressSync(
+    [SEASON_STATS_SEED, seasonIdToBytes(seasonId), wallet.toBuffer()],
+    PROGRAM_ID
+  );
+}
+
+// ArenaConfig.current_season sits after admin, treasury, fee_bps, reward_mint, reward_per_gold
+async function currentSeason(provider: anchor.AnchorProvider): Promise<number> {
+  const acct = await provider.connection.getAccountInfo(findConfigPda()[0]);
+  return acct!.data.readUInt32LE(8 + 32 + 32 + 2 + 32 + 8);
+}
+
+// The config's current season, which the close builders pass — kept in step
+// by the before hook and the season test
+let runningSeason = 0;
+
+function seasonAccounts(): { pubkey: PublicKey; isSigner: boolean; isWritable: boolean }[] {
+  return [
+    { pubkey: findConfigPda()[0], isSigner: false, isWritable: false },
+    // season = None before the first season
+    runningSeason > 0
+      ? { pubkey: findSeasonPda(runningSeason)[0], isSigner: false, isWritable: false }
+      : { pubkey: PROGRAM_ID, isSigner: false, isWritable: false },
+  ];
+}
+
+function buildStartSeasonIx(
+  admin: PublicKey,
+  currentSeasonId: number,
+  startTs: number,
+  endTs: number,
+): anchor.web3.TransactionInstruction {
+  const seasonId = currentSeasonId + 1;
+  const data = Buffer.alloc(8 + 4 + 8 + 8);
+  disc("start_season").copy(data, 0);
+  data.writeUInt32LE(seasonId, 8);
+  data.writeBigInt64LE(BigInt(startTs), 12);
+  data.writeBigInt64LE(BigInt(endTs), 20);
+
+  return new anchor.web3.TransactionInstruction({
+    programId: PROGRAM_ID,
+    keys: [
+      { pubkey: findConfigPda()[0], isSigner: false, isWritable: true },
+      // previous_season = None before the first season
+      currentSeasonId > 0
+        ? { pubkey: findSeasonPda(currentSeasonId)[0], isSigner: false, isWritable: true }
+        : { pubkey: PROGRAM_ID, isSigner: false, isWritable: false },
+      { pubkey: findSeasonPda(seasonId)[0], isSigner: false, isWritable: true },
+      { pubkey: admin, isSigner: true, isWritable: true },
+      { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+    ],
+    data,
+  });
+}
+
+function buildRecordDerbySeasonIx(
+  raceId: number,
+  seasonId: number,
+  player: PublicKey,
+  payer: PublicKey,
+): anchor.web3.TransactionInstruction {
+  const data = Buffer.concat([disc("record_derby_season"), raceIdToBytes(raceId)]);
+
+  return new anchor.web3.TransactionInstruction({
+    programId: PROGRAM_ID,
+    keys: [
+      { pubkey: findDerbyPda(raceId)[0], isSigner: false, isWritable: true },
+      { pubkey: findSeasonPda(seasonId)[0], isSigner: false, isWritable: true },
+      { pubkey: findSeasonStatsPda(seasonId, player)[0], isSigner: false, isWritable: true },
+      { pubkey: payer, isSigner: true, isWritable: true },
+      { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+    ],
+    data,
+  });
+}
+
+function buildCloseDerbyIx(
+  raceId: number,
+  vrfSeed: Buffer,
+  player: PublicKey,
+  rentPayer: PublicKey,
+  payer: PublicKey,
+  finished: boolean,
+  remaining: PublicKey[] = [],
+  sharedTrack = true,
+): anchor.web3.TransactionInstruction {
+  const data = Buffer.concat([disc("close_derby"), raceIdToBytes(raceId)]);
+
+  return new anchor.web3.TransactionInstruction({
+    programId: PROGRAM_ID,
+    keys: [
+      { pubkey: findDerbyPda(raceId)[0], isSigner: false, isWritable: true },
+      { pubkey: payer, isSigner: true, isWritable: true },
+      { pubkey: rentPayer, isSigner: false, isWritable: true },
+      // player_profile = None for multiplayer races (those pass their racers)
+      remaining.length === 0
+        ? { pubkey: PublicKey.findProgramAddressSync([PROFILE_SEED, player.toBuffer()], PROGRAM_ID)[0], isSigner: false, isWritable: true }
+        : { pubkey: PROGRAM_ID, isSigner: false, isWritable: false },
+      // track_leaderboard / track_ghost = None unless the race Finished
+      // (neither for commit-reveal races)
+      finished && sharedTrack
+        ? { pubkey: findTrackLeaderboardPda(vrfSeed)[0], isSigner: false, isWritable: true }
+        : { pubkey: PROGRAM_ID, isSigner: false, isWritable: false },
+      finished && sharedTrack
+        ? { pubkey: findTrackGhostPda(vrfSeed)[0], isSigner: false, isWritable: true }
+        : { pubkey: PROGRAM_ID, isSigner: false, isWritable: false },
+      ...seasonAccounts(),
+      { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+      // multiplayer: derby_player PDAs and the racers' wallets
+      ...remaining.map((pubkey) => ({ pubkey, isSigner: false, isWritable: true })),
+    ],
+    data,
+  });
+}
+
+function buildCloseTrackLeaderboardIx(vrfSeed: Buffer, rentPayer: PublicKey): anchor.web3.TransactionInstruction {
+  return new anchor.web3.TransactionInstruction({
+    programId: PROGRAM_ID,
+    keys: [
+      { pubkey: findTrackLeaderboardPda(vrfSeed)[0], isSigner: false, isWritable: true },
+      { pubkey: rentPayer, isSigner: true, isWritable: true },
+    ],
+    data: Buffer.concat([disc("close_track_leaderboard"), vrfSeed]),
+  });
+}
+
+function buildCloseTrackGhostIx(vrfSeed: Buffer, rentPayer: PublicKey): anchor.web3.TransactionInstruction {
+  return new anchor.web3.TransactionInstruction({
+    programId: PROGRAM_ID,
+    keys: [
+      { pubkey: findTrackGhostPda(vrfSeed)[0], isSigner: false, isWritable: true },
+      { pubkey: rentPayer, isSigner: true, isWritable: true },
+    ],
+    data: Buffer.concat([disc("close_track_ghost"), vrfSeed]),
+  });
+}
+
+function protocolFee(pot: number): number {
+  return Math.floor((pot * TEST_FEE_BPS) / 10_000);
+}
+
 function buildCreateDerbyIx(
   raceId: number,
   gameServer: PublicKey,
   player: PublicKey,
-  vrfSeed: Buffer,
+  seed: SeedRequest,
+  params: Partial<SettlementParams> = {},
+  tokenWager?: TokenWager,
+  derbyEvent?: PublicKey,
+  maxRacers = 1,
+  ghost = false,
 ): anchor.web3.TransactionInstruction {
   const [derbyPda] = findDerbyPda(raceId);
   const raceIdBuf = Buffer.alloc(8);
   raceIdBuf.writeBigUInt64LE(BigInt(raceId));
-  const data = Buffer.concat([disc("create_derby"), raceIdBuf, vrfSeed]);
+  const data = Buffer.concat([disc("create_derby"), raceIdBuf, encodeSeedRequest(seed), encodeSettlementParams(params),
+    encodeTokenWager(tokenWager), Buffer.from([maxRacers])]);
 
   return new anchor.web3.TransactionInstruction({
     programId: PROGRAM_ID,
@@ -102,6 +733,184 @@ function buildCreateDerbyIx(
       { pubkey: gameServer, isSigner: false, isWritable: false },
       { pubkey: player, isSigner: true, isWritable: true },
       { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+      // derby_event = None for a standalone race
+      derbyEvent
+        ? { pubkey: derbyEvent, isSigner: false, isWritable: true }
+        : { pubkey: PROGRAM_ID, isSigner: false, isWritable: false },
+      // track_ghost = None outside ghost mode
+      ghost && Buffer.isBuffer(seed)
+        ? { pubkey: findTrackGhostPda(seed)[0], isSigner: false, isWritable: false }
+        : { pubkey: PROGRAM_ID, isSigner: false, isWritable: false },
+    ],
+    data,
+  });
+}
+
+function buildContributeDerbySeedIx(
+  raceId: number,
+  player: PublicKey,
+  playerSeed: Buffer,
+): anchor.web3.TransactionInstruction {
+  const [derbyPda] = findDerbyPda(raceId);
+  const raceIdBuf = Buffer.alloc(8);
+  raceIdBuf.writeBigUInt64LE(BigInt(raceId));
+  const data = Buffer.concat([disc("contribute_derby_seed"), raceIdBuf, playerSeed]);
+
+  return new anchor.web3.TransactionInstruction({
+    programId: PROGRAM_ID,
+    keys: [
+      { pubkey: derbyPda, isSigner: false, isWritable: true },
+      { pubkey: player, isSigner: true, isWritable: false },
+    ],
+    data,
+  });
+}
+
+function buildCreateDerbyEventIx(
+  eventId: number,
+  gameServer: PublicKey,
+  vrfSeed: Buffer,
+  startTs: number,
+  endTs: number,
+  entryFee: number,
+  prizeSplitBps: [number, number, number],
+): anchor.web3.TransactionInstruction {
+  const args = Buffer.alloc(8 + 8 + 8 + 6);
+  args.writeBigInt64LE(BigInt(startTs), 0);
+  args.writeBigInt64LE(BigInt(endTs), 8);
+  args.writeBigUInt64LE(BigInt(entryFee), 16);
+  prizeSplitBps.forEach((bps, i) => args.writeUInt16LE(bps, 24 + 2 * i));
+  const data = Buffer.concat([disc("create_derby_event"), raceIdToBytes(eventId), vrfSeed, args]);
+
+  return new anchor.web3.TransactionInstruction({
+    programId: PROGRAM_ID,
+    keys: [
+      { pubkey: findDerbyEventPda(eventId)[0], isSigner: false, isWritable: true },
+      { pubkey: gameServer, isSigner: true, isWritable: true },
+      { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+    ],
+    data,
+  });
+}
+
+function buildRecordDerbyEventIx(
+  raceId: number,
+  eventId: number,
+  payer: PublicKey,
+): anchor.web3.TransactionInstruction {
+  const data = Buffer.concat([disc("record_derby_event"), raceIdToBytes(raceId)]);
+
+  return new anchor.web3.TransactionInstruction({
+    programId: PROGRAM_ID,
+    keys: [
+      { pubkey: findDerbyPda(raceId)[0], isSigner: false, isWritable: true },
+      { pubkey: findDerbyEventPda(eventId)[0], isSigner: false, isWritable: true },
+      { pubkey: payer, isSigner: true, isWritable: false },
+    ],
+    data,
+  });
+}
+
+function buildFinalizeDerbyEventIx(
+  eventId: number,
+  treasury: PublicKey,
+  gameServer: PublicKey,
+  podium: PublicKey[],
+): anchor.web3.TransactionInstruction {
+  const data = Buffer.concat([disc("finalize_derby_event"), raceIdToBytes(eventId)]);
+
+  return new anchor.web3.TransactionInstruction({
+    programId: PROGRAM_ID,
+    keys: [
+      { pubkey: findDerbyEventPda(eventId)[0], isSigner: false, isWritable: true },
+      { pubkey: findConfigPda()[0], isSigner: false, isWritable: false },
+      { pubkey: treasury, isSigner: false, isWritable: true },
+      { pubkey: gameServer, isSigner: true, isWritable: true },
+      // podium wallets as remaining accounts
+      ...podium.map((pubkey) => ({ pubkey, isSigner: false, isWritable: true })),
+    ],
+    data,
+  });
+}
+
+function buildJoinDerbyIx(
+  raceId: number,
+  gameServer: PublicKey,
+  racer: PublicKey,
+): anchor.web3.TransactionInstruction {
+  const data = Buffer.concat([disc("join_derby"), raceIdToBytes(raceId)]);
+
+  return new anchor.web3.TransactionInstruction({
+    programId: PROGRAM_ID,
+    keys: [
+      { pubkey: findDerbyPda(raceId)[0], isSigner: false, isWritable: true },
+      { pubkey: findDerbyPlayerPda(raceId, racer)[0], isSigner: false, isWritable: true },
+      { pubkey: gameServer, isSigner: true, isWritable: false },
+      { pubkey: racer, isSigner: true, isWritable: true },
+      { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+    ],
+    data,
+  });
+}
+
+function findDerbyTokenVaultPda(raceId: number): [PublicKey, number] {
+  return PublicKey.findProgramAddressSync(
+    [DERBY_TOKEN_VAULT_SEED, raceIdToBytes(raceId)],
+    PROGRAM_ID
+  );
+}
+
+function buildDepositDerbyTokensIx(
+  raceId: number,
+  mint: PublicKey,
+  depositor: PublicKey,
+): anchor.web3.TransactionInstruction {
+  const [derbyPda] = findDerbyPda(raceId);
+  const [vaultPda] = findDerbyTokenVaultPda(raceId);
+  const data = Buffer.concat([disc("deposit_derby_tokens"), raceIdToBytes(raceId)]);
+
+  return new anchor.web3.TransactionInstruction({
+    programId: PROGRAM_ID,
+    keys: [
+      { pubkey: derbyPda, isSigner: false, isWritable: true },
+      { pubkey: mint, isSigner: false, isWritable: false },
+      { pubkey: vaultPda, isSigner: false, isWritable: true },
+      { pubkey: getAssociatedTokenAddressSync(mint, depositor), isSigner: false, isWritable: true },
+      { pubkey: depositor, isSigner: true, isWritable: true },
+      { pubkey: TOKEN_PROGRAM_ID, isSigner: false, isWritable: false },
+      { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+    ],
+    data,
+  });
+}
+
+function buildPayoutDerbyTokensIx(
+  raceId: number,
+  mint: PublicKey,
+  player: PublicKey,
+  gameServer: PublicKey,
+  payer: PublicKey,
+  treasury: PublicKey,
+): anchor.web3.TransactionInstruction {
+  const [derbyPda] = findDerbyPda(raceId);
+  const [vaultPda] = findDerbyTokenVaultPda(raceId);
+  const [configPda] = findConfigPda();
+  const data = Buffer.concat([disc("payout_derby_tokens"), raceIdToBytes(raceId)]);
+
+  return new anchor.web3.TransactionInstruction({
+    programId: PROGRAM_ID,
+    keys: [
+      { pubkey: derbyPda, isSigner: false, isWritable: true },
+      { pubkey: mint, isSigner: false, isWritable: false },
+      { pubkey: vaultPda, isSigner: false, isWritable: true },
+      { pubkey: getAssociatedTokenAddressSync(mint, player), isSigner: false, isWritable: true },
+      { pubkey: getAssociatedTokenAddressSync(mint, gameServer), isSigner: false, isWritable: true },
+      { pubkey: player, isSigner: false, isWritable: true },
+      { pubkey: payer, isSigner: true, isWritable: false },
+      { pubkey: TOKEN_PROGRAM_ID, isSigner: false, isWritable: false },
+      { pubkey: ASSOCIATED_TOKEN_PROGRAM_ID, isSigner: false, isWritable: false },
+      { pubkey: configPda, isSigner: false, isWritable: false },
+      { pubkey: getAssociatedTokenAddressSync(mint, treasury), isSigner: false, isWritable: true },
     ],
     data,
   });
@@ -129,16 +938,16 @@ function buildDerbyServerActionIx(
 }
 
 function encodeDerbyAction(action:
-  | { type: "RecordCollision" }
+  | { type: "RecordCollision"; obstacleIndex: number }
   | { type: "CollectGold"; itemIndex: number }
   | { type: "CollectBoost"; itemIndex: number }
   | { type: "PassCheckpoint"; checkpointId: number }
   | { type: "CompleteLap" }
-  | { type: "FinishRace" }
+  | { type: "FinishRace"; tick: number }
 ): Buffer {
   switch (action.type) {
     case "RecordCollision":
-      return Buffer.from([0]);
+      return Buffer.from([0, action.obstacleIndex]);
     case "CollectGold":
       return Buffer.from([1, action.itemIndex]);
     case "CollectBoost":
@@ -147,8 +956,12 @@ function encodeDerbyAction(action:
       return Buffer.from([3, action.checkpointId]);
     case "CompleteLap":
       return Buffer.from([4]);
-    case "FinishRace":
-      return Buffer.from([5]);
+    case "FinishRace": {
+      const buf = Buffer.alloc(5);
+      buf.writeUInt8(5, 0);
+      buf.writeUInt32LE(action.tick, 1);
+      return buf;
+    }
   }
 }
 
@@ -156,13 +969,19 @@ function buildDerbyServerUpdateIx(
   raceId: number,
   gameServer: PublicKey,
   action: Parameters<typeof encodeDerbyAction>[0],
+  racer?: PublicKey,
 ): anchor.web3.TransactionInstruction {
-  return buildDerbyServerActionIx(
+  const ix = buildDerbyServerActionIx(
     "derby_server_update",
     raceId,
     gameServer,
     encodeDerbyAction(action),
   );
+  // derby_player = None for a single-racer race
+  ix.keys.push(racer
+    ? { pubkey: findDerbyPlayerPda(raceId, racer)[0], isSigner: false, isWritable: true }
+    : { pubkey: PROGRAM_ID, isSigner: false, isWritable: false });
+  return ix;
 }
 
 function buildSubmitDerbyInputIx(
@@ -196,21 +1015,30 @@ function buildSubmitDerbyInputIx(
   });
 }
 
-// Helper: pass all 4 checkpoints then complete lap
+// Helper: pass all 4 checkpoints then complete lap. The driver's input moves
+// the race clock DERBY_MIN_CHECKPOINT_GAP_TICKS past `tick` before each
+// checkpoint; returns the tick the lap was completed at.
 async function passAllCheckpointsAndCompleteLap(
   provider: anchor.AnchorProvider,
   raceId: number,
   gameServer: Keypair,
-): Promise<void> {
+  driver: Keypair,
+  tick: number,
+  racer?: PublicKey,
+): Promise<number> {
   for (let cp = 0; cp < DERBY_CHECKPOINT_COUNT; cp++) {
+    tick += DERBY_MIN_CHECKPOINT_GAP_TICKS;
+    const inputIx = buildSubmitDerbyInputIx(raceId, driver.publicKey, driver.publicKey, tick, 1, 0);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(inputIx), [driver]);
     const ix = buildDerbyServerUpdateIx(raceId, gameServer.publicKey, {
       type: "PassCheckpoint",
       checkpointId: cp,
-    });
+    }, racer);
     await provider.sendAndConfirm(new anchor.web3.Transaction().add(ix), [gameServer]);
   }
-  const lapIx = buildDerbyServerUpdateIx(raceId, gameServer.publicKey, { type: "CompleteLap" });
+  const lapIx = buildDerbyServerUpdateIx(raceId, gameServer.publicKey, { type: "CompleteLap" }, racer);
   await provider.sendAndConfirm(new anchor.web3.Transaction().add(lapIx), [gameServer]);
+  return tick;
 }
 
 // ═══════════════════════════════════════════════════════════════════════════
@@ -224,10 +1052,16 @@ describe("derby-race", () => {
   // Use provider wallet as "game server" for testing
   const gameServer = (provider.wallet as anchor.Wallet).payer;
   const player = Keypair.generate();
+  const treasury = Keypair.generate();
 
   let raceId: number;
   const vrfSeed = Buffer.alloc(32);
   createHash("sha256").update("test-vrf-seed").digest().copy(vrfSeed);
+  // Items the racer can reach before passing any checkpoint
+  const layout = deriveTrackLayout(vrfSeed);
+  const goldIndex = layout.goldSegments.indexOf(0);
+  const boostIndex = layout.boostSegments.indexOf(0);
+  const obstacleIndex = layout.obstacleSegments.indexOf(0);
 
   before(async () => {
     raceId = Math.floor(Math.random() * 1_000_000);
@@ -242,6 +1076,18 @@ describe("derby-race", () => {
       new anchor.web3.Transaction().add(transferIx),
       [gameServer]
     );
+
+    // Fresh treasury for this run — funded so small SOL fees keep it rent-exempt
+    await provider.sendAndConfirm(
+      new anchor.web3.Transaction().add(SystemProgram.transfer({
+        fromPubkey: gameServer.publicKey,
+        toPubkey: treasury.publicKey,
+        lamports: 0.01 * anchor.web3.LAMPORTS_PER_SOL,
+      })),
+      [gameServer]
+    );
+    await setupConfig(provider, gameServer, treasury.publicKey);
+    runningSeason = await currentSeason(provider);
   });
 
   // ── 1. Create derby race ────────────────────────────────────────────────
@@ -261,6 +1107,7 @@ describe("derby-race", () => {
     expect(state.player.toBase58()).to.equal(player.publicKey.toBase58());
     expect(Buffer.from(state.vrfSeed).equals(vrfSeed)).to.be.true;
     expect(state.status).to.equal(0); // Created
+    expect(state.rentPayer.toBase58()).to.equal(player.publicKey.toBase58());
     expect(state.currentTick).to.equal(0);
     expect(state.currentLap).to.equal(0);
     expect(state.checkpointsPassed).to.equal(0);
@@ -331,34 +1178,61 @@ describe("derby-race", () => {
   // ── 6. Server records collision ────────────────────────────────────────
 
   it("server records collision", async () => {
-    const ix = buildDerbyServerUpdateIx(raceId, gameServer.publicKey, { type: "RecordCollision" });
+    const ix = buildDerbyServerUpdateIx(raceId, gameServer.publicKey, { type: "RecordCollision", obstacleIndex });
     await provider.sendAndConfirm(new anchor.web3.Transaction().add(ix), [gameServer]);
 
+    // A relayed duplicate of the same crash is debounced (the compute budget
+    // ix only keeps the transaction signature distinct)
+    const duplicateTx = new anchor.web3.Transaction()
+      .add(anchor.web3.ComputeBudgetProgram.setComputeUnitLimit({ units: 200_000 }))
+      .add(ix);
+    try {
+      await provider.sendAndConfirm(duplicateTx, [gameServer]);
+      expect.fail("Should have failed with CollisionDebounced");
+    } catch (err: any) {
+      // DerbyError::CollisionDebounced = 6067 = 0x17b3
+      expect(err.toString()).to.include("0x17b3");
+    }
+
+    const offLayoutIx = buildDerbyServerUpdateIx(
+      raceId, gameServer.publicKey, { type: "RecordCollision", obstacleIndex: DERBY_MAX_OBSTACLES },
+    );
+    try {
+      await provider.sendAndConfirm(new anchor.web3.Transaction().add(offLayoutIx), [gameServer]);
+      expect.fail("Should have failed with InvalidObstacleIndex");
+    } catch (err: any) {
+      // DerbyError::InvalidObstacleIndex = 6066 = 0x17b2
+      expect(err.toString()).to.include("0x17b2");
+    }
+
     const [derbyPda] = findDerbyPda(raceId);
     const acct = await provider.connection.getAccountInfo(derbyPda);
     const state = decodeDerbyRaceState(acct!.data);
 
     expect(state.collisions).to.equal(1);
+    expect(state.obstacleHits[0]).to.equal(1 << obstacleIndex);
+    expect(state.obstacleHitTicks[obstacleIndex]).to.equal(state.currentTick);
   });
 
-  // ── 7. Server collects gold (index 0) ──────────────────────────────────
+  // ── 7. Server collects gold (first coin in segment 0) ──────────────────
 
-  it("server collects gold (index 0)", async () => {
-    const ix = buildDerbyServerUpdateIx(raceId, gameServer.publicKey, { type: "CollectGold", itemIndex: 0 });
+  it("server collects gold on the current segment", async () => {
+    const ix = buildDerbyServerUpdateIx(raceId, gameServer.publicKey, { type: "CollectGold", itemIndex: goldIndex });
     await provider.sendAndConfirm(new anchor.web3.Transaction().add(ix), [gameServer]);
 
     const [derbyPda] = findDerbyPda(raceId);
     const acct = await provider.connection.getAccountInfo(derbyPda);
     const state = decodeDerbyRaceState(acct!.data);
 
+    expect(state.layout).to.deep.equal(layout); // derived on-chain at start_derby
     expect(state.goldCollected).to.equal(1);
-    expect(state.goldBitmask & 1).to.equal(1); // bit 0 set
+    expect(state.goldBitmask).to.equal(1 << goldIndex);
   });
 
-  // ── 8. Reject double gold collection (index 0) ─────────────────────────
+  // ── 8. Reject double gold collection ───────────────────────────────────
 
-  it("rejects double gold collection (index 0)", async () => {
-    const ix = buildDerbyServerUpdateIx(raceId, gameServer.publicKey, { type: "CollectGold", itemIndex: 0 });
+  it("rejects double gold collection", async () => {
+    const ix = buildDerbyServerUpdateIx(raceId, gameServer.publicKey, { type: "CollectGold", itemIndex: goldIndex });
     try {
       await provider.sendAndConfirm(new anchor.web3.Transaction().add(ix), [gameServer]);
       expect.fail("Should have failed with ItemAlreadyCollected");
@@ -368,9 +1242,9 @@ describe("derby-race", () => {
     }
   });
 
-  // ── 9. Reject invalid gold index (15) ──────────────────────────────────
+  // ── 9. Reject gold off the layout or out of reach ──────────────────────
 
-  it("rejects invalid gold index (15)", async () => {
+  it("rejects gold that is off the layout or on a later segment", async () => {
     const ix = buildDerbyServerUpdateIx(raceId, gameServer.publicKey, { type: "CollectGold", itemIndex: 15 });
     try {
       await provider.sendAndConfirm(new anchor.web3.Transaction().add(ix), [gameServer]);
@@ -379,12 +1253,36 @@ describe("derby-race", () => {
       // DerbyError::InvalidItemIndex = 6004 = 0x1774
       expect(err.toString()).to.include("0x1774");
     }
+
+    // In range, but this seed didn't spawn that many coins
+    const missingIx = buildDerbyServerUpdateIx(
+      raceId, gameServer.publicKey, { type: "CollectGold", itemIndex: layout.goldCount },
+    );
+    try {
+      await provider.sendAndConfirm(new anchor.web3.Transaction().add(missingIx), [gameServer]);
+      expect.fail("Should have failed with InvalidItemIndex");
+    } catch (err: any) {
+      expect(err.toString()).to.include("0x1774");
+    }
+
+    // A coin further down the track can't be picked up yet
+    const aheadIndex = layout.goldSegments.findIndex((seg, i) => i < layout.goldCount && seg > 0);
+    const aheadIx = buildDerbyServerUpdateIx(
+      raceId, gameServer.publicKey, { type: "CollectGold", itemIndex: aheadIndex },
+    );
+    try {
+      await provider.sendAndConfirm(new anchor.web3.Transaction().add(aheadIx), [gameServer]);
+      expect.fail("Should have failed with ItemNotReachable");
+    } catch (err: any) {
+      // DerbyError::ItemNotReachable = 6065 = 0x17b1
+      expect(err.toString()).to.include("0x17b1");
+    }
   });
 
-  // ── 10. Server collects boost (index 0) ────────────────────────────────
+  // ── 10. Server collects boost (first boost in segment 0) ───────────────
 
-  it("server collects boost (index 0)", async () => {
-    const ix = buildDerbyServerUpdateIx(raceId, gameServer.publicKey, { type: "CollectBoost", itemIndex: 0 });
+  it("server collects boost on the current segment", async () => {
+    const ix = buildDerbyServerUpdateIx(raceId, gameServer.publicKey, { type: "CollectBoost", itemIndex: boostIndex });
     await provider.sendAndConfirm(new anchor.web3.Transaction().add(ix), [gameServer]);
 
     const [derbyPda] = findDerbyPda(raceId);
@@ -392,20 +1290,37 @@ describe("derby-race", () => {
     const state = decodeDerbyRaceState(acct!.data);
 
     expect(state.boostsCollected).to.equal(1);
-    expect(state.boostBitmask & 1).to.equal(1); // bit 0 set
+    expect(state.boostBitmask).to.equal(1 << boostIndex);
     expect(state.boostEndTick).to.equal(100 + DERBY_BOOST_DURATION_TICKS); // current_tick(100) + boost duration
   });
 
-  // ── 11. Server passes all 4 checkpoints ────────────────────────────────
+  // ── 11. Server passes all 4 checkpoints, split gaps enforced ───────────
 
   it("server passes all 4 checkpoints", async () => {
-    for (let cp = 0; cp < DERBY_CHECKPOINT_COUNT; cp++) {
-      const ix = buildDerbyServerUpdateIx(raceId, gameServer.publicKey, {
-        type: "PassCheckpoint",
-        checkpointId: cp,
-      });
+    const passCheckpoint = async (checkpointId: number, tick: number) => {
+      const inputIx = buildSubmitDerbyInputIx(raceId, player.publicKey, player.publicKey, tick, 1, 0);
+      await provider.sendAndConfirm(new anchor.web3.Transaction().add(inputIx), [player]);
+      const ix = buildDerbyServerUpdateIx(raceId, gameServer.publicKey, { type: "PassCheckpoint", checkpointId });
       await provider.sendAndConfirm(new anchor.web3.Transaction().add(ix), [gameServer]);
-    }
+    };
+    const expectTooSoon = async (checkpointId: number, tick: number) => {
+      try {
+        await passCheckpoint(checkpointId, tick);
+        expect.fail("Should have failed with CheckpointTooSoon");
+      } catch (err: any) {
+        // DerbyError::CheckpointTooSoon = 6068 = 0x17b4
+        expect(err.toString()).to.include("0x17b4");
+      }
+    };
+
+    // Boost from test 10 runs until tick 200
+    await passCheckpoint(0, 100);
+    await expectTooSoon(1, 100 + DERBY_MIN_BOOSTED_CHECKPOINT_GAP_TICKS - 1);
+    await passCheckpoint(1, 100 + DERBY_MIN_BOOSTED_CHECKPOINT_GAP_TICKS);
+    // Unboosted splits need the longer gap
+    await passCheckpoint(2, 210);
+    await expectTooSoon(3, 210 + DERBY_MIN_CHECKPOINT_GAP_TICKS - 1);
+    await passCheckpoint(3, 210 + DERBY_MIN_CHECKPOINT_GAP_TICKS);
 
     const [derbyPda] = findDerbyPda(raceId);
     const acct = await provider.connection.getAccountInfo(derbyPda);
@@ -413,6 +1328,7 @@ describe("derby-race", () => {
 
     const allCheckpoints = (1 << DERBY_CHECKPOINT_COUNT) - 1; // 0b1111 = 15
     expect(state.checkpointsPassed).to.equal(allCheckpoints);
+    expect(state.boostedTicks).to.equal(DERBY_BOOST_DURATION_TICKS); // ticks 100–200
   });
 
   // ── 12. Server completes lap 1 ─────────────────────────────────────────
@@ -445,9 +1361,10 @@ describe("derby-race", () => {
   // ── 14. Full race — 3 laps and finish ──────────────────────────────────
 
   it("completes full race — 3 laps and finish", async () => {
-    // Lap 1 already done in test 12. Complete laps 2 and 3.
-    await passAllCheckpointsAndCompleteLap(provider, raceId, gameServer);
-    await passAllCheckpointsAndCompleteLap(provider, raceId, gameServer);
+    // Lap 1 already done in test 12 (tick 250). Complete laps 2 and 3.
+    let tick = 210 + DERBY_MIN_CHECKPOINT_GAP_TICKS;
+    tick = await passAllCheckpointsAndCompleteLap(provider, raceId, gameServer, player, tick);
+    await passAllCheckpointsAndCompleteLap(provider, raceId, gameServer, player, tick);
 
     // Verify current_lap = 3 before finishing
     const [derbyPda] = findDerbyPda(raceId);
@@ -456,14 +1373,14 @@ describe("derby-race", () => {
     expect(state.currentLap).to.equal(3);
 
     // Finish the race
-    const finishIx = buildDerbyServerUpdateIx(raceId, gameServer.publicKey, { type: "FinishRace" });
+    const finishIx = buildDerbyServerUpdateIx(raceId, gameServer.publicKey, { type: "FinishRace", tick: 100 });
     await provider.sendAndConfirm(new anchor.web3.Transaction().add(finishIx), [gameServer]);
 
     acct = await provider.connection.getAccountInfo(derbyPda);
     state = decodeDerbyRaceState(acct!.data);
 
     expect(state.status).to.equal(2); // Finished
-    expect(state.finishTick).to.equal(100); // current_tick was set to 100 in test 4
+    expect(state.finishTick).to.equal(100); // tick reported with FinishRace
     expect(state.settledAt).to.not.equal(BigInt(0));
   });
 
@@ -479,7 +1396,7 @@ describe("derby-race", () => {
     await provider.sendAndConfirm(new anchor.web3.Transaction().add(startIx), [gameServer]);
 
     // Try to finish immediately — no laps completed
-    const finishIx = buildDerbyServerUpdateIx(newRaceId, gameServer.publicKey, { type: "FinishRace" });
+    const finishIx = buildDerbyServerUpdateIx(newRaceId, gameServer.publicKey, { type: "FinishRace", tick: 100 });
     try {
       await provider.sendAndConfirm(new anchor.web3.Transaction().add(finishIx), [gameServer]);
       expect.fail("Should have failed with LapsNotComplete");
@@ -498,7 +1415,7 @@ describe("derby-race", () => {
     await provider.sendAndConfirm(new anchor.web3.Transaction().add(createIx), [player]);
 
     // Try RecordCollision on a Created (not Racing) derby
-    const ix = buildDerbyServerUpdateIx(newRaceId, gameServer.publicKey, { type: "RecordCollision" });
+    const ix = buildDerbyServerUpdateIx(newRaceId, gameServer.publicKey, { type: "RecordCollision", obstacleIndex });
     try {
       await provider.sendAndConfirm(new anchor.web3.Transaction().add(ix), [gameServer]);
       expect.fail("Should have failed with RaceNotActive");
@@ -507,4 +1424,715 @@ describe("derby-race", () => {
       expect(err.toString()).to.include("0x1771");
     }
   });
+
+  // ── 17. Token wager: timed-out race pays the pot to the server ─────────
+
+  it("token wager pays the pot to the server when the race times out", async () => {
+    const newRaceId = raceId + 3;
+    const amount = 500_000;
+
+    const mint = await createMint(provider.connection, gameServer, gameServer.publicKey, null, 6);
+    const playerAta = await getOrCreateAssociatedTokenAccount(provider.connection, gameServer, mint, player.publicKey);
+    const serverAta = await getOrCreateAssociatedTokenAccount(provider.connection, gameServer, mint, gameServer.publicKey);
+    const treasuryAta = await getOrCreateAssociatedTokenAccount(provider.connection, gameServer, mint, treasury.publicKey);
+    await mintTo(provider.connection, gameServer, mint, playerAta.address, gameServer, amount);
+    await mintTo(provider.connection, gameServer, mint, serverAta.address, gameServer, amount);
+
+    // Token-wagered races must use a commit-reveal seed
+    const fixedSeedIx = buildCreateDerbyIx(newRaceId, gameServer.publicKey, player.publicKey, vrfSeed, {}, { mint, amount });
+    try {
+      await provider.sendAndConfirm(new anchor.web3.Transaction().add(fixedSeedIx), [player]);
+      expect.fail("Should have failed with VerifiableSeedRequired");
+    } catch (err: any) {
+      // DerbyError::VerifiableSeedRequired = 6058 = 0x17aa
+      expect(err.toString()).to.include("0x17aa");
+    }
+
+    const serverSecret = createHash("sha256").update("wager-server-secret").digest();
+    const commitment = createHash("sha256").update(serverSecret).digest();
+    const createIx = buildCreateDerbyIx(
+      newRaceId, gameServer.publicKey, player.publicKey, { commitment }, {}, { mint, amount },
+    );
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(createIx), [player]);
+    const contributeIx = buildContributeDerbySeedIx(newRaceId, player.publicKey, Buffer.alloc(32, 7));
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(contributeIx), [player]);
+    const revealIx = buildDerbyServerActionIx("reveal_derby_seed", newRaceId, gameServer.publicKey, serverSecret);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(revealIx), [gameServer]);
+
+    // Can't start before the entry and prize are deposited
+    const earlyStartIx = buildDerbyServerActionIx("start_derby", newRaceId, gameServer.publicKey);
+    try {
+      await provider.sendAndConfirm(new anchor.web3.Transaction().add(earlyStartIx), [gameServer]);
+      expect.fail("Should have failed with TokenStakePending");
+    } catch (err: any) {
+      expect(err.toString()).to.include("Simulation failed");
+    }
+
+    const playerDepositIx = buildDepositDerbyTokensIx(newRaceId, mint, player.publicKey);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(playerDepositIx), [player]);
+    const serverDepositIx = buildDepositDerbyTokensIx(newRaceId, mint, gameServer.publicKey);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(serverDepositIx), [gameServer]);
+
+    const startIx = buildDerbyServerActionIx("start_derby", newRaceId, gameServer.publicKey);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(startIx), [gameServer]);
+
+    // Player stops short of the finish when the clock runs out → server takes the pot
+    const timeoutIx = buildSubmitDerbyInputIx(newRaceId, player.publicKey, player.publicKey, DERBY_MAX_TICKS, 0, 0);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(timeoutIx), [player]);
+    const payoutIx = buildPayoutDerbyTokensIx(
+      newRaceId, mint, player.publicKey, gameServer.publicKey, gameServer.publicKey, treasury.publicKey,
+    );
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(payoutIx), [gameServer]);
+
+    const fee = protocolFee(2 * amount);
+    expect((await getAccount(provider.connection, serverAta.address)).amount).to.equal(BigInt(2 * amount - fee));
+    expect((await getAccount(provider.connection, treasuryAta.address)).amount).to.equal(BigInt(fee));
+    expect((await getAccount(provider.connection, playerAta.address)).amount).to.equal(BigInt(0));
+    const [vaultPda] = findDerbyTokenVaultPda(newRaceId);
+    expect(await provider.connection.getAccountInfo(vaultPda)).to.be.null;
+
+    const [derbyPda] = findDerbyPda(newRaceId);
+    expect(decodeDerbyRaceState((await provider.connection.getAccountInfo(derbyPda))!.data).tokensPaidOut).to.be.true;
+  });
+
+  // ── 18. Gold reward minted once for a finished race ────────────────────
+
+  it("rewards a finished race's gold once", async () => {
+    const rewardPerGold = 1_000;
+    const [configPda] = findConfigPda();
+
+    // Config PDA as mint authority → rewards are minted, no vault needed
+    const mint = await createMint(provider.connection, gameServer, configPda, null, 6);
+    const playerAta = await getOrCreateAssociatedTokenAccount(provider.connection, gameServer, mint, player.publicKey);
+
+    const setIx = buildSetDerbyRewardsIx(gameServer.publicKey, mint, rewardPerGold);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(setIx), [gameServer]);
+
+    const [derbyPda] = findDerbyPda(raceId);
+    const before = decodeDerbyRaceState((await provider.connection.getAccountInfo(derbyPda))!.data);
+    expect(before.status).to.equal(2); // Finished
+
+    const rewardIx = buildRewardDerbyIx(raceId, mint, player.publicKey, gameServer.publicKey, false);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(rewardIx), [gameServer]);
+
+    const expected = BigInt(before.goldCollected * rewardPerGold);
+    expect((await getAccount(provider.connection, playerAta.address)).amount).to.equal(expected);
+    const after = decodeDerbyRaceState((await provider.connection.getAccountInfo(derbyPda))!.data);
+    expect(after.rewarded).to.be.true;
+    expect(after.rewardAmount).to.equal(expected);
+
+    const againIx = buildRewardDerbyIx(raceId, mint, player.publicKey, gameServer.publicKey, false);
+    try {
+      await provider.sendAndConfirm(new anchor.web3.Transaction().add(againIx), [gameServer]);
+      expect.fail("Should have failed with AlreadyRewarded");
+    } catch (err: any) {
+      expect(err.toString()).to.include("Simulation failed");
+    }
+  });
+
+  // ── 19. Finished race feeds the season's best-time leaderboard ─────────
+
+  it("records a finished race in the current season", async () => {
+    const previous = await currentSeason(provider);
+    const now = Math.floor(Date.now() / 1000);
+    // The race settled earlier in this run, so the season opens an hour back
+    const startIx = buildStartSeasonIx(gameServer.publicKey, previous, now - 3600, now + 3600);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(startIx), [gameServer]);
+    const seasonId = previous + 1;
+    runningSeason = seasonId;
+
+    const [derbyPda] = findDerbyPda(raceId);
+    let race = decodeDerbyRaceState((await provider.connection.getAccountInfo(derbyPda))!.data);
+    const closeIx = buildCloseDerbyIx(
+      raceId, Buffer.from(race.vrfSeed), player.publicKey, race.rentPayer, gameServer.publicKey, true,
+    );
+    try {
+      await provider.sendAndConfirm(new anchor.web3.Transaction().add(closeIx), [gameServer]);
+      expect.fail("Should have failed with SeasonReportPending");
+    } catch (err: any) {
+      // DerbyError::SeasonReportPending = 6074 = 0x17ba
+      expect(err.toString()).to.include("0x17ba");
+    }
+
+    const recordIx = buildRecordDerbySeasonIx(raceId, seasonId, player.publicKey, gameServer.publicKey);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(recordIx), [gameServer]);
+
+    race = decodeDerbyRaceState((await provider.connection.getAccountInfo(derbyPda))!.data);
+    expect(race.seasonRecorded).to.be.true;
+
+    // SeasonStats: season_id, wallet, arena wins/losses/draws, then derby fields
+    const stats = (await provider.connection.getAccountInfo(findSeasonStatsPda(seasonId, player.publicKey)[0]))!.data;
+    const derbyOffset = 8 + 4 + 32 + 4 + 4 + 4;
+    expect(stats.readUInt32LE(derbyOffset)).to.equal(1);
+    expect(stats.readUInt32LE(derbyOffset + 4)).to.equal(race.finishTick);
+
+    // Season: header, 10 arena entries (36 bytes), then derby entries
+    const season = (await provider.connection.getAccountInfo(findSeasonPda(seasonId)[0]))!.data;
+    const boardOffset = 8 + 4 + 8 + 8 + 1 + 10 * 36;
+    expect(new PublicKey(season.subarray(boardOffset, boardOffset + 32)).toBase58()).to.equal(player.publicKey.toBase58());
+    expect(season.readUInt32LE(boardOffset + 32)).to.equal(race.finishTick);
+
+    // Roll on to a season that hasn't begun, so later races close unrecorded
+    const nextIx = buildStartSeasonIx(gameServer.publicKey, seasonId, now + 365 * 86_400, now + 366 * 86_400);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(nextIx), [gameServer]);
+    runningSeason = seasonId + 1;
+  });
+
+  // ── 20. Closing a Finished race ranks it on the track leaderboard ──────
+
+  it("close_derby records a finished race on its track leaderboard", async () => {
+    const [derbyPda] = findDerbyPda(raceId);
+    const race = decodeDerbyRaceState((await provider.connection.getAccountInfo(derbyPda))!.data);
+
+    const closeIx = buildCloseDerbyIx(
+      raceId, Buffer.from(race.vrfSeed), player.publicKey, race.rentPayer, gameServer.publicKey, true,
+    );
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(closeIx), [gameServer]);
+    expect(await provider.connection.getAccountInfo(derbyPda)).to.be.null;
+
+    const board = decodeTrackLeaderboard(
+      (await provider.connection.getAccountInfo(findTrackLeaderboardPda(Buffer.from(race.vrfSeed))[0]))!.data
+    );
+    const record = board.find((r) => r.raceId === BigInt(raceId));
+    expect(record).to.not.be.undefined;
+    expect(record!.player.toBase58()).to.equal(player.publicKey.toBase58());
+    expect(record!.finishTick).to.equal(race.finishTick);
+    expect(record!.collisions).to.equal(race.collisions);
+    expect(record!.goldCollected).to.equal(race.goldCollected);
+  });
+
+  // ── 21. Time-trial event: entry fee, ranking, close guard ──────────────
+
+  it("ranks an event race and holds its entry fee in the prize pool", async () => {
+    const eventId = raceId;
+    const eventRaceId = raceId + 4;
+    const entryFee = 1_000_000;
+    const eventSeed = createHash("sha256").update(`event-seed-${eventId}`).digest();
+    const [eventPda] = findDerbyEventPda(eventId);
+    const now = Math.floor(Date.now() / 1000);
+
+    const createEventIx = buildCreateDerbyEventIx(
+      eventId, gameServer.publicKey, eventSeed, now - 60, now + 3600, entryFee, [6000, 3000, 1000],
+    );
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(createEventIx), [gameServer]);
+    const poolBefore = (await provider.connection.getAccountInfo(eventPda))!.lamports;
+
+    // A race on a different seed can't enter the event
+    const wrongSeedIx = buildCreateDerbyIx(
+      eventRaceId, gameServer.publicKey, player.publicKey, vrfSeed, {}, undefined, eventPda,
+    );
+    try {
+      await provider.sendAndConfirm(new anchor.web3.Transaction().add(wrongSeedIx), [player]);
+      expect.fail("Should have failed with EventSeedMismatch");
+    } catch (err: any) {
+      // DerbyError::EventSeedMismatch = 6038 = 0x1796
+      expect(err.toString()).to.include("0x1796");
+    }
+
+    const createIx = buildCreateDerbyIx(
+      eventRaceId, gameServer.publicKey, player.publicKey, eventSeed, {}, undefined, eventPda,
+    );
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(createIx), [player]);
+    expect((await provider.connection.getAccountInfo(eventPda))!.lamports).to.equal(poolBefore + entryFee);
+
+    const startIx = buildDerbyServerActionIx("start_derby", eventRaceId, gameServer.publicKey);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(startIx), [gameServer]);
+    let tick = 0;
+    for (let lap = 0; lap < DERBY_MAX_LAPS; lap++) {
+      tick = await passAllCheckpointsAndCompleteLap(provider, eventRaceId, gameServer, player, tick);
+    }
+    const finishIx = buildDerbyServerUpdateIx(eventRaceId, gameServer.publicKey, { type: "FinishRace", tick: 900 });
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(finishIx), [gameServer]);
+
+    // Can't close before the result is ranked in the event
+    const [derbyPda] = findDerbyPda(eventRaceId);
+    const closeIx = buildCloseDerbyIx(
+      eventRaceId, eventSeed, player.publicKey, player.publicKey, gameServer.publicKey, true,
+    );
+    try {
+      await provider.sendAndConfirm(new anchor.web3.Transaction().add(closeIx), [gameServer]);
+      expect.fail("Should have failed with EventReportPending");
+    } catch (err: any) {
+      // DerbyError::EventReportPending = 6043 = 0x179b
+      expect(err.toString()).to.include("0x179b");
+    }
+
+    const recordIx = buildRecordDerbyEventIx(eventRaceId, eventId, gameServer.publicKey);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(recordIx), [gameServer]);
+
+    const race = decodeDerbyRaceState((await provider.connection.getAccountInfo(derbyPda))!.data);
+    expect(race.event.toBase58()).to.equal(eventPda.toBase58());
+    expect(race.eventRecorded).to.be.true;
+    expect(race.seedSource).to.equal(0); // Server
+    expect(race.trackOrigin).to.equal(1); // Event
+    const event = decodeDerbyEvent((await provider.connection.getAccountInfo(eventPda))!.data);
+    expect(event.entrantCount).to.equal(1);
+    expect(event.finalized).to.be.false;
+    expect(event.leaderboard.length).to.equal(1);
+    expect(event.leaderboard[0].player.toBase58()).to.equal(player.publicKey.toBase58());
+    expect(event.leaderboard[0].finishTick).to.equal(900);
+    expect(event.disputeWindowSecs).to.equal(0);
+
+    // A disputable entry pushes finalization back by its window
+    const disputableIx = buildCreateDerbyIx(
+      raceId + 9, gameServer.publicKey, player.publicKey, eventSeed,
+      { arbiter: gameServer.publicKey, disputeWindowSecs: 600 }, undefined, eventPda,
+    );
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(disputableIx), [player]);
+    expect(decodeDerbyEvent((await provider.connection.getAccountInfo(eventPda))!.data).disputeWindowSecs).to.equal(600);
+
+    // Results only become final once the window and its dispute window close
+    const finalizeIx = buildFinalizeDerbyEventIx(eventId, treasury.publicKey, gameServer.publicKey, [player.publicKey]);
+    try {
+      await provider.sendAndConfirm(new anchor.web3.Transaction().add(finalizeIx), [gameServer]);
+      expect.fail("Should have failed with EventWindowOpen");
+    } catch (err: any) {
+      // DerbyError::EventWindowOpen = 6042 = 0x179a
+      expect(err.toString()).to.include("0x179a");
+    }
+
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(closeIx), [gameServer]);
+    expect(await provider.connection.getAccountInfo(derbyPda)).to.be.null;
+  });
+
+  // ── 22. Multiplayer race: per-racer progress and placements ────────────
+
+  it("runs a multiplayer race and hands out placements in finish order", async () => {
+    const mpRaceId = raceId + 5;
+    const rival = Keypair.generate();
+    await provider.sendAndConfirm(
+      new anchor.web3.Transaction().add(SystemProgram.transfer({
+        fromPubkey: gameServer.publicKey,
+        toPubkey: rival.publicKey,
+        lamports: 0.1 * anchor.web3.LAMPORTS_PER_SOL,
+      })),
+      [gameServer]
+    );
+
+    const createIx = buildCreateDerbyIx(
+      mpRaceId, gameServer.publicKey, player.publicKey, vrfSeed, {}, undefined, undefined, 2,
+    );
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(createIx), [player]);
+
+    // Can't start with a single racer
+    const joinIx = buildJoinDerbyIx(mpRaceId, gameServer.publicKey, player.publicKey);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(joinIx), [gameServer, player]);
+    const startIx = buildDerbyServerActionIx("start_derby", mpRaceId, gameServer.publicKey);
+    try {
+      await provider.sendAndConfirm(new anchor.web3.Transaction().add(startIx), [gameServer]);
+      expect.fail("Should have failed with NotEnoughRacers");
+    } catch (err: any) {
+      // DerbyError::NotEnoughRacers = 6050 = 0x17a2
+      expect(err.toString()).to.include("0x17a2");
+    }
+
+    const rivalJoinIx = buildJoinDerbyIx(mpRaceId, gameServer.publicKey, rival.publicKey);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(rivalJoinIx), [gameServer, rival]);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(startIx), [gameServer]);
+
+    // Updates must name a racer
+    const anonIx = buildDerbyServerUpdateIx(
+      mpRaceId, gameServer.publicKey, { type: "RecordCollision", obstacleIndex },
+    );
+    try {
+      await provider.sendAndConfirm(new anchor.web3.Transaction().add(anonIx), [gameServer]);
+      expect.fail("Should have failed with MissingDerbyPlayer");
+    } catch (err: any) {
+      // DerbyError::MissingDerbyPlayer = 6051 = 0x17a3
+      expect(err.toString()).to.include("0x17a3");
+    }
+
+    const goldIx = buildDerbyServerUpdateIx(
+      mpRaceId, gameServer.publicKey, { type: "CollectGold", itemIndex: goldIndex }, rival.publicKey,
+    );
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(goldIx), [gameServer]);
+    // The same coin is still available to the other racer
+    const playerGoldIx = buildDerbyServerUpdateIx(
+      mpRaceId, gameServer.publicKey, { type: "CollectGold", itemIndex: goldIndex }, player.publicKey,
+    );
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(playerGoldIx), [gameServer]);
+
+    // Rival finishes first, then the player
+    // Any joined racer's input moves the shared race clock
+    let clock = 0;
+    for (const [racer, tick] of [[rival.publicKey, 800], [player.publicKey, 850]] as [PublicKey, number][]) {
+      for (let lap = 0; lap < DERBY_MAX_LAPS; lap++) {
+        clock = await passAllCheckpointsAndCompleteLap(provider, mpRaceId, gameServer, player, clock, racer);
+      }
+      const finishIx = buildDerbyServerUpdateIx(mpRaceId, gameServer.publicKey, { type: "FinishRace", tick }, racer);
+      await provider.sendAndConfirm(new anchor.web3.Transaction().add(finishIx), [gameServer]);
+    }
+
+    const [derbyPda] = findDerbyPda(mpRaceId);
+    const race = decodeDerbyRaceState((await provider.connection.getAccountInfo(derbyPda))!.data);
+    expect(race.status).to.equal(2); // Finished
+    expect(race.maxRacers).to.equal(2);
+    expect(race.racerCount).to.equal(2);
+    expect(race.finishedCount).to.equal(2);
+    expect(race.finishTick).to.equal(800);
+    expect(race.currentLap).to.equal(0); // progress lives on the racers
+
+    const [rivalPda] = findDerbyPlayerPda(mpRaceId, rival.publicKey);
+    const [playerPda] = findDerbyPlayerPda(mpRaceId, player.publicKey);
+    const rivalState = decodeDerbyPlayerState((await provider.connection.getAccountInfo(rivalPda))!.data);
+    const playerState = decodeDerbyPlayerState((await provider.connection.getAccountInfo(playerPda))!.data);
+    expect(rivalState.placement).to.equal(1);
+    expect(rivalState.finishTick).to.equal(800);
+    expect(rivalState.currentLap).to.equal(DERBY_MAX_LAPS);
+    expect(rivalState.goldBitmask).to.equal(1 << goldIndex);
+    expect(playerState.placement).to.equal(2);
+    expect(playerState.finishTick).to.equal(850);
+
+    // Closing takes both derby_player PDAs and refunds the rival's rent to the rival
+    const closeIx = buildCloseDerbyIx(
+      mpRaceId, vrfSeed, player.publicKey, player.publicKey, gameServer.publicKey, true,
+      [playerPda, rivalPda, rival.publicKey],
+    );
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(closeIx), [gameServer]);
+    expect(await provider.connection.getAccountInfo(derbyPda)).to.be.null;
+    expect(await provider.connection.getAccountInfo(rivalPda)).to.be.null;
+    expect(await provider.connection.getAccountInfo(playerPda)).to.be.null;
+  });
+
+  // ── 23. Ghost mode: race the track's best run and replace it ───────────
+
+  it("reports the margin against the ghost and replaces it when beaten", async () => {
+    const ghostRaceId = raceId + 6;
+    const [ghostPda] = findTrackGhostPda(vrfSeed);
+    const ghostBefore = decodeTrackGhost((await provider.connection.getAccountInfo(ghostPda))!.data);
+    expect(ghostBefore.player.equals(PublicKey.default)).to.be.false; // set by test 20
+
+    const createIx = buildCreateDerbyIx(
+      ghostRaceId, gameServer.publicKey, player.publicKey, vrfSeed, {}, undefined, undefined, 1, true,
+    );
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(createIx), [player]);
+    const [derbyPda] = findDerbyPda(ghostRaceId);
+    let race = decodeDerbyRaceState((await provider.connection.getAccountInfo(derbyPda))!.data);
+    expect(race.ghostRaceId).to.equal(ghostBefore.raceId);
+    expect(race.ghostFinishTick).to.equal(ghostBefore.finishTick);
+    expect(race.seedSource).to.equal(0); // Server
+    expect(race.trackOrigin).to.equal(2); // Ghost
+
+    const startIx = buildDerbyServerActionIx("start_derby", ghostRaceId, gameServer.publicKey);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(startIx), [gameServer]);
+    let tick = 0;
+    for (let lap = 0; lap < DERBY_MAX_LAPS; lap++) {
+      tick = await passAllCheckpointsAndCompleteLap(provider, ghostRaceId, gameServer, player, tick);
+    }
+    const finishTick = ghostBefore.finishTick - 10;
+    const finishIx = buildDerbyServerUpdateIx(ghostRaceId, gameServer.publicKey, { type: "FinishRace", tick: finishTick });
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(finishIx), [gameServer]);
+
+    race = decodeDerbyRaceState((await provider.connection.getAccountInfo(derbyPda))!.data);
+    expect(race.ghostMargin).to.equal(10);
+    const lapLength = DERBY_CHECKPOINT_COUNT * DERBY_MIN_CHECKPOINT_GAP_TICKS;
+    const lapTicks = [lapLength, 2 * lapLength, 3 * lapLength];
+    expect(race.lapTicks).to.deep.equal(lapTicks);
+    expect(race.checkpointTicks.slice(0, DERBY_CHECKPOINT_COUNT)).to.deep.equal([40, 80, 120, 160]);
+
+    const closeIx = buildCloseDerbyIx(
+      ghostRaceId, vrfSeed, player.publicKey, player.publicKey, gameServer.publicKey, true,
+    );
+    const closeSig = await provider.sendAndConfirm(
+      new anchor.web3.Transaction().add(closeIx), [gameServer], { commitment: "confirmed" },
+    );
+
+    // The logged result hash covers every lap and checkpoint split
+    const closeTx = await provider.connection.getTransaction(closeSig, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    expect(closeTx!.meta!.logMessages!.join("\n")).to.include(
+      `Derby ${ghostRaceId} result: hash=${derbyResultHash(race)}`,
+    );
+
+    const ghostAfter = decodeTrackGhost((await provider.connection.getAccountInfo(ghostPda))!.data);
+    expect(ghostAfter.raceId).to.equal(BigInt(ghostRaceId));
+    expect(ghostAfter.finishTick).to.equal(finishTick);
+    expect(ghostAfter.lapTicks).to.deep.equal(lapTicks);
+    expect(ghostAfter.checkpointTicks).to.deep.equal(race.checkpointTicks);
+  });
+
+  // ── 24. Commit-reveal seed: start blocked until the reveal ─────────────
+
+  it("derives the seed from a commit-reveal and records its provenance", async () => {
+    const seedRaceId = raceId + 7;
+    const serverSecret = createHash("sha256").update("server-secret").digest();
+    const commitment = createHash("sha256").update(serverSecret).digest();
+    const playerSeed = createHash("sha256").update("player-seed").digest();
+    const [derbyPda] = findDerbyPda(seedRaceId);
+
+    const createIx = buildCreateDerbyIx(seedRaceId, gameServer.publicKey, player.publicKey, { commitment });
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(createIx), [player]);
+    let race = decodeDerbyRaceState((await provider.connection.getAccountInfo(derbyPda))!.data);
+    expect(race.seedSource).to.equal(1); // CommitReveal
+    expect(race.trackOrigin).to.equal(0); // Own
+    expect(race.seedFulfilled).to.be.false;
+    expect(race.seedCommitment.equals(commitment)).to.be.true;
+
+    const startIx = buildDerbyServerActionIx("start_derby", seedRaceId, gameServer.publicKey);
+    try {
+      await provider.sendAndConfirm(new anchor.web3.Transaction().add(startIx), [gameServer]);
+      expect.fail("Should have failed with SeedNotFulfilled");
+    } catch (err: any) {
+      // DerbyError::SeedNotFulfilled = 6064 = 0x17b0
+      expect(err.toString()).to.include("0x17b0");
+    }
+
+    // The server can't reveal before the player has contributed
+    const revealIx = buildDerbyServerActionIx("reveal_derby_seed", seedRaceId, gameServer.publicKey, serverSecret);
+    try {
+      await provider.sendAndConfirm(new anchor.web3.Transaction().add(revealIx), [gameServer]);
+      expect.fail("Should have failed with SeedNotContributed");
+    } catch (err: any) {
+      // DerbyError::SeedNotContributed = 6061 = 0x17ad
+      expect(err.toString()).to.include("0x17ad");
+    }
+
+    const contributeIx = buildContributeDerbySeedIx(seedRaceId, player.publicKey, playerSeed);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(contributeIx), [player]);
+
+    const wrongRevealIx = buildDerbyServerActionIx(
+      "reveal_derby_seed", seedRaceId, gameServer.publicKey, Buffer.alloc(32, 1),
+    );
+    try {
+      await provider.sendAndConfirm(new anchor.web3.Transaction().add(wrongRevealIx), [gameServer]);
+      expect.fail("Should have failed with SeedCommitmentMismatch");
+    } catch (err: any) {
+      // DerbyError::SeedCommitmentMismatch = 6062 = 0x17ae
+      expect(err.toString()).to.include("0x17ae");
+    }
+
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(revealIx), [gameServer]);
+    race = decodeDerbyRaceState((await provider.connection.getAccountInfo(derbyPda))!.data);
+    const raceIdBuf = Buffer.alloc(8);
+    raceIdBuf.writeBigUInt64LE(BigInt(seedRaceId));
+    const expectedSeed = createHash("sha256").update(Buffer.concat([serverSecret, playerSeed, raceIdBuf])).digest();
+    expect(race.vrfSeed.equals(expectedSeed)).to.be.true;
+    expect(race.playerSeed.equals(playerSeed)).to.be.true;
+    expect(race.serverSecret.equals(serverSecret)).to.be.true;
+    expect(race.seedFulfilled).to.be.true;
+
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(startIx), [gameServer]);
+    race = decodeDerbyRaceState((await provider.connection.getAccountInfo(derbyPda))!.data);
+    expect(race.status).to.equal(1); // Racing
+  });
+  // ── 25. Strict race that can't finish is cancelled, then settles ───────
+
+  it("cancels a strict race stuck in Racing so it can be closed", async () => {
+    const stuckRaceId = raceId + 8;
+    const [derbyPda] = findDerbyPda(stuckRaceId);
+    const createIx = buildCreateDerbyIx(stuckRaceId, gameServer.publicKey, player.publicKey, vrfSeed, { strict: true });
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(createIx), [player]);
+    const startIx = buildDerbyServerActionIx("start_derby", stuckRaceId, gameServer.publicKey);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(startIx), [gameServer]);
+
+    const closeIx = buildCloseDerbyIx(
+      stuckRaceId, vrfSeed, player.publicKey, player.publicKey, gameServer.publicKey, false,
+    );
+    try {
+      await provider.sendAndConfirm(new anchor.web3.Transaction().add(closeIx), [gameServer]);
+      expect.fail("Should have failed with RaceNotFinished");
+    } catch (err: any) {
+      // DerbyError::RaceNotFinished = 6002 = 0x1772
+      expect(err.toString()).to.include("0x1772");
+    }
+
+    // Anyone could cancel it too, but only once the race has expired
+    const outsider = Keypair.generate();
+    const expireIx = buildDerbyServerActionIx("expire_derby", stuckRaceId, outsider.publicKey);
+    try {
+      await provider.sendAndConfirm(new anchor.web3.Transaction().add(expireIx), [outsider]);
+      expect.fail("Should have failed with RaceNotExpired");
+    } catch (err: any) {
+      // DerbyError::RaceNotExpired = 6073 = 0x17b9
+      expect(err.toString()).to.include("0x17b9");
+    }
+
+    const cancelIx = buildDerbyServerActionIx("cancel_derby", stuckRaceId, gameServer.publicKey);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(cancelIx), [gameServer]);
+    expect(decodeDerbyRaceState((await provider.connection.getAccountInfo(derbyPda))!.data).status).to.equal(3); // Cancelled
+
+    // Cancelling twice is rejected
+    try {
+      await provider.sendAndConfirm(
+        new anchor.web3.Transaction().add(buildDerbyServerActionIx("cancel_derby", stuckRaceId, gameServer.publicKey)),
+        [gameServer]
+      );
+      expect.fail("Should have failed with InvalidDerbyState");
+    } catch (err: any) {
+      // DerbyError::InvalidDerbyState = 6000 = 0x1770
+      expect(err.toString()).to.include("0x1770");
+    }
+
+    // A cancelled run never ranks, so it can't create the track accounts
+    const withBoardIx = buildCloseDerbyIx(
+      stuckRaceId, vrfSeed, player.publicKey, player.publicKey, gameServer.publicKey, true,
+    );
+    try {
+      await provider.sendAndConfirm(new anchor.web3.Transaction().add(withBoardIx), [gameServer]);
+      expect.fail("Should have failed with UnexpectedTrackAccount");
+    } catch (err: any) {
+      // DerbyError::UnexpectedTrackAccount = 6072 = 0x17b8
+      expect(err.toString()).to.include("0x17b8");
+    }
+
+    // ...nor the ghost on its own
+    const withGhostIx = buildCloseDerbyIx(
+      stuckRaceId, vrfSeed, player.publicKey, player.publicKey, gameServer.publicKey, true,
+    );
+    withGhostIx.keys[4] = { pubkey: PROGRAM_ID, isSigner: false, isWritable: false };
+    try {
+      await provider.sendAndConfirm(new anchor.web3.Transaction().add(withGhostIx), [gameServer]);
+      expect.fail("Should have failed with UnexpectedTrackAccount");
+    } catch (err: any) {
+      expect(err.toString()).to.include("0x17b8");
+    }
+
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(closeIx), [gameServer]);
+    expect(await provider.connection.getAccountInfo(derbyPda)).to.be.null;
+  });
+
+  // ── 26. Commit-reveal races skip the track leaderboard and ghost ──────
+
+  it("closes a commit-reveal race without track accounts", async () => {
+    const seedRaceId = raceId + 7; // left Racing by test 24
+    const [derbyPda] = findDerbyPda(seedRaceId);
+    let tick = DERBY_MIN_CHECKPOINT_GAP_TICKS;
+    for (let lap = 0; lap < 3; lap++) {
+      tick = await passAllCheckpointsAndCompleteLap(provider, seedRaceId, gameServer, player, tick);
+    }
+    const finishIx = buildDerbyServerUpdateIx(seedRaceId, gameServer.publicKey, { type: "FinishRace", tick });
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(finishIx), [gameServer]);
+    const race = decodeDerbyRaceState((await provider.connection.getAccountInfo(derbyPda))!.data);
+    expect(race.status).to.equal(2); // Finished
+
+    const withBoardIx = buildCloseDerbyIx(
+      seedRaceId, Buffer.from(race.vrfSeed), player.publicKey, race.rentPayer, gameServer.publicKey, true,
+    );
+    try {
+      await provider.sendAndConfirm(new anchor.web3.Transaction().add(withBoardIx), [gameServer]);
+      expect.fail("Should have failed with NoSharedTrack");
+    } catch (err: any) {
+      // DerbyError::NoSharedTrack = 6069 = 0x17b5
+      expect(err.toString()).to.include("0x17b5");
+    }
+
+    const closeIx = buildCloseDerbyIx(
+      seedRaceId, Buffer.from(race.vrfSeed), player.publicKey, race.rentPayer, gameServer.publicKey, true, [], false,
+    );
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(closeIx), [gameServer]);
+    expect(await provider.connection.getAccountInfo(derbyPda)).to.be.null;
+    expect(await provider.connection.getAccountInfo(findTrackLeaderboardPda(Buffer.from(race.vrfSeed))[0])).to.be.null;
+    expect(await provider.connection.getAccountInfo(findTrackGhostPda(Buffer.from(race.vrfSeed))[0])).to.be.null;
+  });
+
+  // ── 27. Only the board's rent payer can close a track leaderboard ──────
+
+  it("closes a track leaderboard back to its rent payer", async () => {
+    const [boardPda] = findTrackLeaderboardPda(vrfSeed);
+    expect(await provider.connection.getAccountInfo(boardPda)).to.not.be.null;
+
+    try {
+      await provider.sendAndConfirm(
+        new anchor.web3.Transaction().add(buildCloseTrackLeaderboardIx(vrfSeed, player.publicKey)),
+        [player]
+      );
+      expect.fail("Should have failed with InvalidRentPayer");
+    } catch (err: any) {
+      // DerbyError::InvalidRentPayer = 6010 = 0x177a
+      expect(err.toString()).to.include("0x177a");
+    }
+
+    await provider.sendAndConfirm(
+      new anchor.web3.Transaction().add(buildCloseTrackLeaderboardIx(vrfSeed, gameServer.publicKey)),
+      [gameServer]
+    );
+    expect(await provider.connection.getAccountInfo(boardPda)).to.be.null;
+  });
+
+  // ── 28. Only the ghost's rent payer can close a track ghost ────────────
+
+  it("closes a track ghost back to its rent payer", async () => {
+    const [ghostPda] = findTrackGhostPda(vrfSeed);
+    expect(await provider.connection.getAccountInfo(ghostPda)).to.not.be.null;
+
+    try {
+      await provider.sendAndConfirm(new anchor.web3.Transaction().add(buildCloseTrackGhostIx(vrfSeed, player.publicKey)), [player]);
+      expect.fail("Should have failed with InvalidRentPayer");
+    } catch (err: any) {
+      // DerbyError::InvalidRentPayer = 6010 = 0x177a
+      expect(err.toString()).to.include("0x177a");
+    }
+
+    await provider.sendAndConfirm(
+      new anchor.web3.Transaction().add(buildCloseTrackGhostIx(vrfSeed, gameServer.publicKey)),
+      [gameServer]
+    );
+    expect(await provider.connection.getAccountInfo(ghostPda)).to.be.null;
+  });
+
+  // ── 29. Multiplayer race settled with a racer who did not finish ───────
+
+  it("finishes a multiplayer race with a DNF once someone has finished", async () => {
+    const dnfRaceId = raceId + 10;
+    const rival = Keypair.generate();
+    await provider.sendAndConfirm(
+      new anchor.web3.Transaction().add(SystemProgram.transfer({
+        fromPubkey: gameServer.publicKey,
+        toPubkey: rival.publicKey,
+        lamports: 0.1 * anchor.web3.LAMPORTS_PER_SOL,
+      })),
+      [gameServer]
+    );
+    const createIx = buildCreateDerbyIx(
+      dnfRaceId, gameServer.publicKey, player.publicKey, vrfSeed, {}, undefined, undefined, 2,
+    );
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(createIx), [player]);
+    for (const racer of [player, rival]) {
+      const joinIx = buildJoinDerbyIx(dnfRaceId, gameServer.publicKey, racer.publicKey);
+      await provider.sendAndConfirm(new anchor.web3.Transaction().add(joinIx), [gameServer, racer]);
+    }
+    const startIx = buildDerbyServerActionIx("start_derby", dnfRaceId, gameServer.publicKey);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(startIx), [gameServer]);
+
+    // Nobody has finished yet — the race has to be cancelled instead
+    const dnfIx = buildDerbyServerActionIx("finish_derby_dnf", dnfRaceId, gameServer.publicKey);
+    try {
+      await provider.sendAndConfirm(new anchor.web3.Transaction().add(dnfIx), [gameServer]);
+      expect.fail("Should have failed with NoFinishers");
+    } catch (err: any) {
+      // DerbyError::NoFinishers = 6070 = 0x17b6
+      expect(err.toString()).to.include("0x17b6");
+    }
+
+    let clock = 0;
+    for (let lap = 0; lap < DERBY_MAX_LAPS; lap++) {
+      clock = await passAllCheckpointsAndCompleteLap(provider, dnfRaceId, gameServer, player, clock, rival.publicKey);
+    }
+    const finishIx = buildDerbyServerUpdateIx(
+      dnfRaceId, gameServer.publicKey, { type: "FinishRace", tick: 800 }, rival.publicKey,
+    );
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(finishIx), [gameServer]);
+
+    const [derbyPda] = findDerbyPda(dnfRaceId);
+    expect(decodeDerbyRaceState((await provider.connection.getAccountInfo(derbyPda))!.data).status).to.equal(1); // Racing
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(dnfIx), [gameServer]);
+    const race = decodeDerbyRaceState((await provider.connection.getAccountInfo(derbyPda))!.data);
+    expect(race.status).to.equal(2); // Finished
+    expect(race.finishedCount).to.equal(1);
+    expect(race.finishTick).to.equal(800);
+
+    const [rivalPda] = findDerbyPlayerPda(dnfRaceId, rival.publicKey);
+    const [playerPda] = findDerbyPlayerPda(dnfRaceId, player.publicKey);
+    expect(decodeDerbyPlayerState((await provider.connection.getAccountInfo(rivalPda))!.data).placement).to.equal(1);
+    expect(decodeDerbyPlayerState((await provider.connection.getAccountInfo(playerPda))!.data).placement).to.equal(0); // DNF
+
+    const closeIx = buildCloseDerbyIx(
+      dnfRaceId, vrfSeed, player.publicKey, player.publicKey, gameServer.publicKey, true,
+      [playerPda, rivalPda, rival.publicKey],
+    );
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(closeIx), [gameServer]);
+    expect(await provider.connection.getAccountInfo(derbyPda)).to.be.null;
+  });
 });